@@ -0,0 +1,97 @@
+// Push/pop throughput and cross-thread latency of RingBuf, compared with
+// heapless::spsc and rtrb. Run with `cargo bench`
+#![cfg(not(loom))]
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use spsc_ringbuf_core::ringbuf::RingBuf;
+
+// Items moved through the ring per iteration
+const BATCH: u64 = 32;
+
+// Push then pop BATCH items through a ring of capacity N on one thread
+fn push_pop<T: Copy + 'static, const N: usize>(val: T) -> impl FnMut() {
+    let ring: &'static RingBuf<T, N> = Box::leak(Box::new(RingBuf::new()));
+    let (mut producer, mut consumer) = ring.split().unwrap();
+    move || {
+        for _ in 0..BATCH {
+            let _ = producer.try_push(black_box(val));
+            black_box(consumer.pop_value());
+        }
+    }
+}
+
+fn single_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_pop");
+    group.throughput(Throughput::Elements(BATCH));
+
+    // Power of two versus non power of two capacity
+    let mut f = push_pop::<u32, 64>(1);
+    group.bench_function(BenchmarkId::new("ringbuf_u32", 64), |b| b.iter(&mut f));
+    let mut f = push_pop::<u32, 63>(1);
+    group.bench_function(BenchmarkId::new("ringbuf_u32", 63), |b| b.iter(&mut f));
+
+    // Small versus large items
+    let mut f = push_pop::<[u8; 256], 64>([1; 256]);
+    group.bench_function(BenchmarkId::new("ringbuf_256B", 64), |b| b.iter(&mut f));
+
+    let mut queue: heapless::spsc::Queue<u32, 64> = heapless::spsc::Queue::new();
+    let (mut producer, mut consumer) = queue.split();
+    group.bench_function(BenchmarkId::new("heapless_u32", 64), |b| {
+        b.iter(|| {
+            for _ in 0..BATCH {
+                let _ = producer.enqueue(black_box(1));
+                black_box(consumer.dequeue());
+            }
+        })
+    });
+
+    let (mut producer, mut consumer) = rtrb::RingBuffer::<u32>::new(64);
+    group.bench_function(BenchmarkId::new("rtrb_u32", 64), |b| {
+        b.iter(|| {
+            for _ in 0..BATCH {
+                let _ = producer.push(black_box(1));
+                black_box(consumer.pop().ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+// Round trip of one item to an echo thread and back, iters times
+fn round_trip(iters: u64) -> Duration {
+    let ping: &'static RingBuf<u64, 8> = Box::leak(Box::new(RingBuf::new()));
+    let pong: &'static RingBuf<u64, 8> = Box::leak(Box::new(RingBuf::new()));
+    let (mut ping_tx, mut ping_rx) = ping.split().unwrap();
+    let (mut pong_tx, mut pong_rx) = pong.split().unwrap();
+
+    let echo = std::thread::spawn(move || {
+        for _ in 0..iters {
+            let val = loop {
+                match ping_rx.pop_value() {
+                    Some(val) => break val,
+                    None => std::hint::spin_loop(),
+                }
+            };
+            while pong_tx.try_push(val).is_err() {}
+        }
+    });
+    let start = Instant::now();
+    for i in 0..iters {
+        while ping_tx.try_push(i).is_err() {}
+        while pong_rx.pop_value().is_none() {
+            std::hint::spin_loop();
+        }
+    }
+    let elapsed = start.elapsed();
+    echo.join().unwrap();
+    elapsed
+}
+
+fn cross_thread(c: &mut Criterion) {
+    c.bench_function("round_trip_latency", |b| b.iter_custom(round_trip));
+}
+
+criterion_group!(benches, single_thread, cross_thread);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Derive macros re-exported by spsc-ringbuf-core with the `derive` feature.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, GenericArgument, PathArguments, Type};
+
+/// Implement `HasPoolIdx<N>` for a struct with a field of type
+/// `PoolIndex<N>`. When several fields are pool indices, the one carrying
+/// the message payload is marked with `#[pool_idx]`. The struct must also
+/// implement `Default`, messages are staged from it.
+#[proc_macro_derive(HasPoolIdx, attributes(pool_idx))]
+pub fn derive_has_pool_idx(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new_spanned(input, "HasPoolIdx can only be derived for structs")),
+    };
+    let (index, field) = find_field(input, fields)?;
+    let depth = pool_depth(&field.ty)
+        .ok_or_else(|| Error::new_spanned(&field.ty, "#[pool_idx] field must be of type PoolIndex<N>"))?;
+    let member = match &field.ident {
+        Some(ident) => quote!(#ident),
+        None => {
+            let index = syn::Index::from(index);
+            quote!(#index)
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::spsc_ringbuf_core::shared_pool::HasPoolIdx<{ #depth }> for #name #ty_generics #where_clause {
+            #[inline]
+            fn get_pool_idx(&self) -> ::spsc_ringbuf_core::shared_pool::PoolIndex<{ #depth }> {
+                self.#member
+            }
+            #[inline]
+            fn set_pool_idx(&mut self, pindex: ::spsc_ringbuf_core::shared_pool::PoolIndex<{ #depth }>) {
+                self.#member = pindex
+            }
+        }
+    })
+}
+
+// The field marked #[pool_idx], or else the only PoolIndex field
+fn find_field<'a>(input: &DeriveInput, fields: &'a Fields) -> syn::Result<(usize, &'a Field)> {
+    let mut marked = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.attrs.iter().any(|a| a.path().is_ident("pool_idx")));
+    if let Some(found) = marked.next() {
+        return match marked.next() {
+            None => Ok(found),
+            Some((_, f)) => Err(Error::new_spanned(f, "only one field can be marked #[pool_idx]")),
+        };
+    }
+
+    let mut candidates = fields.iter().enumerate().filter(|(_, f)| pool_depth(&f.ty).is_some());
+    match (candidates.next(), candidates.next()) {
+        (Some(found), None) => Ok(found),
+        (Some(_), Some(_)) => Err(Error::new_spanned(input, "several PoolIndex fields, mark one with #[pool_idx]")),
+        (None, _) => Err(Error::new_spanned(input, "no PoolIndex<N> field to derive HasPoolIdx from")),
+    }
+}
+
+// N of a PoolIndex<N> type, with or without a path
+fn pool_depth(ty: &Type) -> Option<&GenericArgument> {
+    let Type::Path(path) = ty else { return None };
+    let last = path.path.segments.last()?;
+    if last.ident != "PoolIndex" {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => args.args.first(),
+        _ => None,
+    }
+}
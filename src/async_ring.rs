@@ -0,0 +1,189 @@
+//! futures `Stream` and `Sink` adapters on top of RingBuf, behind the
+//! `async` feature.
+//!
+//! The consumer is woken after each push and the producer after each pop,
+//! through one `AtomicWaker` per side. Dropping or closing the producer ends
+//! the stream once the remaining items are consumed.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use atomic_waker::AtomicWaker;
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::ringbuf::{Consumer, Producer, RingBuf, SplitError};
+use crate::ringbuf_ref::ErrCode;
+
+pub struct AsyncRingBuf<T, const N: usize> {
+    ringbuf: RingBuf<T, N>,
+    // Woken after a push
+    consumer_waker: AtomicWaker,
+    // Woken after a pop
+    producer_waker: AtomicWaker,
+    // Set once the producer is closed or dropped
+    closed: AtomicBool,
+}
+
+/// Sink side, once in life time
+pub struct AsyncProducer<'a, T, const N: usize> {
+    inner: Producer<'a, T, N>,
+    shared: &'a AsyncRingBuf<T, N>,
+}
+
+/// Stream side, once in life time
+pub struct AsyncConsumer<'a, T, const N: usize> {
+    inner: Consumer<'a, T, N>,
+    shared: &'a AsyncRingBuf<T, N>,
+}
+
+/// AsyncProducer and AsyncConsumer pair returned by AsyncRingBuf::split
+pub type AsyncRingBufHandles<'a, T, const N: usize> = (AsyncProducer<'a, T, N>, AsyncConsumer<'a, T, N>);
+
+impl<T, const N: usize> Default for AsyncRingBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> AsyncRingBuf<T, N> {
+
+    pub const fn new() -> Self {
+        AsyncRingBuf {
+            ringbuf: RingBuf::new(),
+            consumer_waker: AtomicWaker::new(),
+            producer_waker: AtomicWaker::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn split(&self) -> Result<AsyncRingBufHandles<'_, T, N>, SplitError> {
+        let (inner_prod, inner_cons) = self.ringbuf.split()?;
+        Ok((
+            AsyncProducer { inner: inner_prod, shared: self },
+            AsyncConsumer { inner: inner_cons, shared: self },
+        ))
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ringbuf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ringbuf.is_empty()
+    }
+}
+
+impl<'a, T, const N: usize> AsyncProducer<'a, T, N> {
+
+    fn mark_closed(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.consumer_waker.wake();
+    }
+}
+
+impl<'a, T, const N: usize> Drop for AsyncProducer<'a, T, N> {
+    fn drop(&mut self) {
+        self.mark_closed();
+    }
+}
+
+impl<'a, T, const N: usize> Sink<T> for AsyncProducer<'a, T, N> {
+    type Error = ErrCode;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), ErrCode>> {
+        let this = self.get_mut();
+        if this.inner.alloc_uninit().is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        this.shared.producer_waker.register(cx.waker());
+        // A pop in between would have missed the registration
+        if this.inner.alloc_uninit().is_some() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), ErrCode> {
+        let this = self.get_mut();
+        match this.inner.alloc_uninit() {
+            Some(loc) => {
+                loc.write(item);
+            }
+            None => return Err(ErrCode::BufFull),
+        }
+        this.inner.commit()?;
+        this.shared.consumer_waker.wake();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), ErrCode>> {
+        // Items are visible to the consumer once sent
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), ErrCode>> {
+        self.mark_closed();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T, const N: usize> AsyncConsumer<'a, T, N> {
+
+    fn poll_pop(&mut self) -> Poll<Option<T>> {
+        // Check closed first, items pushed before closing are still taken
+        let closed = self.shared.closed.load(Ordering::Acquire);
+        match self.inner.pop_value() {
+            Some(val) => {
+                self.shared.producer_waker.wake();
+                Poll::Ready(Some(val))
+            }
+            None if closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T, const N: usize> Stream for AsyncConsumer<'a, T, N> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        if let Poll::Ready(item) = this.poll_pop() {
+            return Poll::Ready(item);
+        }
+        this.shared.consumer_waker.register(cx.waker());
+        // A push in between would have missed the registration
+        this.poll_pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::{SinkExt, StreamExt};
+
+    static RINGBUF: AsyncRingBuf<u32, 4> = AsyncRingBuf::new();
+
+    #[test]
+    fn stream_and_sink() {
+        let (mut producer, consumer) = RINGBUF.split().unwrap();
+
+        let p_handle = std::thread::spawn(move || {
+            block_on(async {
+                for i in 0..100 {
+                    producer.send(i).await.unwrap();
+                }
+                producer.close().await.unwrap();
+            })
+        });
+
+        // Sees every item in order, then the end of the stream
+        let received: Vec<u32> = block_on(consumer.collect());
+        assert!(received == (0..100).collect::<Vec<u32>>());
+        p_handle.join().unwrap();
+    }
+}
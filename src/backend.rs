@@ -0,0 +1,237 @@
+//! Selection of the ring buffer algorithm used by `RingBuf`.
+//!
+//! `IndexPair` (the default) is the compact read/write index implementation
+//! of `RingBufRef`, best suited for MCU RAM. `SlotSeq` is the per-slot
+//! sequence stamp implementation of `SeqRingBufRef`, which keeps the producer
+//! and consumer off each other's index for SMP throughput.
+
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+use crate::ringbuf_ref::{ErrCode, IndexWidth, RingBufRef};
+use crate::seq_ring::SeqRingBufRef;
+
+/// Operations common to all the inner ring buffer implementations
+pub trait RingOps<T> {
+    /// Initial (empty) value for static instantiation
+    const INIT: Self;
+
+    fn is_empty(&self) -> bool;
+    fn is_full(&self) -> bool;
+    fn len(&self) -> u32;
+    fn capacity(&self) -> usize;
+    fn free_len(&self) -> u32;
+    /// Raw (read, write) index or position pair, for diagnostics
+    fn indices(&self) -> (u32, u32);
+
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn writer_front(&self) -> Option<&mut T>;
+    fn commit(&self) -> Result<(), ErrCode>;
+    fn push(&self, val: T) -> Result<(), ErrCode>;
+    fn try_push(&self, val: T) -> Result<(), T>;
+    fn push_slice(&self, src: &[T]) -> usize
+    where
+        T: Copy;
+
+    fn reader_front(&self) -> Option<&T>;
+    fn peek_at(&self, n: u32) -> Option<&T>;
+    #[allow(clippy::mut_from_ref)]
+    fn reader_front_mut(&self) -> Option<&mut T>;
+    fn pop(&self) -> Result<(), ErrCode>;
+    fn pop_value(&self) -> Option<T>;
+    fn pop_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy;
+    fn copy_to_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy;
+    fn process_n<F: FnMut(&mut T)>(&self, max: u32, f: F) -> u32;
+    fn clear(&self) -> u32;
+    fn skip(&self, n: u32) -> u32;
+
+    /// Number of slots the producer can fill without checking again
+    fn writable(&self) -> u32;
+    /// Number of items the consumer can read without checking again
+    fn readable(&self) -> u32;
+    /// # Safety
+    /// Only the producer may call it, with a non-zero writable count
+    /// accounted for this slot
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn writer_front_unchecked(&self) -> &mut T;
+    /// # Safety
+    /// Same as `writer_front_unchecked`
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn writer_slot_unchecked(&self) -> &mut MaybeUninit<T>;
+    /// # Safety
+    /// Same as `writer_front_unchecked`
+    unsafe fn commit_unchecked(&self);
+    /// # Safety
+    /// Only the consumer may call it, with a non-zero readable count
+    /// accounted for this item
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn reader_front_unchecked(&self) -> &mut T;
+    /// # Safety
+    /// Same as `reader_front_unchecked`
+    unsafe fn pop_unchecked(&self);
+}
+
+/// Marker type selecting the inner ring buffer of capacity N
+pub trait Backend {
+    type Ring<T, const N: usize>: RingOps<T>;
+    /// Capacities other than a power of two fail to compile
+    const POWER_OF_TWO: bool = false;
+}
+
+/// Read/write index pair backend, `RingBufRef` with indices of type I.
+/// With P2 only power of two capacities are accepted, see
+/// `ringbuf::RingBufP2`
+pub struct IndexPair<I: IndexWidth = u32, const P2: bool = false>(PhantomData<I>);
+
+/// Slot sequence stamp backend, `SeqRingBufRef`
+pub struct SlotSeq;
+
+impl<I: IndexWidth, const P2: bool> Backend for IndexPair<I, P2> {
+    type Ring<T, const N: usize> = RingBufRef<T, N, I>;
+    const POWER_OF_TWO: bool = P2;
+}
+
+impl Backend for SlotSeq {
+    type Ring<T, const N: usize> = SeqRingBufRef<T, N>;
+}
+
+// Both implementations share the same inherent API, forward to it
+macro_rules! impl_ring_ops {
+    ($ring:ident $(, $I:ident)?) => {
+        impl<T, const N: usize $(, $I: IndexWidth)?> RingOps<T> for $ring<T, N $(, $I)?> {
+            #[allow(clippy::declare_interior_mutable_const)]
+            const INIT: Self = $ring::new();
+
+            #[inline(always)]
+            fn is_empty(&self) -> bool {
+                $ring::is_empty(self)
+            }
+            #[inline(always)]
+            fn is_full(&self) -> bool {
+                $ring::is_full(self)
+            }
+            #[inline(always)]
+            fn len(&self) -> u32 {
+                $ring::len(self)
+            }
+            #[inline(always)]
+            fn capacity(&self) -> usize {
+                $ring::capacity(self)
+            }
+            #[inline(always)]
+            fn free_len(&self) -> u32 {
+                $ring::free_len(self)
+            }
+            #[inline(always)]
+            fn indices(&self) -> (u32, u32) {
+                $ring::indices(self)
+            }
+            #[inline(always)]
+            unsafe fn writer_front(&self) -> Option<&mut T> {
+                $ring::writer_front(self)
+            }
+            #[inline(always)]
+            fn commit(&self) -> Result<(), ErrCode> {
+                $ring::commit(self)
+            }
+            #[inline(always)]
+            fn push(&self, val: T) -> Result<(), ErrCode> {
+                $ring::push(self, val)
+            }
+            #[inline(always)]
+            fn try_push(&self, val: T) -> Result<(), T> {
+                $ring::try_push(self, val)
+            }
+            #[inline(always)]
+            fn push_slice(&self, src: &[T]) -> usize
+            where
+                T: Copy,
+            {
+                $ring::push_slice(self, src)
+            }
+            #[inline(always)]
+            fn reader_front(&self) -> Option<&T> {
+                $ring::reader_front(self)
+            }
+            #[inline(always)]
+            fn peek_at(&self, n: u32) -> Option<&T> {
+                $ring::peek_at(self, n)
+            }
+            #[inline(always)]
+            fn reader_front_mut(&self) -> Option<&mut T> {
+                $ring::reader_front_mut(self)
+            }
+            #[inline(always)]
+            fn pop(&self) -> Result<(), ErrCode> {
+                $ring::pop(self)
+            }
+            #[inline(always)]
+            fn pop_value(&self) -> Option<T> {
+                $ring::pop_value(self)
+            }
+            #[inline(always)]
+            fn pop_slice(&self, dst: &mut [T]) -> usize
+            where
+                T: Copy,
+            {
+                $ring::pop_slice(self, dst)
+            }
+            #[inline(always)]
+            fn copy_to_slice(&self, dst: &mut [T]) -> usize
+            where
+                T: Copy,
+            {
+                $ring::copy_to_slice(self, dst)
+            }
+            #[inline(always)]
+            fn process_n<F: FnMut(&mut T)>(&self, max: u32, f: F) -> u32 {
+                $ring::process_n(self, max, f)
+            }
+            #[inline(always)]
+            fn clear(&self) -> u32 {
+                $ring::clear(self)
+            }
+            #[inline(always)]
+            fn skip(&self, n: u32) -> u32 {
+                $ring::skip(self, n)
+            }
+            #[inline(always)]
+            fn writable(&self) -> u32 {
+                $ring::writable(self)
+            }
+            #[inline(always)]
+            fn readable(&self) -> u32 {
+                $ring::readable(self)
+            }
+            #[inline(always)]
+            unsafe fn writer_front_unchecked(&self) -> &mut T {
+                $ring::writer_front_unchecked(self)
+            }
+            #[inline(always)]
+            unsafe fn writer_slot_unchecked(&self) -> &mut MaybeUninit<T> {
+                $ring::writer_slot_unchecked(self)
+            }
+            #[inline(always)]
+            unsafe fn commit_unchecked(&self) {
+                $ring::commit_unchecked(self)
+            }
+            #[inline(always)]
+            unsafe fn reader_front_unchecked(&self) -> &mut T {
+                $ring::reader_front_unchecked(self)
+            }
+            #[inline(always)]
+            unsafe fn pop_unchecked(&self) {
+                $ring::pop_unchecked(self)
+            }
+        }
+    };
+}
+
+impl_ring_ops!(RingBufRef, I);
+impl_ring_ops!(SeqRingBufRef);
@@ -0,0 +1,469 @@
+//! Bip buffer of N bytes for variable length contiguous records.
+//!
+//! The producer reserves a contiguous region of any length up to N with
+//! `grant`, writes it and commits the bytes used. A region that does not
+//! fit before the end of the buffer starts over at the beginning instead,
+//! and the end of the data before the wrap is recorded as a watermark. The
+//! consumer hence always reads committed data as one contiguous slice: a
+//! record committed in one grant is never split. Useful for DMA and packet
+//! logging where the data must stay contiguous.
+//!
+//! A grant can also be turned into a DMA grant that does not borrow the
+//! handle, for the DMA complete ISR to commit a received length with
+//! `complete_write` or release transmitted data with `complete_read`. One
+//! DMA grant per side is in flight at a time. The fences only order the
+//! CPU accesses around the DMA transfer, data caches are up to the user.
+
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, Ordering};
+
+use crate::dma::DmaError;
+
+pub struct BipBuffer<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    // Only written by the producer
+    write: AtomicU32,
+    // End of valid data before the producer wrapped, N if not wrapped
+    last: AtomicU32,
+    // Only written by the consumer
+    read: AtomicU32,
+    // Set while a DMA grant of the side is in flight
+    write_in_flight: AtomicBool,
+    read_in_flight: AtomicBool,
+    has_split: Cell<bool>,
+}
+
+// The producer only writes bytes outside the committed region, the consumer
+// only reads inside it
+unsafe impl<const N: usize> Sync for BipBuffer<N> {}
+
+/// Producer handle, once in life time
+pub struct BipProducer<'a, const N: usize> {
+    inner: &'a BipBuffer<N>,
+}
+
+/// Consumer handle, once in life time
+pub struct BipConsumer<'a, const N: usize> {
+    inner: &'a BipBuffer<N>,
+}
+
+/// Contiguous region reserved for writing, borrowing the producer for 'p.
+/// Dropping it commits nothing
+pub struct BipWriteGrant<'p, 'a, const N: usize> {
+    inner: &'a BipBuffer<N>,
+    start: u32,
+    len: u32,
+    _handle: PhantomData<&'p mut ()>,
+}
+
+/// Contiguous committed data, borrowing the consumer for 'p. Dropping it
+/// releases nothing
+pub struct BipReadGrant<'p, 'a, const N: usize> {
+    inner: &'a BipBuffer<N>,
+    start: u32,
+    len: u32,
+    _handle: PhantomData<&'p mut ()>,
+}
+
+/// Write grant handed to a DMA engine, committed by `complete_write`. Only
+/// bound to the buffer lifetime, `'static` for a static buffer
+pub struct BipDmaWrite<'a> {
+    ptr: *mut u8,
+    start: u32,
+    len: u32,
+    _buf: PhantomData<&'a [u8]>,
+}
+
+/// Read grant handed to a DMA engine, released by `complete_read`
+pub struct BipDmaRead<'a> {
+    ptr: *const u8,
+    start: u32,
+    len: u32,
+    _buf: PhantomData<&'a [u8]>,
+}
+
+// The region is exclusively owned by the grant until completed
+unsafe impl Send for BipDmaWrite<'_> {}
+unsafe impl Send for BipDmaRead<'_> {}
+
+// The region stays at ptr and the buffer outlives the grant
+#[cfg(feature = "embedded-dma")]
+unsafe impl embedded_dma::WriteBuffer for BipDmaWrite<'_> {
+    type Word = u8;
+
+    unsafe fn write_buffer(&mut self) -> (*mut u8, usize) {
+        self.as_raw_parts()
+    }
+}
+
+#[cfg(feature = "embedded-dma")]
+unsafe impl embedded_dma::ReadBuffer for BipDmaRead<'_> {
+    type Word = u8;
+
+    unsafe fn read_buffer(&self) -> (*const u8, usize) {
+        self.as_raw_parts()
+    }
+}
+
+impl BipDmaWrite<'_> {
+    /// Start address and length in bytes of the region
+    pub fn as_raw_parts(&self) -> (*mut u8, usize) {
+        (self.ptr, self.len as usize)
+    }
+}
+
+impl BipDmaRead<'_> {
+    /// Start address and length in bytes of the data
+    pub fn as_raw_parts(&self) -> (*const u8, usize) {
+        (self.ptr, self.len as usize)
+    }
+}
+
+impl<const N: usize> Default for BipBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BipBuffer<N> {
+
+    const OK: () = assert!(N > 0 && N < (1 << 31), "Bip buffer capacity must be > 0 and < 2^31");
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = BipBuffer::<N>::OK;
+        BipBuffer {
+            buf: UnsafeCell::new([0; N]),
+            write: AtomicU32::new(0),
+            last: AtomicU32::new(N as u32),
+            read: AtomicU32::new(0),
+            write_in_flight: AtomicBool::new(false),
+            read_in_flight: AtomicBool::new(false),
+            has_split: Cell::new(false),
+        }
+    }
+
+    /// Return the producer and consumer, once in life time
+    pub fn split(&self) -> Option<(BipProducer<'_, N>, BipConsumer<'_, N>)> {
+        if self.has_split.get() {
+            None
+        } else {
+            self.has_split.set(true);
+            Some((BipProducer { inner: self }, BipConsumer { inner: self }))
+        }
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline(always)]
+    fn region(&self, start: u32, len: u32) -> *mut [u8] {
+        let ptr = unsafe { (self.buf.get() as *mut u8).add(start as usize) };
+        core::ptr::slice_from_raw_parts_mut(ptr, len as usize)
+    }
+
+    // Start of a contiguous free region of len bytes
+    #[inline]
+    fn reserve(&self, len: u32) -> Option<u32> {
+        let write = self.write.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+        if write < read {
+            // Wrapped, free space is up to the consumer. Equal indices would
+            // read as empty, hence strictly below
+            (write + len < read).then_some(write)
+        } else if write + len <= N as u32 {
+            Some(write)
+        } else {
+            // Start over at the beginning
+            (len < read).then_some(0)
+        }
+    }
+
+    // Publish used bytes of the region reserved at start
+    #[inline]
+    fn commit_region(&self, start: u32, used: u32) {
+        let write = self.write.load(Ordering::Relaxed);
+        let new_write = start + used;
+        if new_write < write && write != N as u32 {
+            // Wrapped, data before the wrap ends at write
+            self.last.store(write, Ordering::Relaxed);
+        } else if new_write > self.last.load(Ordering::Relaxed) {
+            // Past the previous lap's watermark
+            self.last.store(N as u32, Ordering::Relaxed);
+        }
+        // Watermark is published along with the data
+        self.write.store(new_write, Ordering::Release);
+    }
+
+    // Start and length of the committed data at the consumer
+    #[inline]
+    fn readable(&self) -> (u32, u32) {
+        let mut read = self.read.load(Ordering::Relaxed);
+        // Write first, the watermark it was published with is seen then
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Relaxed);
+        if read == last && write < read {
+            // Consumed up to the wrap
+            read = 0;
+            self.read.store(0, Ordering::Release);
+        }
+        let end = if write < read { last } else { write };
+        (read, end - read)
+    }
+
+    // Grant must be in flight and of this buffer
+    fn check_dma(&self, in_flight: &AtomicBool, ptr: *const u8, start: u32) -> Result<(), DmaError> {
+        if in_flight.load(Ordering::Acquire) && start < N as u32 && core::ptr::eq(self.region(start, 0) as *const u8, ptr) {
+            Ok(())
+        } else {
+            Err(DmaError::ForeignGrant)
+        }
+    }
+
+    /// Commit len bytes received into grant, e.g. from the DMA complete
+    /// ISR. The grant is handed back on error
+    pub fn complete_write<'a>(&'a self, grant: BipDmaWrite<'a>, len: usize) -> Result<(), (BipDmaWrite<'a>, DmaError)> {
+        if let Err(e) = self.check_dma(&self.write_in_flight, grant.ptr, grant.start) {
+            return Err((grant, e));
+        }
+        if len > grant.len as usize {
+            return Err((grant, DmaError::LenOverflow));
+        }
+        // Transfer is complete before the data is published
+        fence(Ordering::SeqCst);
+        self.commit_region(grant.start, len as u32);
+        self.write_in_flight.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Release len bytes transmitted from grant, e.g. from the DMA complete
+    /// ISR. The grant is handed back on error
+    pub fn complete_read<'a>(&'a self, grant: BipDmaRead<'a>, len: usize) -> Result<(), (BipDmaRead<'a>, DmaError)> {
+        if let Err(e) = self.check_dma(&self.read_in_flight, grant.ptr, grant.start) {
+            return Err((grant, e));
+        }
+        if len > grant.len as usize {
+            return Err((grant, DmaError::LenOverflow));
+        }
+        // Transfer has read the data before the region is reused
+        fence(Ordering::SeqCst);
+        self.read.store(grant.start + len as u32, Ordering::Release);
+        self.read_in_flight.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<'a, const N: usize> BipProducer<'a, N> {
+
+    /// Reserve a contiguous region of len bytes, None if there is no such
+    /// region free or a DMA grant is in flight
+    pub fn grant(&mut self, len: usize) -> Option<BipWriteGrant<'_, 'a, N>> {
+        if len > N || self.inner.write_in_flight.load(Ordering::Acquire) {
+            return None;
+        }
+        let start = self.inner.reserve(len as u32)?;
+        Some(BipWriteGrant { inner: self.inner, start, len: len as u32, _handle: PhantomData })
+    }
+
+    /// Copy record into a single region
+    pub fn push(&mut self, record: &[u8]) -> bool {
+        match self.grant(record.len()) {
+            Some(mut grant) => {
+                grant.copy_from_slice(record);
+                grant.commit(record.len());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a, const N: usize> BipConsumer<'a, N> {
+
+    /// All the committed data that is contiguous, None if there is none
+    /// or a DMA grant is in flight
+    pub fn read(&mut self) -> Option<BipReadGrant<'_, 'a, N>> {
+        if self.inner.read_in_flight.load(Ordering::Acquire) {
+            return None;
+        }
+        let (start, len) = self.inner.readable();
+        if len == 0 {
+            None
+        } else {
+            Some(BipReadGrant { inner: self.inner, start, len, _handle: PhantomData })
+        }
+    }
+}
+
+impl<'a, const N: usize> BipWriteGrant<'_, 'a, N> {
+
+    /// Publish the first used bytes of the region to the consumer
+    pub fn commit(self, used: usize) {
+        self.inner.commit_region(self.start, used.min(self.len as usize) as u32);
+    }
+
+    /// Hand the region to a DMA engine. No further grant is made until
+    /// it is completed
+    pub fn into_dma(self) -> BipDmaWrite<'a> {
+        let inner = self.inner;
+        inner.write_in_flight.store(true, Ordering::Release);
+        // Nothing of the CPU accesses before is reordered past the start
+        fence(Ordering::SeqCst);
+        BipDmaWrite { ptr: inner.region(self.start, self.len) as *mut u8, start: self.start, len: self.len, _buf: PhantomData }
+    }
+}
+
+impl<const N: usize> Deref for BipWriteGrant<'_, '_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { &*self.inner.region(self.start, self.len) }
+    }
+}
+
+impl<const N: usize> DerefMut for BipWriteGrant<'_, '_, N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { &mut *self.inner.region(self.start, self.len) }
+    }
+}
+
+impl<'a, const N: usize> BipReadGrant<'_, 'a, N> {
+
+    /// Hand the first used bytes back to the producer
+    pub fn release(self, used: usize) {
+        let used = used.min(self.len as usize) as u32;
+        self.inner.read.store(self.start + used, Ordering::Release);
+    }
+
+    /// Hand the data to a DMA engine. No further read is made until it is
+    /// completed
+    pub fn into_dma(self) -> BipDmaRead<'a> {
+        let inner = self.inner;
+        inner.read_in_flight.store(true, Ordering::Release);
+        // Data written by the CPU is in memory before the transfer starts
+        fence(Ordering::SeqCst);
+        BipDmaRead { ptr: inner.region(self.start, self.len) as *const u8, start: self.start, len: self.len, _buf: PhantomData }
+    }
+}
+
+impl<const N: usize> Deref for BipReadGrant<'_, '_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { &*self.inner.region(self.start, self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static BIP: BipBuffer<16> = BipBuffer::new();
+
+    #[test]
+    fn records_stay_contiguous() {
+        let (mut producer, mut consumer) = BIP.split().unwrap();
+        assert!(BIP.split().is_none());
+        assert!(consumer.read().is_none());
+        assert!(producer.grant(17).is_none());
+
+        assert!(producer.push(&[1; 6]));
+        assert!(producer.push(&[2; 6]));
+        // Grant of 8 only partly used
+        let mut grant = producer.grant(4).unwrap();
+        grant[..2].copy_from_slice(&[3; 2]);
+        grant.commit(2);
+
+        let data = consumer.read().unwrap();
+        assert!(data.len() == 14);
+        assert!(data[..6] == [1; 6]);
+        data.release(6);
+
+        // 5 bytes do not fit before the end, start over at the beginning
+        assert!(producer.grant(6).is_none());
+        assert!(producer.push(&[4; 5]));
+        // Data before the wrap comes first, up to the watermark
+        let data = consumer.read().unwrap();
+        assert!(*data == [2, 2, 2, 2, 2, 2, 3, 3]);
+        data.release(8);
+        let data = consumer.read().unwrap();
+        assert!(*data == [4; 5]);
+        data.release(5);
+        assert!(consumer.read().is_none());
+
+        // Grant dropped without commit publishes nothing
+        assert!(producer.grant(4).is_some());
+        assert!(consumer.read().is_none());
+        assert!(producer.push(&[5; 11]));
+        assert!(*consumer.read().unwrap() == [5; 11]);
+    }
+
+    #[test]
+    fn dma_grants() {
+        static RX: BipBuffer<16> = BipBuffer::new();
+        let (mut producer, mut consumer) = RX.split().unwrap();
+
+        let grant = producer.grant(8).unwrap().into_dma();
+        assert!(producer.grant(1).is_none());
+        // DMA engine and complete ISR on another thread
+        std::thread::spawn(move || {
+            let (ptr, len) = grant.as_raw_parts();
+            assert!(len == 8);
+            unsafe { core::ptr::copy_nonoverlapping(b"abcde".as_ptr(), ptr, 5) };
+            let grant = RX.complete_write(grant, 9).unwrap_err().0;
+            assert!(RX.complete_write(grant, 5).is_ok());
+        }).join().unwrap();
+
+        let tx = consumer.read().unwrap().into_dma();
+        assert!(consumer.read().is_none());
+        let (ptr, len) = tx.as_raw_parts();
+        assert!(unsafe { core::slice::from_raw_parts(ptr, len) } == b"abcde");
+        // Only part transmitted
+        std::thread::spawn(move || assert!(RX.complete_read(tx, 2).is_ok())).join().unwrap();
+        assert!(*consumer.read().unwrap() == *b"cde");
+
+        // Not in flight anymore
+        let stale = BipDmaWrite { ptr: RX.region(5, 0) as *mut u8, start: 5, len: 1, _buf: PhantomData };
+        assert!(RX.complete_write(stale, 0).unwrap_err().1 == DmaError::ForeignGrant);
+    }
+
+    #[test]
+    fn threads() {
+        static RING: BipBuffer<64> = BipBuffer::new();
+        let (mut producer, mut consumer) = RING.split().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            // Records of length n filled with n
+            for i in 0..2000u32 {
+                let n = (i % 13 + 1) as u8;
+                while !producer.push(&[n; 13][..n as usize]) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut count = 0;
+        while count < 2000 {
+            match consumer.read() {
+                Some(data) => {
+                    // Whole records only, each contiguous
+                    let mut off = 0;
+                    while off < data.len() {
+                        let n = data[off] as usize;
+                        assert!(n == (count % 13 + 1) as usize);
+                        assert!(data[off..off + n].iter().all(|v| *v as usize == n));
+                        off += n;
+                        count += 1;
+                    }
+                    assert!(off == data.len());
+                    data.release(off);
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        handle.join().unwrap();
+    }
+}
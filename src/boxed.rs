@@ -0,0 +1,255 @@
+//! Heap allocated ring buffer with a capacity chosen at runtime.
+//!
+//! For host side tools and tests wanting big or configurable buffers.
+//! Same index scheme as RingBufRef with the capacity as a field: indices
+//! wrap at twice the capacity so that all slots are usable. The handles
+//! offer the core of the `ringbuf` Producer and Consumer API. Unlike
+//! RingBuf, popped and remaining items are dropped.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use crate::ringbuf::SplitError;
+use crate::ringbuf_ref::ErrCode;
+use crate::sync::{AtomicU32, Ordering};
+
+pub struct RingBufBoxed<T> {
+    // Only written by the consumer
+    rd_idx: AtomicU32,
+    // Only written by the producer
+    wr_idx: AtomicU32,
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    has_split_prod: Cell<bool>,
+    has_split_cons: Cell<bool>,
+}
+
+// Thread safe under the SPSC premise enforced by split, as RingBuf
+unsafe impl<T: Send> Sync for RingBufBoxed<T> {}
+
+impl<T> Drop for RingBufBoxed<T> {
+    fn drop(&mut self) {
+        while self.pop_value().is_some() {}
+    }
+}
+
+impl<T> RingBufBoxed<T> {
+
+    /// Ring of capacity items, between 1 and 2^31 - 1
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0 && capacity < 1 << 31, "Ringbuf capacity must be > 0 and < 2^31");
+        RingBufBoxed {
+            rd_idx: AtomicU32::new(0),
+            wr_idx: AtomicU32::new(0),
+            buffer: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            has_split_prod: Cell::new(false),
+            has_split_cons: Cell::new(false),
+        }
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // Index n positions after idx, wrapping at twice the capacity
+    #[inline(always)]
+    fn wrap_add(&self, idx: u32, n: u32) -> u32 {
+        let wrap = 2 * self.capacity() as u32;
+        let next = idx + n;
+        if next >= wrap { next - wrap } else { next }
+    }
+
+    #[inline(always)]
+    fn slot(&self, idx: u32) -> *mut T {
+        let cap = self.capacity();
+        let idx = idx as usize;
+        let slot = if idx >= cap { idx - cap } else { idx };
+        self.buffer[slot].get() as *mut T
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        let rd = self.rd_idx.load(Ordering::Acquire);
+        let wr = self.wr_idx.load(Ordering::Acquire);
+        if wr >= rd { wr - rd } else { wr + 2 * self.capacity() as u32 - rd }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Vacant slots, capacity minus len
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        self.capacity() as u32 - self.len()
+    }
+
+    // Producer side
+    fn try_push(&self, val: T) -> Result<(), T> {
+        if self.free_len() == 0 {
+            return Err(val);
+        }
+        let wr = self.wr_idx.load(Ordering::Relaxed);
+        unsafe { self.slot(wr).write(val) };
+        self.wr_idx.store(self.wrap_add(wr, 1), Ordering::Release);
+        Ok(())
+    }
+
+    // Consumer side
+    fn peek_at(&self, n: u32) -> Option<&T> {
+        if n >= self.len() {
+            return None;
+        }
+        let rd = self.rd_idx.load(Ordering::Relaxed);
+        Some(unsafe { &*self.slot(self.wrap_add(rd, n)) })
+    }
+
+    fn pop_value(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let rd = self.rd_idx.load(Ordering::Relaxed);
+        let val = unsafe { self.slot(rd).read() };
+        self.rd_idx.store(self.wrap_add(rd, 1), Ordering::Release);
+        Some(val)
+    }
+
+    // Return the producer, once in life time
+    pub fn split_prod(&self) -> Result<Producer<'_, T>, SplitError> {
+        if self.has_split_prod.get() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        self.has_split_prod.set(true);
+        Ok(Producer { inner: self })
+    }
+
+    // Return the consumer, once in life time
+    pub fn split_cons(&self) -> Result<Consumer<'_, T>, SplitError> {
+        if self.has_split_cons.get() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        self.has_split_cons.set(true);
+        Ok(Consumer { inner: self })
+    }
+
+    /// Split both handles, or neither if one has been split before
+    pub fn split(&self) -> Result<(Producer<'_, T>, Consumer<'_, T>), SplitError> {
+        if self.has_split_prod.get() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.has_split_cons.get() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_prod()?, self.split_cons()?))
+    }
+}
+
+pub struct Producer<'a, T> {
+    inner: &'a RingBufBoxed<T>,
+}
+
+impl<T> Producer<'_, T> {
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.inner.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        self.inner.free_len()
+    }
+
+    /// Push val, handed back if the ring is full
+    #[inline(always)]
+    pub fn try_push(&mut self, val: T) -> Result<(), T> {
+        self.inner.try_push(val)
+    }
+
+    /// Copy as many items of src as fit. Returns the number pushed
+    pub fn push_slice(&mut self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        src.iter().take_while(|&&val| self.inner.try_push(val).is_ok()).count()
+    }
+}
+
+pub struct Consumer<'a, T> {
+    inner: &'a RingBufBoxed<T>,
+}
+
+impl<T> Consumer<'_, T> {
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.inner.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Item at the head, left in the ring
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<&T> {
+        self.inner.peek_at(0)
+    }
+
+    /// Reference to the nth queued item relative to the head
+    #[inline(always)]
+    pub fn peek_at(&self, n: u32) -> Option<&T> {
+        self.inner.peek_at(n)
+    }
+
+    /// Drop the item at the head
+    #[inline(always)]
+    pub fn pop(&mut self) -> Result<(), ErrCode> {
+        self.inner.pop_value().map(|_| ()).ok_or(ErrCode::BufEmpty)
+    }
+
+    /// Move the item at the head out and pop it
+    #[inline(always)]
+    pub fn pop_value(&mut self) -> Option<T> {
+        self.inner.pop_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    #[test]
+    fn runtime_capacity() {
+        let ring: RingBufBoxed<String> = RingBufBoxed::new(3);
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        assert!(ring.split().err() == Some(SplitError::AlreadySplitProd));
+
+        for i in 0..10 {
+            assert!(producer.try_push(i.to_string()).is_ok());
+            assert!(producer.try_push((i + 1).to_string()).is_ok());
+            assert!(consumer.peek_at(1).map(String::as_str) == Some((i + 1).to_string().as_str()));
+            assert!(consumer.pop_value() == Some(i.to_string()));
+            assert!(consumer.pop().is_ok());
+        }
+        // Remaining items dropped with the ring
+        assert!(producer.try_push("left".to_string()).is_ok());
+
+        let big: RingBufBoxed<u32> = RingBufBoxed::new(100_000);
+        let (mut producer, mut consumer) = big.split().unwrap();
+        assert!(producer.push_slice(&[7; 150_000]) == 100_000);
+        assert!(producer.free_len() == 0 && consumer.len() == 100_000);
+        assert!(consumer.pop_value() == Some(7));
+    }
+}
@@ -0,0 +1,293 @@
+//! Single producer ring buffer broadcasting every item to C consumers.
+//!
+//! All consumers read the same storage, each through its own read
+//! position. A slot is reused once every consumer moved past it, so the
+//! slowest consumer holds the producer back. The consumers are all handed
+//! out by `split`, none can join later and miss items or block the
+//! producer unexpectedly.
+//!
+//! N must be a power of two so that the positions wrap over the full u32
+//! range.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::ringbuf_ref::CachePadded;
+
+pub struct BroadcastRingBuf<T, const N: usize, const C: usize> {
+    // Only written by the producer
+    wr_pos: CachePadded<AtomicU32>,
+    // One per consumer, only written by that consumer
+    rd_pos: [CachePadded<AtomicU32>; C],
+    buffer_ucell: [UnsafeCell<MaybeUninit<T>>; N],
+    // Every slot has been written once, only written by the producer
+    wrapped: Cell<bool>,
+    has_split: Cell<bool>,
+}
+
+// Items are written by the producer only into slots no consumer can reach
+// anymore and shared by reference between the consumers
+unsafe impl<T: Send + Sync, const N: usize, const C: usize> Sync for BroadcastRingBuf<T, N, C> {}
+
+/// Producer handle, once in life time
+pub struct BroadcastProducer<'a, T, const N: usize, const C: usize> {
+    inner: &'a BroadcastRingBuf<T, N, C>,
+}
+
+/// One of the C consumer handles
+pub struct BroadcastConsumer<'a, T, const N: usize, const C: usize> {
+    inner: &'a BroadcastRingBuf<T, N, C>,
+    id: usize,
+}
+
+/// Producer and consumers returned by BroadcastRingBuf::split
+pub type BroadcastHandles<'a, T, const N: usize, const C: usize> =
+    (BroadcastProducer<'a, T, N, C>, [BroadcastConsumer<'a, T, N, C>; C]);
+
+impl<T, const N: usize, const C: usize> Default for BroadcastRingBuf<T, N, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, const C: usize> BroadcastRingBuf<T, N, C> {
+
+    const OK: () = assert!(N.is_power_of_two() && N < (1 << 31) && C > 0, "Broadcast capacity must be a power of two and C > 0");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_U: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_POS: CachePadded<AtomicU32> = CachePadded(AtomicU32::new(0));
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = BroadcastRingBuf::<T, N, C>::OK;
+        BroadcastRingBuf {
+            wr_pos: Self::INIT_POS,
+            rd_pos: [Self::INIT_POS; C],
+            buffer_ucell: [Self::INIT_U; N],
+            wrapped: Cell::new(false),
+            has_split: Cell::new(false),
+        }
+    }
+
+    /// Return the producer and all the consumers, once in life time
+    pub fn split(&self) -> Option<BroadcastHandles<'_, T, N, C>> {
+        if self.has_split.get() {
+            None
+        } else {
+            self.has_split.set(true);
+            Some((
+                BroadcastProducer { inner: self },
+                core::array::from_fn(|id| BroadcastConsumer { inner: self, id }),
+            ))
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, pos: u32) -> *mut T {
+        self.buffer_ucell[pos as usize & (N - 1)].get() as *mut T
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize, const C: usize> Drop for BroadcastRingBuf<T, N, C> {
+    fn drop(&mut self) {
+        // Items stay in their slot after all consumers passed them
+        let written = if self.wrapped.get() { N } else { self.wr_pos.load(Ordering::Relaxed) as usize };
+        for cell in self.buffer_ucell[..written].iter_mut() {
+            unsafe { cell.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<'a, T, const N: usize, const C: usize> BroadcastProducer<'a, T, N, C> {
+
+    /// Items not yet read by the slowest consumer
+    #[inline]
+    pub fn len(&self) -> u32 {
+        let ring = self.inner;
+        let wr = ring.wr_pos.load(Ordering::Relaxed);
+        ring.rd_pos
+            .iter()
+            .map(|rd| wr.wrapping_sub(rd.load(Ordering::Acquire)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len() as usize == N
+    }
+
+    /// Publish val to all consumers, handing it back while the slowest
+    /// consumer still has N items to read
+    pub fn push(&mut self, val: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(val);
+        }
+        let ring = self.inner;
+        let wr = ring.wr_pos.load(Ordering::Relaxed);
+        let slot = ring.slot(wr);
+        unsafe {
+            // Every consumer is past the item held from the previous lap
+            if ring.wrapped.get() {
+                slot.drop_in_place();
+            }
+            slot.write(val);
+        }
+        let next = wr.wrapping_add(1);
+        if next as usize & (N - 1) == 0 {
+            ring.wrapped.set(true);
+        }
+        ring.wr_pos.store(next, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<'a, T, const N: usize, const C: usize> BroadcastConsumer<'a, T, N, C> {
+
+    /// Index of this consumer among the C handed out by split
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Items this consumer has not read yet
+    #[inline]
+    pub fn len(&self) -> u32 {
+        let ring = self.inner;
+        ring.wr_pos.load(Ordering::Acquire).wrapping_sub(ring.rd_pos[self.id].load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next item for this consumer, left in place
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            let ring = self.inner;
+            Some(unsafe { &*ring.slot(ring.rd_pos[self.id].load(Ordering::Relaxed)) })
+        }
+    }
+
+    /// Move past the next item, false if there is none
+    #[inline]
+    pub fn advance(&mut self) -> bool {
+        if self.is_empty() {
+            false
+        } else {
+            let rd = &self.inner.rd_pos[self.id];
+            rd.store(rd.load(Ordering::Relaxed).wrapping_add(1), Ordering::Release);
+            true
+        }
+    }
+
+    /// Copy of the next item, moving past it
+    #[inline]
+    pub fn recv(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let val = self.peek()?.clone();
+        self.advance();
+        Some(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static EVENTS: BroadcastRingBuf<u32, 4, 2> = BroadcastRingBuf::new();
+
+    #[test]
+    fn every_consumer_sees_every_item() {
+        let (mut producer, [mut fast, mut slow]) = EVENTS.split().unwrap();
+        assert!(EVENTS.split().is_none());
+        assert!(fast.id() == 0 && slow.id() == 1);
+
+        for i in 0..4 {
+            assert!(producer.push(i).is_ok());
+        }
+        for i in 0..4 {
+            assert!(fast.recv() == Some(i));
+        }
+        // Slow consumer still holds every slot
+        assert!(producer.push(4) == Err(4));
+        assert!(slow.peek() == Some(&0));
+        assert!(slow.advance());
+        assert!(producer.push(4).is_ok());
+        assert!(producer.is_full());
+        assert!(fast.recv() == Some(4));
+        for i in 1..5 {
+            assert!(slow.recv() == Some(i));
+        }
+        assert!(slow.recv().is_none() && fast.is_empty() && producer.is_empty());
+    }
+
+    #[test]
+    fn drops_items() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        {
+            let ring: BroadcastRingBuf<Rc<()>, 2, 3> = BroadcastRingBuf::new();
+            let (mut producer, mut consumers) = ring.split().unwrap();
+            for _ in 0..5 {
+                assert!(producer.push(tracker.clone()).is_ok());
+                for c in consumers.iter_mut() {
+                    assert!(c.advance());
+                }
+            }
+            // Passed items stay until overwritten
+            assert!(Rc::strong_count(&tracker) == 3);
+        }
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+
+    #[test]
+    fn threads() {
+        static RING: BroadcastRingBuf<u32, 8, 3> = BroadcastRingBuf::new();
+        let (mut producer, consumers) = RING.split().unwrap();
+
+        let handles: Vec<_> = consumers
+            .into_iter()
+            .map(|mut consumer| {
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        loop {
+                            if let Some(val) = consumer.recv() {
+                                assert!(val == i);
+                                break;
+                            }
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 0..1000 {
+            while producer.push(i).is_err() {
+                std::thread::yield_now();
+            }
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Byte stream ring implementing the `embedded_io` and `std::io` traits.
+//!
+//! `RingBufBytes` is a `RingBuf<u8, N>`; its Producer implements `Write`
+//! and its Consumer implements `Read`, so the ring can back UART drivers
+//! and no_std serialization code directly. Following the `embedded_io`
+//! contract, `write` spins until at least one byte fits and `read` until
+//! at least one byte is available. Poll `WriteReady`/`ReadReady` first to
+//! stay non-blocking, e.g. in an ISR.
+//!
+//! The `std::io` implementations never block and behave like a byte
+//! slice: `read` returns 0 once the ring is drained and `write` returns 0
+//! while it is full, so `io::copy` from the Consumer drains what is
+//! queued and `write_all` into a full ring fails with `WriteZero`.
+
+#[cfg(feature = "embedded-io")]
+use core::convert::Infallible;
+
+#[cfg(feature = "embedded-io")]
+use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+
+use crate::backend::{Backend, IndexPair};
+use crate::ringbuf::{Consumer, Producer, RingBuf};
+
+pub type RingBufBytes<const N: usize, B = IndexPair> = RingBuf<u8, N, B>;
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> ErrorType for Producer<'a, u8, N, B> {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> Write for Producer<'a, u8, N, B> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = self.push_slice(buf);
+            if n > 0 {
+                return Ok(n);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Written bytes are visible to the consumer right away
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> WriteReady for Producer<'a, u8, N, B> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.alloc_uninit().is_some())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> ErrorType for Consumer<'a, u8, N, B> {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> Read for Consumer<'a, u8, N, B> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = self.pop_slice(buf);
+            if n > 0 {
+                return Ok(n);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<'a, const N: usize, B: Backend> ReadReady for Consumer<'a, u8, N, B> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.reader_front().is_some())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N: usize, B: Backend> std::io::Write for Producer<'a, u8, N, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.push_slice(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N: usize, B: Backend> std::io::Read for Consumer<'a, u8, N, B> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.pop_slice(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SlotSeq;
+    #[cfg(feature = "std")]
+    use std::io;
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn read_write() {
+        let ring = RingBufBytes::<16>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+
+        assert!(!consumer.read_ready().unwrap());
+        producer.write_all(b"hello world").unwrap();
+        assert!(producer.write_ready().unwrap());
+        assert!(producer.write(b"!!!!!!!!").unwrap() == 5);
+        assert!(!producer.write_ready().unwrap());
+
+        let mut out = [0u8; 16];
+        consumer.read_exact(&mut out[..5]).unwrap();
+        assert!(&out[..5] == b"hello");
+        // Wraps around
+        assert!(producer.write(b"??").unwrap() == 2);
+        assert!(consumer.read(&mut out).unwrap() == 13);
+        assert!(&out[..13] == b" world!!!!!??");
+        assert!(!consumer.read_ready().unwrap());
+
+        let seq = RingBufBytes::<4, SlotSeq>::new();
+        let (mut producer, mut consumer) = seq.split().unwrap();
+        assert!(producer.write(b"abcdef").unwrap() == 4);
+        assert!(consumer.read(&mut out).unwrap() == 4);
+        assert!(&out[..4] == b"abcd");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_pipe() {
+        let ring = RingBufBytes::<8, SlotSeq>::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+
+        assert!(io::copy(&mut &b"abcdef"[..], &mut producer).unwrap() == 6);
+        let mut out = std::vec::Vec::new();
+        assert!(io::copy(&mut consumer, &mut out).unwrap() == 6);
+        assert!(out == b"abcdef");
+
+        // Qualified, embedded_io::Write is in scope as well
+        let err = io::Write::write_all(&mut producer, b"0123456789").unwrap_err();
+        assert!(err.kind() == io::ErrorKind::WriteZero);
+        let mut s = std::string::String::new();
+        io::Read::read_to_string(&mut consumer, &mut s).unwrap();
+        assert!(s == "01234567");
+    }
+}
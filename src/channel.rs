@@ -0,0 +1,165 @@
+//! Channel facade with the embassy-sync `Channel` method names on top of
+//! `AsyncRingBuf`, behind the `async` feature.
+//!
+//! Being SPSC, the channel is split once into a Sender and a Receiver
+//! instead of being shared by reference. Otherwise code written against
+//! embassy-sync's `try_send`/`send`/`try_receive`/`receive` keeps working
+//! with a statically allocated ring.
+
+use core::future::poll_fn;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::async_ring::{AsyncConsumer, AsyncProducer, AsyncRingBuf};
+use crate::ringbuf::SplitError;
+
+/// Error of `Sender::try_send`, handing the message back
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+}
+
+/// Error of `Receiver::try_receive`
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryReceiveError {
+    Empty,
+}
+
+pub struct Channel<T, const N: usize> {
+    ring: AsyncRingBuf<T, N>,
+}
+
+pub struct Sender<'a, T, const N: usize> {
+    inner: AsyncProducer<'a, T, N>,
+}
+
+pub struct Receiver<'a, T, const N: usize> {
+    inner: AsyncConsumer<'a, T, N>,
+}
+
+/// Sender and Receiver pair returned by Channel::split
+pub type ChannelHandles<'a, T, const N: usize> = (Sender<'a, T, N>, Receiver<'a, T, N>);
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Channel<T, N> {
+
+    pub const fn new() -> Self {
+        Channel { ring: AsyncRingBuf::new() }
+    }
+
+    pub fn split(&self) -> Result<ChannelHandles<'_, T, N>, SplitError> {
+        let (prod, cons) = self.ring.split()?;
+        Ok((Sender { inner: prod }, Receiver { inner: cons }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<'a, T, const N: usize> Sender<'a, T, N> {
+
+    /// Wait for space, registering the waker when full
+    pub fn poll_ready_to_send(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Cannot fail
+        Pin::new(&mut self.inner).poll_ready(cx).map(|_| ())
+    }
+
+    pub fn try_send(&mut self, message: T) -> Result<(), TrySendError<T>> {
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        match self.poll_ready_to_send(&mut cx) {
+            Poll::Ready(()) => {
+                // Space checked above
+                let _ = Pin::new(&mut self.inner).start_send(message);
+                Ok(())
+            }
+            Poll::Pending => Err(TrySendError::Full(message)),
+        }
+    }
+
+    /// Send message, waiting for space if full
+    pub async fn send(&mut self, message: T) {
+        poll_fn(|cx| self.poll_ready_to_send(cx)).await;
+        // Space checked above
+        let _ = Pin::new(&mut self.inner).start_send(message);
+    }
+}
+
+impl<'a, T, const N: usize> Receiver<'a, T, N> {
+
+    /// Poll for a message, registering the waker when empty. Stays pending
+    /// once the sender is gone
+    pub fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(message)) => Poll::Ready(message),
+            _ => Poll::Pending,
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        match self.poll_receive(&mut cx) {
+            Poll::Ready(message) => Ok(message),
+            Poll::Pending => Err(TryReceiveError::Empty),
+        }
+    }
+
+    /// Receive a message, waiting if empty
+    pub async fn receive(&mut self) -> T {
+        poll_fn(|cx| self.poll_receive(cx)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    static CHANNEL: Channel<u32, 2> = Channel::new();
+
+    #[test]
+    fn send_receive() {
+        let (mut sender, mut receiver) = CHANNEL.split().unwrap();
+
+        assert!(receiver.try_receive() == Err(TryReceiveError::Empty));
+        assert!(sender.try_send(1).is_ok());
+        assert!(sender.try_send(2).is_ok());
+        assert!(sender.try_send(3) == Err(TrySendError::Full(3)));
+        assert!(CHANNEL.is_full());
+        assert!(receiver.try_receive() == Ok(1));
+
+        let r_handle = std::thread::spawn(move || {
+            block_on(async {
+                for i in 2..50 {
+                    assert!(receiver.receive().await == i);
+                }
+            })
+        });
+        block_on(async {
+            for i in 3..50 {
+                sender.send(i).await;
+            }
+        });
+        r_handle.join().unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Ring buffer with a CRC per item.
+//!
+//! For rings shared with an unreliable partner, e.g. a DSP with known
+//! memory bugs. The producer computes a CRC-32 over the item bytes at commit
+//! and stores it next to the item. The consumer verifies it on peek and pop
+//! and gets `ErrCode::CorruptEntry` instead of a garbled item. A corrupt
+//! item stays queued until dropped with `pop`, so that the consumer decides
+//! whether to skip it or give up on the ring.
+
+use core::mem::size_of;
+
+use crate::crc::crc32;
+use crate::ringbuf_ref::{ErrCode, RingBufRef};
+
+/// Items whose bytes are all initialized, i.e. Copy types without padding
+///
+/// # Safety
+///
+/// The implementing type must not contain padding or other uninitialized
+/// bytes, e.g. a `#[repr(C)]` struct of PlainData fields laid out without
+/// gaps.
+pub unsafe trait PlainData: Copy {}
+
+macro_rules! impl_plain_data {
+    ($($t:ty),*) => {
+        $(unsafe impl PlainData for $t {})*
+    };
+}
+
+impl_plain_data!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: PlainData, const M: usize> PlainData for [T; M] {}
+
+/// Slot layout, CRC-32 of the item bytes followed by the item
+#[repr(C)]
+pub struct CheckedEntry<T> {
+    pub crc: u32,
+    pub val: T,
+}
+
+fn item_crc<T: PlainData>(val: &T) -> u32 {
+    // PlainData has no uninitialized bytes
+    crc32(unsafe { core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) })
+}
+
+/// RingBufRef of CRC checked items
+pub struct CheckedRingBufRef<T: PlainData, const N: usize> {
+    ring: RingBufRef<CheckedEntry<T>, N>,
+}
+
+impl<T: PlainData, const N: usize> Default for CheckedRingBufRef<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PlainData, const N: usize> CheckedRingBufRef<T, N> {
+
+    pub const fn new() -> Self {
+        CheckedRingBufRef { ring: RingBufRef::new() }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Slot at the write index, published by `commit`
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        self.ring.alloc_uninit().map(|slot| &mut *core::ptr::addr_of_mut!((*slot.as_mut_ptr()).val))
+    }
+
+    /// Compute the CRC of the item at the write index and commit it
+    ///
+    /// # Safety
+    /// The item must have been written through `writer_front`, the CRC
+    /// reads all of its bytes.
+    #[inline]
+    pub unsafe fn commit(&self) -> Result<(), ErrCode> {
+        let slot = self.ring.alloc_uninit().ok_or(ErrCode::BufFull)?;
+        let entry = slot.as_mut_ptr();
+        (*entry).crc = item_crc(&(*entry).val);
+        self.ring.commit()
+    }
+
+    /// Write and commit in one step
+    #[inline]
+    pub fn push(&self, val: T) -> Result<(), ErrCode> {
+        self.ring.push(CheckedEntry { crc: item_crc(&val), val })
+    }
+
+    /// Oldest item if its CRC matches
+    #[inline]
+    pub fn reader_front(&self) -> Result<&T, ErrCode> {
+        let entry = self.ring.reader_front().ok_or(ErrCode::BufEmpty)?;
+        if item_crc(&entry.val) != entry.crc {
+            return Err(ErrCode::CorruptEntry);
+        }
+        Ok(&entry.val)
+    }
+
+    /// Drop the oldest item without checking it, e.g. to skip a corrupt one
+    #[inline(always)]
+    pub fn pop(&self) -> Result<(), ErrCode> {
+        self.ring.pop()
+    }
+
+    /// Copy the oldest item out and pop it if its CRC matches. The copy is
+    /// the one checked, so later corruption of the slot cannot slip through
+    #[inline]
+    pub fn pop_value(&self) -> Result<T, ErrCode> {
+        let entry = self.ring.reader_front().ok_or(ErrCode::BufEmpty)?;
+        let (crc, val) = (entry.crc, entry.val);
+        if item_crc(&val) != crc {
+            return Err(ErrCode::CorruptEntry);
+        }
+        self.ring.pop()?;
+        Ok(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_entry() {
+        let ring: CheckedRingBufRef<[u16; 3], 2> = CheckedRingBufRef::new();
+        assert!(ring.pop_value() == Err(ErrCode::BufEmpty));
+
+        unsafe {
+            core::ptr::write(ring.writer_front().unwrap(), [1, 2, 3]);
+            assert!(ring.commit().is_ok());
+        }
+        assert!(ring.push([4, 5, 6]).is_ok());
+        assert!(ring.push([7, 8, 9]) == Err(ErrCode::BufFull));
+
+        // Flip a bit behind the producer's back
+        ring.ring.reader_front_mut().unwrap().val[1] ^= 0x10;
+        assert!(ring.reader_front() == Err(ErrCode::CorruptEntry));
+        assert!(ring.pop_value() == Err(ErrCode::CorruptEntry));
+        assert!(ring.len() == 2);
+
+        // Skipped by the consumer
+        assert!(ring.pop().is_ok());
+        assert!(ring.reader_front() == Ok(&[4, 5, 6]));
+        assert!(ring.pop_value() == Ok([4, 5, 6]));
+    }
+}
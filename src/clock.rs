@@ -0,0 +1,15 @@
+//! Time source used by the time aware helpers of this crate.
+
+/// Monotonic tick counter, e.g. a cycle counter or an RTOS tick. The counter
+/// is expected to wrap around at u32::MAX, users of the ticks only compare
+/// wrapping differences.
+pub trait Clock {
+    fn now(&self) -> u32;
+}
+
+impl<C: Clock> Clock for &C {
+    #[inline]
+    fn now(&self) -> u32 {
+        (*self).now()
+    }
+}
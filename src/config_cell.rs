@@ -0,0 +1,185 @@
+//! Read mostly configuration cell with a single writer and many readers.
+//!
+//! Two version slots are kept, each with a count of the readers currently
+//! holding it. Readers never wait for the writer: they take a reference to
+//! the current slot and only retry when a publish happened in between. The
+//! writer fills the other slot once no reader holds it anymore (dropping the
+//! old version stored there) and then flips the current slot. A publish
+//! fails, handing the value back, while readers still hold the old version.
+//!
+//! Reader counts use atomic read-modify-write, not available on targets
+//! without CAS such as thumbv6m unless the `critical-section` feature is on.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+pub struct ConfigCell<T> {
+    slots: [UnsafeCell<MaybeUninit<T>>; 2],
+    // Number of readers holding each slot
+    readers: [AtomicU32; 2],
+    // Slot holding the latest version
+    current: AtomicU32,
+    // Set once the second slot has been written, only touched by the writer
+    second_filled: Cell<bool>,
+    has_split_writer: Cell<bool>,
+}
+
+// Readers only get shared references and the single writer only writes a slot
+// no reader holds. T is handed between contexts hence must be Send and Sync.
+unsafe impl<T: Send + Sync> Sync for ConfigCell<T> {}
+
+/// Writer handle, once in life time
+pub struct ConfigWriter<'a, T> {
+    inner: &'a ConfigCell<T>,
+}
+
+/// Reference to a consistent version, keeps the version alive until dropped
+pub struct ConfigGuard<'a, T> {
+    inner: &'a ConfigCell<T>,
+    slot: usize,
+}
+
+impl<T> ConfigCell<T> {
+
+    pub const fn new(init: T) -> Self {
+        ConfigCell {
+            slots: [UnsafeCell::new(MaybeUninit::new(init)), UnsafeCell::new(MaybeUninit::uninit())],
+            readers: [AtomicU32::new(0), AtomicU32::new(0)],
+            current: AtomicU32::new(0),
+            second_filled: Cell::new(false),
+            has_split_writer: Cell::new(false),
+        }
+    }
+
+    /// Return the writer, once in life time
+    pub fn split_writer(&self) -> Option<ConfigWriter<'_, T>> {
+        if self.has_split_writer.get() {
+            None
+        } else {
+            self.has_split_writer.set(true);
+            Some(ConfigWriter { inner: self })
+        }
+    }
+
+    /// Latest published version
+    #[inline]
+    pub fn read(&self) -> ConfigGuard<'_, T> {
+        loop {
+            let slot = self.current.load(Ordering::Acquire) as usize;
+            // Registering and re-checking current pair with the writer's
+            // check of the count and flip of current. SeqCst on all four so
+            // that at least one side sees the other's write
+            self.readers[slot].rmw_fetch_add(1, Ordering::SeqCst);
+            // Writer cannot start writing this slot anymore if it is still
+            // current after registering. Otherwise back off and retry
+            if self.current.load(Ordering::SeqCst) as usize == slot {
+                return ConfigGuard { inner: self, slot };
+            }
+            self.readers[slot].rmw_fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+impl<T> Drop for ConfigCell<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.slots[0].get_mut().assume_init_drop();
+            if self.second_filled.get() {
+                self.slots[1].get_mut().assume_init_drop();
+            }
+        }
+    }
+}
+
+impl<'a, T> ConfigWriter<'a, T> {
+
+    /// Publish a new version. Fails with the value handed back if readers
+    /// still hold the previous version
+    pub fn publish(&mut self, val: T) -> Result<(), T> {
+        let cell = self.inner;
+        let spare = 1 - cell.current.load(Ordering::Relaxed) as usize;
+
+        // SeqCst, see ConfigCell::read
+        if cell.readers[spare].load(Ordering::SeqCst) != 0 {
+            return Err(val);
+        }
+
+        // No reader can observe the spare slot, reclaim the old version
+        let loc = unsafe { &mut *cell.slots[spare].get() };
+        if spare == 0 || cell.second_filled.get() {
+            unsafe { loc.assume_init_drop() };
+        }
+        loc.write(val);
+        cell.second_filled.set(true);
+
+        cell.current.store(spare as u32, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl<'a, T> Deref for ConfigGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { (*self.inner.slots[self.slot].get()).assume_init_ref() }
+    }
+}
+
+impl<'a, T> Drop for ConfigGuard<'a, T> {
+    fn drop(&mut self) {
+        self.inner.readers[self.slot].rmw_fetch_sub(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub struct Tuning {
+        gain: u32,
+        offset: i32,
+    }
+
+    static CONFIG: ConfigCell<Tuning> = ConfigCell::new(Tuning { gain: 1, offset: 0 });
+
+    #[test]
+    fn publish_and_reclaim() {
+        let mut writer = CONFIG.split_writer().unwrap();
+        assert!(CONFIG.split_writer().is_none());
+
+        assert!(CONFIG.read().gain == 1);
+
+        let old = CONFIG.read();
+        assert!(writer.publish(Tuning { gain: 2, offset: -1 }).is_ok());
+
+        // Old version stays consistent while held
+        assert!(old.gain == 1);
+        let new = CONFIG.read();
+        assert!(new.gain == 2 && new.offset == -1);
+
+        // Cannot overwrite the version still held by a reader
+        assert!(writer.publish(Tuning { gain: 3, offset: 0 }).is_err());
+        drop(old);
+        assert!(writer.publish(Tuning { gain: 3, offset: 0 }).is_ok());
+        assert!(new.gain == 2);
+        assert!(CONFIG.read().gain == 3);
+    }
+
+    #[test]
+    fn drops_versions() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        {
+            let cell = ConfigCell::new(tracker.clone());
+            let mut writer = cell.split_writer().unwrap();
+            assert!(writer.publish(tracker.clone()).is_ok());
+            assert!(writer.publish(tracker.clone()).is_ok());
+            assert!(Rc::strong_count(&tracker) == 3);
+        }
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+}
@@ -0,0 +1,37 @@
+//! CRC-32 (IEEE 802.3, reflected, as zlib) for integrity checks.
+//!
+//! Bitwise and table free, trading speed for flash on small targets.
+
+const POLY: u32 = 0xEDB8_8320;
+
+/// Continue crc (as returned by `crc32`) over data
+pub const fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    let mut i = 0;
+    while i < data.len() {
+        crc ^= data[i] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        i += 1;
+    }
+    !crc
+}
+
+pub const fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert!(crc32(b"123456789") == 0xCBF4_3926);
+        assert!(crc32_update(crc32(b"1234"), b"56789") == 0xCBF4_3926);
+        assert!(crc32(b"") == 0);
+    }
+}
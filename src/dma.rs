@@ -0,0 +1,239 @@
+//! Split-phase DMA handoff of ring buffer frames.
+//!
+//! The producer takes a grant of the next vacant frame and hands its raw
+//! parts to a DMA engine. The grant does not borrow the producer and can be
+//! moved to the DMA complete ISR, which commits the frame with the received
+//! length via `DmaRing::complete`. One grant is in flight at a time.
+//!
+//! The consumer side is a plain `ringbuf::Consumer` of `InlinePayload<K>`
+//! frames.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+
+use crate::ringbuf::{Consumer, RingBuf};
+use crate::shared_pool::InlinePayload;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DmaError {
+    /// Handle already split
+    AlreadySplit,
+    /// Completed length exceeds the frame capacity
+    LenOverflow,
+    /// Grant was not issued by this ring or not in flight
+    ForeignGrant,
+}
+
+/// Ring of N frames of up to K bytes filled by DMA
+pub struct DmaRing<const K: usize, const N: usize> {
+    ring: RingBuf<InlinePayload<K>, N>,
+    // Set by the producer on grant, cleared on completion
+    in_flight: AtomicBool,
+    has_split_prod: Cell<bool>,
+}
+
+// Delcare this is thread safe, the frame behind a grant is only accessed by
+// the grant holder until it is completed.
+unsafe impl<const K: usize, const N: usize> Sync for DmaRing<K, N> {}
+
+/// Producer handle issuing grants, once in life time
+pub struct DmaProducer<'a, const K: usize, const N: usize> {
+    inner: &'a DmaRing<K, N>,
+}
+
+/// Frame handed to the DMA engine. Does not borrow the producer, only the
+/// ring, and can be sent to the completing context
+pub struct DmaGrant<'a> {
+    ptr: *mut u8,
+    cap: usize,
+    _ring: PhantomData<&'a [u8]>,
+}
+
+// The frame is exclusively owned by the grant until completed
+unsafe impl Send for DmaGrant<'_> {}
+
+// The frame stays at ptr and the ring outlives the grant
+#[cfg(feature = "embedded-dma")]
+unsafe impl embedded_dma::WriteBuffer for DmaGrant<'_> {
+    type Word = u8;
+
+    unsafe fn write_buffer(&mut self) -> (*mut u8, usize) {
+        self.as_raw_parts()
+    }
+}
+
+impl DmaGrant<'_> {
+    /// Start address and capacity in bytes of the frame, for programming the
+    /// DMA engine
+    pub fn as_raw_parts(&self) -> (*mut u8, usize) {
+        (self.ptr, self.cap)
+    }
+}
+
+impl<const K: usize, const N: usize> Default for DmaRing<K, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const K: usize, const N: usize> DmaRing<K, N> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: DmaRing<K, N> = Self::new();
+
+    pub const fn new() -> Self {
+        DmaRing {
+            ring: RingBuf::new(),
+            in_flight: AtomicBool::new(false),
+            has_split_prod: Cell::new(false),
+        }
+    }
+
+    pub fn split_prod(&self) -> Result<DmaProducer<'_, K, N>, DmaError> {
+        if self.has_split_prod.get() {
+            Err(DmaError::AlreadySplit)
+        } else {
+            self.has_split_prod.set(true);
+            Ok(DmaProducer { inner: self })
+        }
+    }
+
+    pub fn split_cons(&self) -> Result<Consumer<'_, InlinePayload<K>, N>, DmaError> {
+        self.ring.split_cons().map_err(|_| DmaError::AlreadySplit)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    // Grant must be of the vacant frame at the write index
+    #[allow(clippy::mut_from_ref)]
+    fn check_grant(&self, grant: &DmaGrant<'_>) -> Result<&mut InlinePayload<K>, DmaError> {
+        if !self.in_flight.load(Ordering::Acquire) {
+            return Err(DmaError::ForeignGrant);
+        }
+        // Frame initialized by the grant
+        match unsafe { self.ring.ring().writer_front() } {
+            Some(frame) if core::ptr::eq(frame.data.as_ptr(), grant.ptr) => Ok(frame),
+            _ => Err(DmaError::ForeignGrant),
+        }
+    }
+
+    /// Commit the frame of grant holding len bytes, e.g. from the DMA
+    /// complete ISR. The grant is handed back on error
+    pub fn complete<'a>(&'a self, grant: DmaGrant<'a>, len: usize) -> Result<(), (DmaGrant<'a>, DmaError)> {
+        let frame = match self.check_grant(&grant) {
+            Ok(frame) => frame,
+            Err(e) => return Err((grant, e)),
+        };
+        if len > K {
+            return Err((grant, DmaError::LenOverflow));
+        }
+        // Transfer is complete before the frame is published
+        fence(Ordering::SeqCst);
+        frame.len = len as u32;
+        // Frame is vacant as checked above
+        let _ = self.ring.ring().commit();
+        self.in_flight.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Drop the grant without committing, e.g. on a DMA error
+    pub fn cancel<'a>(&'a self, grant: DmaGrant<'a>) -> Result<(), (DmaGrant<'a>, DmaError)> {
+        if let Err(e) = self.check_grant(&grant) {
+            return Err((grant, e));
+        }
+        self.in_flight.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<'a, const K: usize, const N: usize> DmaProducer<'a, K, N> {
+
+    /// Grant the next vacant frame. None if the ring is full or a grant is
+    /// still in flight
+    pub fn grant(&mut self) -> Option<DmaGrant<'a>> {
+        if self.inner.in_flight.load(Ordering::Acquire) {
+            return None;
+        }
+        // Cleared so that the bytes past the transfer are initialized too
+        let frame = self.inner.ring.ring().alloc_uninit()?.write(InlinePayload::new());
+        self.inner.in_flight.store(true, Ordering::Release);
+        // Nothing of the CPU accesses before is reordered past the start
+        fence(Ordering::SeqCst);
+        Some(DmaGrant { ptr: frame.data.as_mut_ptr(), cap: K, _ring: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static DMA_RING: DmaRing<8, 2> = DmaRing::new();
+
+    #[test]
+    fn grant_complete_from_other_context() {
+        let mut producer = DMA_RING.split_prod().unwrap();
+        let mut consumer = DMA_RING.split_cons().unwrap();
+        assert!(DMA_RING.split_prod().is_err());
+
+        let grant = producer.grant().unwrap();
+        assert!(producer.grant().is_none());
+
+        // DMA engine and complete ISR on another thread
+        std::thread::spawn(move || {
+            let (ptr, cap) = grant.as_raw_parts();
+            assert!(cap == 8);
+            unsafe { core::ptr::copy_nonoverlapping(b"abc".as_ptr(), ptr, 3) };
+            assert!(DMA_RING.complete(grant, 3).is_ok());
+        }).join().unwrap();
+
+        assert!(consumer.reader_front().unwrap().as_slice() == b"abc");
+        assert!(consumer.pop().is_ok());
+
+        let grant = producer.grant().unwrap();
+        let grant = DMA_RING.complete(grant, 9).unwrap_err().0;
+        assert!(DMA_RING.cancel(grant).is_ok());
+        assert!(DMA_RING.is_empty());
+
+        // A stale grant is rejected once completed
+        let grant = producer.grant().unwrap();
+        let stale = DmaGrant { ptr: grant.ptr, cap: grant.cap, _ring: PhantomData };
+        assert!(DMA_RING.complete(grant, 0).is_ok());
+        assert!(DMA_RING.complete(stale, 0).unwrap_err().1 == DmaError::ForeignGrant);
+    }
+
+    // Buffer bounds of the HAL transfer APIs
+    #[cfg(feature = "embedded-dma")]
+    fn dma_rx<B: embedded_dma::WriteBuffer<Word = u8> + 'static>(mut buf: B) -> (B, usize) {
+        let (_, len) = unsafe { buf.write_buffer() };
+        (buf, len)
+    }
+
+    #[cfg(feature = "embedded-dma")]
+    fn dma_tx<B: embedded_dma::ReadBuffer<Word = u8> + 'static>(buf: B) -> (B, usize) {
+        let (_, len) = unsafe { buf.read_buffer() };
+        (buf, len)
+    }
+
+    #[cfg(feature = "embedded-dma")]
+    #[test]
+    fn embedded_dma_buffers() {
+        static RING: DmaRing<8, 2> = DmaRing::new();
+        static BIP: crate::bip::BipBuffer<16> = crate::bip::BipBuffer::new();
+
+        let (grant, len) = dma_rx(RING.split_prod().unwrap().grant().unwrap());
+        assert!(len == 8 && RING.complete(grant, 2).is_ok());
+
+        let (mut producer, mut consumer) = BIP.split().unwrap();
+        let (grant, len) = dma_rx(producer.grant(4).unwrap().into_dma());
+        assert!(len == 4 && BIP.complete_write(grant, 3).is_ok());
+        let (grant, len) = dma_tx(consumer.read().unwrap().into_dma());
+        assert!(len == 3 && BIP.complete_read(grant, 3).is_ok());
+    }
+}
@@ -0,0 +1,120 @@
+//! Bidirectional channel made of two rings.
+//!
+//! `Duplex` holds an A to B ring and a B to A ring. Each endpoint owns the
+//! producer of its outgoing ring and the consumer of its incoming one, so
+//! that a side cannot send on the wrong direction.
+
+use crate::ringbuf::{Consumer, Producer, RingBuf, SplitError};
+
+/// One side of a Duplex, sending Tx and receiving Rx
+pub struct Endpoint<'a, Tx: 'a, Rx: 'a, const NT: usize, const NR: usize> {
+    tx: Producer<'a, Tx, NT>,
+    rx: Consumer<'a, Rx, NR>,
+}
+
+impl<'a, Tx, Rx, const NT: usize, const NR: usize> Endpoint<'a, Tx, Rx, NT, NR> {
+
+    // Send to the other side, the item is handed back if the ring is full
+    #[inline(always)]
+    pub fn send(&mut self, val: Tx) -> Result<(), Tx> {
+        self.tx.try_push(val)
+    }
+
+    // Next item from the other side
+    #[inline(always)]
+    pub fn recv(&mut self) -> Option<Rx> {
+        self.rx.pop_value()
+    }
+
+    // Next item from the other side, left in the ring
+    #[inline(always)]
+    pub fn peek(&self) -> Option<&Rx> {
+        self.rx.reader_front()
+    }
+
+    /// Producer of the outgoing ring, for the rest of the ring API
+    pub fn sender(&mut self) -> &mut Producer<'a, Tx, NT> {
+        &mut self.tx
+    }
+
+    /// Consumer of the incoming ring
+    pub fn receiver(&mut self) -> &mut Consumer<'a, Rx, NR> {
+        &mut self.rx
+    }
+}
+
+/// Endpoints of side A and side B returned by Duplex::split
+pub type DuplexHandles<'a, TA, TB, const NA: usize, const NB: usize> =
+    (Endpoint<'a, TA, TB, NA, NB>, Endpoint<'a, TB, TA, NB, NA>);
+
+/// Ring of NA items TA from A to B and ring of NB items TB from B to A
+pub struct Duplex<TA, TB, const NA: usize, const NB: usize> {
+    a_to_b: RingBuf<TA, NA>,
+    b_to_a: RingBuf<TB, NB>,
+}
+
+impl<TA, TB, const NA: usize, const NB: usize> Default for Duplex<TA, TB, NA, NB> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<TA, TB, const NA: usize, const NB: usize> Duplex<TA, TB, NA, NB> {
+
+    pub const fn new() -> Self {
+        Duplex {
+            a_to_b: RingBuf::new(),
+            b_to_a: RingBuf::new(),
+        }
+    }
+
+    // Return the endpoint of side A, once in life time
+    pub fn split_a(&self) -> Result<Endpoint<'_, TA, TB, NA, NB>, SplitError> {
+        let tx = self.a_to_b.split_prod()?;
+        // Split along with the A to B ring, cannot fail
+        let rx = self.b_to_a.split_cons().unwrap();
+        Ok(Endpoint { tx, rx })
+    }
+
+    // Return the endpoint of side B, once in life time
+    pub fn split_b(&self) -> Result<Endpoint<'_, TB, TA, NB, NA>, SplitError> {
+        let rx = self.a_to_b.split_cons()?;
+        // Split along with the A to B ring, cannot fail
+        let tx = self.b_to_a.split_prod().unwrap();
+        Ok(Endpoint { tx, rx })
+    }
+
+    // Split both endpoints, see `SharedPool::split`
+    pub fn split(&self) -> Result<DuplexHandles<'_, TA, TB, NA, NB>, SplitError> {
+        if self.a_to_b.has_split_prod() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.a_to_b.has_split_cons() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_a()?, self.split_b()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_directions() {
+        let duplex: Duplex<u8, [u16; 2], 2, 3> = Duplex::new();
+        let (mut a, mut b) = duplex.split().unwrap();
+        assert!(duplex.split_b().err() == Some(SplitError::AlreadySplitCons));
+
+        assert!(a.send(1).is_ok() && a.send(2).is_ok());
+        assert!(a.send(3) == Err(3));
+        assert!(a.recv().is_none());
+        assert!(b.peek() == Some(&1));
+        assert!(b.recv() == Some(1));
+
+        assert!(b.send([4, 5]).is_ok());
+        assert!(b.sender().free_len() == 2);
+        assert!(a.recv() == Some([4, 5]));
+        assert!(a.receiver().is_empty());
+    }
+}
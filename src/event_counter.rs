@@ -0,0 +1,103 @@
+//! Single Producer Single Consumer event counter.
+//!
+//! For "N things happened" notifications where no item needs to be
+//! transported. Uses the same wrapping read and write indices as `RingBufRef`
+//! without any buffer: the producer advances the signaled index and the
+//! consumer the taken index, up to N events can be pending.
+
+use crate::ringbuf_ref::Index;
+
+pub struct EventCounter<const N: usize> {
+    // Events taken by the consumer
+    taken: Index<N>,
+    // Events signaled by the producer
+    signaled: Index<N>,
+}
+
+// Delcare this is thread safe due to the SPSC premise, each index is
+// only written by one side
+unsafe impl<const N: usize> Sync for EventCounter<N> {}
+
+impl<const N: usize> Default for EventCounter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> EventCounter<N> {
+
+    const OK: () = assert!(N > 0, "EventCounter capacity must be larger than 0!");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: EventCounter<N> = Self::new();
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = EventCounter::<N>::OK;
+        EventCounter {
+            taken: Index::new(),
+            signaled: Index::new(),
+        }
+    }
+
+    /// Number of events signaled and not taken yet
+    #[inline(always)]
+    pub fn pending(&self) -> u32 {
+        self.signaled.wrap_dist(&self.taken)
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Producer side - signal n events. Returns the number of events
+    /// accepted, less than n if the counter would exceed N pending
+    #[inline(always)]
+    pub fn signal(&self, n: u32) -> u32 {
+        let n = n.min(N as u32 - self.pending());
+        self.signaled.wrap_add(n);
+        n
+    }
+
+    /// Consumer side - take up to max pending events. Returns the number
+    /// of events taken
+    #[inline(always)]
+    pub fn take(&self, max: u32) -> u32 {
+        let n = max.min(self.pending());
+        self.taken.wrap_add(n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_counts<const N: usize>() {
+        let counter: EventCounter<N> = EventCounter::new();
+
+        // Cycle a few times through the index range
+        for _ in 0..4 * N {
+            assert!(counter.signal(1) == 1);
+            assert!(counter.signal(N as u32) == N as u32 - 1);
+            assert!(counter.pending() == N as u32);
+            assert!(counter.signal(1) == 0);
+
+            assert!(counter.take(1) == 1);
+            assert!(counter.take(u32::MAX) == N as u32 - 1);
+            assert!(counter.take(1) == 0);
+        }
+    }
+
+    #[test]
+    fn power_of_two() {
+        test_counts::<8>();
+    }
+
+    #[test]
+    fn non_power_of_two() {
+        test_counts::<5>();
+        test_counts::<1>();
+    }
+}
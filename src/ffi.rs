@@ -0,0 +1,355 @@
+//! `extern "C"` API over the `shmem` region layout.
+//!
+//! For heterogeneous parts where a core programmed in C links this crate
+//! and shares a ring with a Rust core. Capacity and element size are
+//! runtime values read from the header, so that one set of symbols serves
+//! all rings. A region created here attaches as a `SharedMemRing` of the
+//! same capacity and element size and vice versa.
+//!
+//! Items are handed over as bytes, stored right after the 24 byte header,
+//! so their alignment must not exceed 8. The C header is generated with
+//! cbindgen, see cbindgen.toml. Each side only calls its half of the API:
+//! `spsc_producer_*` on one side, `spsc_consumer_*` on the other.
+
+use core::ptr::{addr_of, null, null_mut};
+
+use crate::shmem::{SHM_MAGIC, SHM_VERSION};
+use crate::sync::{index_barrier, AtomicU32, Ordering};
+
+/// Result of the spsc_ functions, 0 on success
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpscStatus {
+    Ok = 0,
+    BufFull = -1,
+    BufEmpty = -2,
+    Misaligned = -3,
+    RegionTooSmall = -4,
+    BadMagic = -5,
+    BadVersion = -6,
+    CapacityMismatch = -7,
+    ElementSizeMismatch = -8,
+    InvalidState = -9,
+    /// Capacity 0 or beyond 2^31 - 1, or a region size beyond usize
+    InvalidArgument = -10,
+}
+
+/// Header at the start of the region, the layout of `shmem::ShmHeader`.
+/// Only the spsc_ functions may touch the indices
+#[repr(C)]
+pub struct SpscRing {
+    pub magic: u32,
+    pub version: u32,
+    pub capacity: u32,
+    pub elem_size: u32,
+    rd_idx: u32,
+    wr_idx: u32,
+}
+
+const STORAGE_OFFSET: usize = core::mem::size_of::<SpscRing>();
+
+// View of a ring without referencing the header, whose index cells are
+// written by the other side. Index helpers of `ringbuf_ref::Index` with N
+// read from the header
+struct RingView<'a> {
+    n: u32,
+    elem_size: u32,
+    rd: &'a AtomicU32,
+    wr: &'a AtomicU32,
+    base: *mut u8,
+}
+
+impl RingView<'_> {
+
+    // ring must be valid and set up, the index cells aligned u32s shared
+    // as atomics like Index does
+    #[inline(always)]
+    unsafe fn new(ring: *const SpscRing) -> Self {
+        RingView {
+            n: addr_of!((*ring).capacity).read(),
+            elem_size: addr_of!((*ring).elem_size).read(),
+            rd: &*(addr_of!((*ring).rd_idx) as *const AtomicU32),
+            wr: &*(addr_of!((*ring).wr_idx) as *const AtomicU32),
+            base: ring as *mut u8,
+        }
+    }
+
+    #[inline(always)]
+    fn get(idx: &AtomicU32) -> u32 {
+        let val = idx.load(Ordering::Acquire);
+        index_barrier();
+        val
+    }
+
+    #[inline(always)]
+    fn wrap_inc(&self, idx: &AtomicU32) {
+        let n = self.n;
+        let val = idx.load(Ordering::Relaxed).wrapping_add(1);
+        index_barrier();
+        if !n.is_power_of_two() && val > 2 * n - 1 {
+            idx.store(val.wrapping_sub(2 * n), Ordering::Release);
+        } else {
+            idx.store(val, Ordering::Release);
+        }
+    }
+
+    #[inline(always)]
+    fn dist(&self, to: u32, from: u32) -> u32 {
+        let n = self.n;
+        let raw = to.wrapping_sub(from);
+        if n.is_power_of_two() {
+            raw
+        } else if (raw as i32) < 0 {
+            raw.wrapping_add(2 * n)
+        } else if raw > 2 * n - 1 {
+            raw.wrapping_sub(2 * n)
+        } else {
+            raw
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> u32 {
+        self.dist(Self::get(self.wr), Self::get(self.rd))
+    }
+
+    // Pointer to the slot of index value idx
+    #[inline(always)]
+    unsafe fn slot(&self, idx: u32) -> *mut u8 {
+        let n = self.n;
+        let slot = if n.is_power_of_two() {
+            idx & (n - 1)
+        } else if idx > n - 1 {
+            idx - n
+        } else {
+            idx
+        };
+        self.base.add(STORAGE_OFFSET + slot as usize * self.elem_size as usize)
+    }
+}
+
+/// Bytes needed for a region of capacity items of elem_size bytes, 0 if
+/// that does not fit in a size_t
+#[no_mangle]
+pub extern "C" fn spsc_region_size(capacity: u32, elem_size: u32) -> usize {
+    (capacity as usize)
+        .checked_mul(elem_size as usize)
+        .and_then(|storage| storage.checked_add(STORAGE_OFFSET))
+        .unwrap_or(0)
+}
+
+/// Create an empty ring in the len bytes at ring, writing the header
+///
+/// # Safety
+/// ring must be valid for reads and writes of len bytes for as long as the
+/// ring is used, and not accessed by the other side before this returns.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_init(ring: *mut SpscRing, len: usize, capacity: u32, elem_size: u32) -> SpscStatus {
+    let size = spsc_region_size(capacity, elem_size);
+    if capacity == 0 || capacity > i32::MAX as u32 || size == 0 {
+        return SpscStatus::InvalidArgument;
+    }
+    if !(ring as usize).is_multiple_of(core::mem::align_of::<SpscRing>()) {
+        return SpscStatus::Misaligned;
+    }
+    if len < size {
+        return SpscStatus::RegionTooSmall;
+    }
+    ring.write(SpscRing {
+        magic: SHM_MAGIC,
+        version: SHM_VERSION,
+        capacity,
+        elem_size,
+        rd_idx: 0,
+        wr_idx: 0,
+    });
+    SpscStatus::Ok
+}
+
+/// Validate a ring created by the other side, same checks as
+/// `SharedMemRing::attach`
+///
+/// # Safety
+/// ring must be valid for reads and writes of len bytes for as long as the
+/// ring is used.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_attach(ring: *mut SpscRing, len: usize, capacity: u32, elem_size: u32) -> SpscStatus {
+    // Same bounds as spsc_init, the indices run up to 2 * capacity
+    if capacity == 0 || capacity > i32::MAX as u32 {
+        return SpscStatus::InvalidArgument;
+    }
+    if !(ring as usize).is_multiple_of(core::mem::align_of::<SpscRing>()) {
+        return SpscStatus::Misaligned;
+    }
+    if len < STORAGE_OFFSET {
+        return SpscStatus::RegionTooSmall;
+    }
+    if addr_of!((*ring).magic).read() != SHM_MAGIC {
+        return SpscStatus::BadMagic;
+    }
+    if addr_of!((*ring).version).read() != SHM_VERSION {
+        return SpscStatus::BadVersion;
+    }
+    let view = RingView::new(ring);
+    if view.n != capacity {
+        return SpscStatus::CapacityMismatch;
+    }
+    if view.elem_size != elem_size {
+        return SpscStatus::ElementSizeMismatch;
+    }
+    match spsc_region_size(capacity, elem_size) {
+        0 => return SpscStatus::InvalidArgument,
+        size if len < size => return SpscStatus::RegionTooSmall,
+        _ => {}
+    }
+    let (rd, wr) = (RingView::get(view.rd), RingView::get(view.wr));
+    let valid = |idx: u32| capacity.is_power_of_two() || idx < 2 * capacity;
+    if !valid(rd) || !valid(wr) || view.dist(wr, rd) > capacity {
+        return SpscStatus::InvalidState;
+    }
+    SpscStatus::Ok
+}
+
+/// Number of queued items
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_len(ring: *const SpscRing) -> u32 {
+    RingView::new(ring).len()
+}
+
+/// Slot at the write index to fill before spsc_producer_commit, NULL if
+/// the ring is full
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, producer only.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_producer_writer_front(ring: *mut SpscRing) -> *mut u8 {
+    let view = RingView::new(ring);
+    if view.len() == view.n {
+        return null_mut();
+    }
+    view.slot(view.wr.load(Ordering::Relaxed))
+}
+
+/// Publish the slot returned by spsc_producer_writer_front
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, producer only.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_producer_commit(ring: *mut SpscRing) -> SpscStatus {
+    let view = RingView::new(ring);
+    if view.len() == view.n {
+        return SpscStatus::BufFull;
+    }
+    view.wrap_inc(view.wr);
+    SpscStatus::Ok
+}
+
+/// Copy elem_size bytes from val in and commit them
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, producer only.
+/// val must be valid for reads of elem_size bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_producer_push(ring: *mut SpscRing, val: *const u8) -> SpscStatus {
+    let slot = spsc_producer_writer_front(ring);
+    if slot.is_null() {
+        return SpscStatus::BufFull;
+    }
+    core::ptr::copy_nonoverlapping(val, slot, addr_of!((*ring).elem_size).read() as usize);
+    spsc_producer_commit(ring)
+}
+
+/// Slot at the read index, NULL if the ring is empty
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, consumer only.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_consumer_peek(ring: *const SpscRing) -> *const u8 {
+    let view = RingView::new(ring);
+    if view.len() == 0 {
+        return null();
+    }
+    view.slot(view.rd.load(Ordering::Relaxed))
+}
+
+/// Consume the item at the read index
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, consumer only.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_consumer_pop(ring: *mut SpscRing) -> SpscStatus {
+    let view = RingView::new(ring);
+    if view.len() == 0 {
+        return SpscStatus::BufEmpty;
+    }
+    view.wrap_inc(view.rd);
+    SpscStatus::Ok
+}
+
+/// Copy the item at the read index to dst and consume it
+///
+/// # Safety
+/// ring must have been set up with spsc_init or spsc_attach, consumer only.
+/// dst must be valid for writes of elem_size bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spsc_consumer_pop_value(ring: *mut SpscRing, dst: *mut u8) -> SpscStatus {
+    let slot = spsc_consumer_peek(ring);
+    if slot.is_null() {
+        return SpscStatus::BufEmpty;
+    }
+    core::ptr::copy_nonoverlapping(slot, dst, addr_of!((*ring).elem_size).read() as usize);
+    spsc_consumer_pop(ring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shmem::SharedMemRing;
+
+    #[repr(C, align(8))]
+    struct Region([u8; 64]);
+
+    #[test]
+    fn c_side_with_rust_side() {
+        let mut region = Region([0; 64]);
+        let ring = region.0.as_mut_ptr() as *mut SpscRing;
+        assert!(spsc_region_size(5, 4) == 44);
+
+        unsafe {
+            assert!(spsc_attach(ring, 64, 5, 4) == SpscStatus::BadMagic);
+            assert!(spsc_init(ring, 40, 5, 4) == SpscStatus::RegionTooSmall);
+            assert!(spsc_init(ring, 64, 0, 4) == SpscStatus::InvalidArgument);
+            // Region size beyond usize
+            #[cfg(target_pointer_width = "32")]
+            assert!(spsc_init(ring, 64, 1 << 20, 1 << 12) == SpscStatus::InvalidArgument);
+            assert!(spsc_init(ring, 64, 5, 4) == SpscStatus::Ok);
+            assert!(spsc_attach(ring, 64, 4, 4) == SpscStatus::CapacityMismatch);
+            assert!(spsc_attach(ring, 64, 0, 4) == SpscStatus::InvalidArgument);
+            assert!(spsc_attach(ring, 64, u32::MAX, 4) == SpscStatus::InvalidArgument);
+
+            // C producer, Rust consumer through the shmem layout
+            let rust = SharedMemRing::<u32, 5>::attach(ring as *mut u8).unwrap();
+            for i in 0..12u32 {
+                let slot = spsc_producer_writer_front(ring) as *mut u32;
+                slot.write_unaligned(i);
+                assert!(spsc_producer_commit(ring) == SpscStatus::Ok);
+                assert!(rust.pop_value() == Some(i));
+            }
+
+            // Rust producer, C consumer
+            for i in 0..5u32 {
+                assert!(rust.push(i).is_ok());
+            }
+            assert!(spsc_producer_push(ring, [0u8; 4].as_ptr()) == SpscStatus::BufFull);
+            assert!(spsc_len(ring) == 5);
+            assert!((spsc_consumer_peek(ring) as *const u32).read_unaligned() == 0);
+            assert!(spsc_consumer_pop(ring) == SpscStatus::Ok);
+            let mut val = [0u8; 4];
+            assert!(spsc_consumer_pop_value(ring, val.as_mut_ptr()) == SpscStatus::Ok);
+            assert!(u32::from_ne_bytes(val) == 1);
+        }
+    }
+}
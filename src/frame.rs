@@ -0,0 +1,210 @@
+//! Length prefixed frames over a byte ring.
+//!
+//! Variable length messages are exchanged over a `RingBuf<u8, N>` without
+//! defining a max-size element type. Each frame is a little endian u16
+//! length followed by the bytes, and for the `_crc` variants a little endian
+//! CRC-32 over length and bytes. A frame is only popped once it is queued
+//! completely; the producer checks for space for the whole frame first.
+//! Both sides must agree on whether the CRC is used.
+
+use crate::backend::Backend;
+use crate::crc::{crc32, crc32_update};
+use crate::ringbuf::{Consumer, Producer};
+
+const HDR_LEN: usize = 2;
+const CRC_LEN: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// Frame can never fit in the (effective) capacity of the ring or
+    /// exceeds u16::MAX bytes. Also a received length beyond the capacity,
+    /// see `pop_frame_crc`
+    TooLarge,
+    /// Not enough room in the ring right now
+    Full,
+    /// Destination is shorter than the frame, which is left queued
+    BufTooSmall,
+    /// CRC mismatch, the frame was dropped
+    Crc,
+}
+
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            FrameError::TooLarge => "frame too large",
+            FrameError::Full => "ring full",
+            FrameError::BufTooSmall => "destination too small",
+            FrameError::Crc => "frame CRC mismatch",
+        })
+    }
+}
+
+impl core::error::Error for FrameError {}
+
+impl<'a, const N: usize, B: Backend> Producer<'a, u8, N, B> {
+
+    fn push_frame_with(&mut self, frame: &[u8], trailer: usize) -> Result<[u8; HDR_LEN], FrameError> {
+        let total = HDR_LEN + frame.len() + trailer;
+        if frame.len() > u16::MAX as usize || total > self.capacity() as usize {
+            return Err(FrameError::TooLarge);
+        }
+        // Only the producer fills the ring, the room can only grow
+        if (self.free_len() as usize) < total {
+            return Err(FrameError::Full);
+        }
+        let hdr = (frame.len() as u16).to_le_bytes();
+        self.push_slice(&hdr);
+        self.push_slice(frame);
+        Ok(hdr)
+    }
+
+    /// Queue frame as a whole
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<(), FrameError> {
+        self.push_frame_with(frame, 0).map(|_| ())
+    }
+
+    /// Queue frame followed by its CRC
+    pub fn push_frame_crc(&mut self, frame: &[u8]) -> Result<(), FrameError> {
+        let hdr = self.push_frame_with(frame, CRC_LEN)?;
+        self.push_slice(&crc32_update(crc32(&hdr), frame).to_le_bytes());
+        Ok(())
+    }
+}
+
+impl<'a, const N: usize, B: Backend> Consumer<'a, u8, N, B> {
+
+    /// Length of the frame at the head, None until it is queued completely
+    fn queued_frame(&self, trailer: usize) -> Option<usize> {
+        let mut hdr = [0; HDR_LEN];
+        if self.copy_to_slice(&mut hdr) < HDR_LEN {
+            return None;
+        }
+        let len = u16::from_le_bytes(hdr) as usize;
+        if (self.len() as usize) < HDR_LEN + len + trailer {
+            return None;
+        }
+        Some(len)
+    }
+
+    /// Length of the next frame, if queued completely
+    pub fn frame_len(&self) -> Option<usize> {
+        self.queued_frame(0)
+    }
+
+    /// Pop the next frame into dst and return its length. None if no frame
+    /// is queued completely or dst is too short, see `frame_len`
+    pub fn pop_frame(&mut self, dst: &mut [u8]) -> Option<usize> {
+        let len = self.queued_frame(0)?;
+        if dst.len() < len {
+            return None;
+        }
+        self.skip(HDR_LEN as u32);
+        self.pop_slice(&mut dst[..len]);
+        Some(len)
+    }
+
+    /// Same as `pop_frame` for frames pushed with `push_frame_crc`. A frame
+    /// failing the CRC check is dropped. A corrupted length that could never
+    /// be queued completely is rejected with TooLarge, dropping one byte to
+    /// resynchronize on the next
+    pub fn pop_frame_crc(&mut self, dst: &mut [u8]) -> Option<Result<usize, FrameError>> {
+        let mut hdr = [0; HDR_LEN];
+        let capacity = self.capacity() as usize;
+        if self.copy_to_slice(&mut hdr) == HDR_LEN && HDR_LEN + u16::from_le_bytes(hdr) as usize + CRC_LEN > capacity {
+            self.skip(1);
+            return Some(Err(FrameError::TooLarge));
+        }
+        let len = self.queued_frame(CRC_LEN)?;
+        if dst.len() < len {
+            return Some(Err(FrameError::BufTooSmall));
+        }
+        let mut crc = [0; CRC_LEN];
+        self.pop_slice(&mut hdr);
+        self.pop_slice(&mut dst[..len]);
+        self.pop_slice(&mut crc);
+        if crc32_update(crc32(&hdr), &dst[..len]) == u32::from_le_bytes(crc) {
+            Some(Ok(len))
+        } else {
+            Some(Err(FrameError::Crc))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ringbuf::RingBuf;
+
+    #[test]
+    fn frames() {
+        let ring: RingBuf<u8, 16> = RingBuf::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        let mut buf = [0; 16];
+
+        assert!(producer.push_frame(&[0; 15]) == Err(FrameError::TooLarge));
+        assert!(producer.push_frame(b"hello").is_ok());
+        assert!(producer.push_frame(b"").is_ok());
+        assert!(producer.push_frame(b"world!") == Err(FrameError::Full));
+        assert!(producer.push_frame(b"abc").is_ok());
+
+        assert!(consumer.frame_len() == Some(5));
+        assert!(consumer.pop_frame(&mut buf[..4]).is_none());
+        assert!(consumer.pop_frame(&mut buf) == Some(5));
+        assert!(buf[..5] == *b"hello");
+        assert!(consumer.pop_frame(&mut buf) == Some(0));
+        assert!(consumer.pop_frame(&mut buf) == Some(3));
+        assert!(buf[..3] == *b"abc");
+        assert!(consumer.pop_frame(&mut buf).is_none());
+
+        // Partly queued frame is not popped
+        producer.push_slice(&[4, 0, 1, 2]);
+        assert!(consumer.frame_len().is_none());
+        producer.push_slice(&[3, 4]);
+        assert!(consumer.pop_frame(&mut buf) == Some(4));
+        assert!(buf[..4] == [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn frames_crc() {
+        let ring: RingBuf<u8, 32> = RingBuf::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        let mut buf = [0; 8];
+
+        assert!(producer.push_frame_crc(b"status").is_ok());
+        assert!(consumer.pop_frame_crc(&mut buf[..2]) == Some(Err(FrameError::BufTooSmall)));
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Ok(6)));
+        assert!(buf[..6] == *b"status");
+        assert!(consumer.pop_frame_crc(&mut buf).is_none());
+
+        // Corrupted on the way
+        producer.push_slice(&[4, 0, b'd', b'a', b't', b'a', 0, 0, 0, 0]);
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Err(FrameError::Crc)));
+        assert!(consumer.is_empty());
+
+        // Stray byte read as the length, skipped up to the next frame
+        producer.push_slice(&[0x40]);
+        assert!(producer.push_frame_crc(b"ok").is_ok());
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Err(FrameError::TooLarge)));
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Ok(2)));
+        assert!(buf[..2] == *b"ok");
+    }
+
+    #[test]
+    fn frames_runtime_capacity() {
+        let ring: RingBuf<u8, 16> = RingBuf::new();
+        assert!(ring.set_capacity(8).is_ok());
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        let mut buf = [0; 16];
+
+        // Fits in N but never in the capacity
+        assert!(producer.push_frame(&[0; 7]) == Err(FrameError::TooLarge));
+        assert!(producer.push_frame_crc(&[0; 3]) == Err(FrameError::TooLarge));
+        assert!(producer.push_frame_crc(&[0; 2]).is_ok());
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Ok(2)));
+
+        // Length beyond the capacity, skipped
+        producer.push_slice(&[3, 0]);
+        assert!(consumer.pop_frame_crc(&mut buf) == Some(Err(FrameError::TooLarge)));
+    }
+}
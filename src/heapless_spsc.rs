@@ -0,0 +1,172 @@
+//! `heapless::spsc` shaped API over RingBufRef.
+//!
+//! Existing users of `heapless::spsc::Queue` can migrate by changing the
+//! import. `Queue::split` borrows the queue mutably like heapless does, so
+//! it can be split again once the handles are dropped. Unlike heapless 0.8,
+//! whose `Queue<T, N>` holds N - 1 items, all N slots are usable here.
+
+use crate::ringbuf_ref::RingBufRef;
+
+/// Statically allocatable queue of N items
+pub struct Queue<T, const N: usize> {
+    ring: RingBufRef<T, N>,
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        self.ring.clear();
+    }
+}
+
+impl<T, const N: usize> Queue<T, N> {
+
+    pub const fn new() -> Self {
+        Queue { ring: RingBufRef::new() }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ring.len() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    /// Add item at the back, handed back if full
+    #[inline]
+    pub fn enqueue(&mut self, val: T) -> Result<(), T> {
+        self.ring.try_push(val)
+    }
+
+    /// Remove the item at the front
+    #[inline]
+    pub fn dequeue(&mut self) -> Option<T> {
+        self.ring.pop_value()
+    }
+
+    /// Item at the front, left in the queue
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        self.ring.reader_front()
+    }
+
+    /// Producer and consumer borrowing the queue
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { ring: &self.ring }, Consumer { ring: &self.ring })
+    }
+}
+
+/// Enqueueing half of a split Queue
+pub struct Producer<'a, T, const N: usize> {
+    ring: &'a RingBufRef<T, N>,
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+
+    /// Room for at least one item
+    #[inline]
+    pub fn ready(&self) -> bool {
+        !self.ring.is_full()
+    }
+
+    #[inline]
+    pub fn enqueue(&mut self, val: T) -> Result<(), T> {
+        self.ring.try_push(val)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ring.len() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+/// Dequeueing half of a split Queue
+pub struct Consumer<'a, T, const N: usize> {
+    ring: &'a RingBufRef<T, N>,
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+
+    /// At least one item queued
+    #[inline]
+    pub fn ready(&self) -> bool {
+        !self.ring.is_empty()
+    }
+
+    #[inline]
+    pub fn dequeue(&mut self) -> Option<T> {
+        self.ring.pop_value()
+    }
+
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        self.ring.reader_front()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ring.len() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heapless_api() {
+        let mut queue: Queue<u32, 2> = Queue::new();
+        assert!(queue.enqueue(1).is_ok());
+        assert!(queue.peek() == Some(&1));
+        {
+            let (mut producer, mut consumer) = queue.split();
+            assert!(producer.ready() && producer.enqueue(2).is_ok());
+            assert!(!producer.ready() && producer.enqueue(3) == Err(3));
+            std::thread::scope(|s| {
+                s.spawn(move || assert!(consumer.dequeue() == Some(1)));
+            });
+        }
+        // Split again once the halves are gone
+        let (_, mut consumer) = queue.split();
+        assert!(consumer.ready() && consumer.dequeue() == Some(2));
+        assert!(queue.dequeue().is_none() && queue.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+//! Timestamped sample history for time aligned lookups.
+//!
+//! Keeps the last N (timestamp, sample) pairs, overwriting the oldest when
+//! full. Samples are recorded in time order hence the occupied region is
+//! sorted, `latest_before` binary searches it. Timestamps are wrapping u32
+//! ticks (see `clock::Clock`), ordered relative to the oldest sample. The
+//! recorded samples must span less than half the tick range.
+//!
+//! The history is owned by one context, recording takes `&mut self`.
+
+use crate::ringbuf_ref::RingBufRef;
+
+/// Linear interpolation between two samples, `num / den` of the way from a
+/// to b
+pub trait Lerp: Sized {
+    fn lerp(a: &Self, b: &Self, num: u32, den: u32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(a: &f32, b: &f32, num: u32, den: u32) -> f32 {
+        a + (b - a) * (num as f32 / den as f32)
+    }
+}
+
+impl Lerp for f64 {
+    fn lerp(a: &f64, b: &f64, num: u32, den: u32) -> f64 {
+        a + (b - a) * (num as f64 / den as f64)
+    }
+}
+
+pub struct SampleHistory<S, const N: usize> {
+    ring: RingBufRef<(u32, S), N>,
+}
+
+impl<S, const N: usize> Default for SampleHistory<S, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, const N: usize> Drop for SampleHistory<S, N> {
+    fn drop(&mut self) {
+        while self.ring.pop_value().is_some() {}
+    }
+}
+
+impl<S, const N: usize> SampleHistory<S, N> {
+
+    pub const fn new() -> Self {
+        SampleHistory { ring: RingBufRef::new() }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Record a sample taken at t, which must not be older than the last
+    /// recorded one. The oldest sample is dropped when full
+    pub fn record(&mut self, t: u32, sample: S) {
+        if self.ring.is_full() {
+            drop(self.ring.pop_value());
+        }
+        // Cannot be full after the pop above
+        let _ = self.ring.push((t, sample));
+    }
+
+    /// Most recent sample
+    pub fn latest(&self) -> Option<(u32, &S)> {
+        self.ring.peek_at(self.len().checked_sub(1)?).map(|(t, s)| (*t, s))
+    }
+
+    // Position of the last sample at or before t
+    fn search(&self, t: u32) -> Option<u32> {
+        let base = self.ring.peek_at(0)?.0;
+        let key = t.wrapping_sub(base);
+        // Half the tick range behind the oldest sample is in the past
+        if key > i32::MAX as u32 {
+            return None;
+        }
+        // Samples are ordered by their distance to the oldest, find the
+        // first one after key
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.ring.peek_at(mid).unwrap().0.wrapping_sub(base) <= key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo.checked_sub(1)
+    }
+
+    /// Latest sample taken at or before t
+    pub fn latest_before(&self, t: u32) -> Option<(u32, &S)> {
+        let pos = self.search(t)?;
+        self.ring.peek_at(pos).map(|(ts, s)| (*ts, s))
+    }
+
+    /// Sample at t, linearly interpolated between the samples around it.
+    /// None if t is before the oldest or after the latest sample
+    pub fn interpolate(&self, t: u32) -> Option<S>
+    where
+        S: Lerp + Clone,
+    {
+        let pos = self.search(t)?;
+        let (t0, s0) = self.ring.peek_at(pos)?;
+        if *t0 == t {
+            return Some(s0.clone());
+        }
+        let (t1, s1) = self.ring.peek_at(pos + 1)?;
+        Some(S::lerp(s0, s1, t.wrapping_sub(*t0), t1.wrapping_sub(*t0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_and_interpolate() {
+        let mut history: SampleHistory<f32, 4> = SampleHistory::new();
+        assert!(history.latest_before(10).is_none());
+
+        // Timestamps wrap across u32::MAX
+        let t0 = u32::MAX - 15;
+        for i in 0..6u32 {
+            history.record(t0.wrapping_add(i * 10), i as f32);
+        }
+        // Only the last 4 samples (2..5) are kept
+        assert!(history.len() == 4);
+        assert!(history.latest().unwrap().1 == &5.0);
+        assert!(history.latest_before(t0.wrapping_add(19)).is_none());
+        assert!(history.latest_before(t0.wrapping_add(20)).unwrap().1 == &2.0);
+        assert!(history.latest_before(t0.wrapping_add(35)).unwrap().1 == &3.0);
+        assert!(history.latest_before(t0.wrapping_add(500)).unwrap().1 == &5.0);
+
+        assert!(history.interpolate(t0.wrapping_add(35)).unwrap() == 3.5);
+        assert!(history.interpolate(t0.wrapping_add(50)).unwrap() == 5.0);
+        assert!(history.interpolate(t0.wrapping_add(51)).is_none());
+    }
+
+    #[test]
+    fn drops_evicted() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        let mut history: SampleHistory<Rc<()>, 2> = SampleHistory::new();
+        for t in 0..5 {
+            history.record(t, tracker.clone());
+        }
+        assert!(Rc::strong_count(&tracker) == 3);
+        drop(history);
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+}
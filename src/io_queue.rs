@@ -0,0 +1,221 @@
+//! Submission/completion queue pair with tag matching.
+//!
+//! The initiator submits requests tagged from a fixed table of N tags, each
+//! holding a caller context until completion. The device consumes the
+//! submissions and posts completions carrying the same tag, in any order.
+//! Reaping a completion hands back the context of the matching submission
+//! and frees its tag.
+//!
+//! At most N requests are outstanding hence both rings of capacity N never
+//! overflow once a tag was obtained.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use crate::ringbuf_ref::RingBufRef;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IoQueueError {
+    /// All N tags are outstanding
+    NoFreeTag,
+    /// Completion tag does not match an outstanding submission
+    UnknownTag,
+    /// Handle already split
+    AlreadySplit,
+}
+
+/// Tag of an outstanding submission
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tag(u32);
+
+impl Tag {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+pub struct IoQueue<S, C, U, const N: usize> {
+    sq: RingBufRef<(Tag, S), N>,
+    cq: RingBufRef<(Tag, C), N>,
+    // Caller context per tag, only accessed by the initiator
+    contexts: [UnsafeCell<MaybeUninit<U>>; N],
+    // Outstanding tags, only accessed by the initiator
+    outstanding: Cell<u32>,
+    has_split_initiator: Cell<bool>,
+    has_split_device: Cell<bool>,
+}
+
+// Delcare this is thread safe due to the SPSC premise on both rings. The tag
+// table is private to the initiator.
+unsafe impl<S: Send, C: Send, U: Send, const N: usize> Sync for IoQueue<S, C, U, N> {}
+
+/// Submitting and reaping side, once in life time
+pub struct Initiator<'a, S, C, U, const N: usize> {
+    inner: &'a IoQueue<S, C, U, N>,
+}
+
+/// Serving side, once in life time
+pub struct Device<'a, S, C, U, const N: usize> {
+    inner: &'a IoQueue<S, C, U, N>,
+}
+
+/// Initiator and Device pair returned by IoQueue::split
+pub type IoQueueHandles<'a, S, C, U, const N: usize> = (Initiator<'a, S, C, U, N>, Device<'a, S, C, U, N>);
+
+impl<S, C, U, const N: usize> Default for IoQueue<S, C, U, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, C, U, const N: usize> IoQueue<S, C, U, N> {
+
+    const OK: () = assert!(N > 0 && N <= 32, "IoQueue supports 1 to 32 tags");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_CONTEXT: UnsafeCell<MaybeUninit<U>> = UnsafeCell::new(MaybeUninit::uninit());
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: IoQueue<S, C, U, N> = Self::new();
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = IoQueue::<S, C, U, N>::OK;
+        IoQueue {
+            sq: RingBufRef::new(),
+            cq: RingBufRef::new(),
+            contexts: [Self::INIT_CONTEXT; N],
+            outstanding: Cell::new(0),
+            has_split_initiator: Cell::new(false),
+            has_split_device: Cell::new(false),
+        }
+    }
+
+    pub fn split_initiator(&self) -> Result<Initiator<'_, S, C, U, N>, IoQueueError> {
+        if self.has_split_initiator.get() {
+            Err(IoQueueError::AlreadySplit)
+        } else {
+            self.has_split_initiator.set(true);
+            Ok(Initiator { inner: self })
+        }
+    }
+
+    pub fn split_device(&self) -> Result<Device<'_, S, C, U, N>, IoQueueError> {
+        if self.has_split_device.get() {
+            Err(IoQueueError::AlreadySplit)
+        } else {
+            self.has_split_device.set(true);
+            Ok(Device { inner: self })
+        }
+    }
+
+    pub fn split(&self) -> Result<IoQueueHandles<'_, S, C, U, N>, IoQueueError> {
+        Ok((self.split_initiator()?, self.split_device()?))
+    }
+}
+
+impl<'a, S, C, U, const N: usize> Initiator<'a, S, C, U, N> {
+
+    /// Number of submissions not reaped yet
+    pub fn outstanding(&self) -> u32 {
+        self.inner.outstanding.get().count_ones()
+    }
+
+    /// Submit a request, ctx is handed back when its completion is reaped
+    pub fn submit(&mut self, req: S, ctx: U) -> Result<Tag, IoQueueError> {
+        let taken = self.inner.outstanding.get();
+        let free = !taken & (u32::MAX >> (32 - N));
+        if free == 0 {
+            return Err(IoQueueError::NoFreeTag);
+        }
+        let tag = Tag(free.trailing_zeros());
+        unsafe { (*self.inner.contexts[tag.index()].get()).write(ctx) };
+        self.inner.outstanding.set(taken | 1 << tag.0);
+        // Cannot be full with a free tag
+        let _ = self.inner.sq.push((tag, req));
+        Ok(tag)
+    }
+
+    /// Reap the oldest completion, returning the context of its submission.
+    /// The completion is dropped if its tag is not outstanding
+    pub fn reap(&mut self) -> Option<Result<(U, C), IoQueueError>> {
+        let (tag, cqe) = unsafe { core::ptr::read(self.inner.cq.reader_front()?) };
+        // Checked non-empty above
+        let _ = self.inner.cq.pop();
+
+        let taken = self.inner.outstanding.get();
+        if tag.index() >= N || taken & (1 << tag.0) == 0 {
+            return Some(Err(IoQueueError::UnknownTag));
+        }
+        self.inner.outstanding.set(taken & !(1 << tag.0));
+        let ctx = unsafe { (*self.inner.contexts[tag.index()].get()).assume_init_read() };
+        Some(Ok((ctx, cqe)))
+    }
+}
+
+impl<'a, S, C, U, const N: usize> Device<'a, S, C, U, N> {
+
+    /// Take the next submitted request
+    pub fn next_request(&mut self) -> Option<(Tag, S)> {
+        let sqe = unsafe { core::ptr::read(self.inner.sq.reader_front()?) };
+        // Checked non-empty above
+        let _ = self.inner.sq.pop();
+        Some(sqe)
+    }
+
+    /// Post the completion of the request with the given tag. Completions
+    /// may be posted in any order. Only fails, handing cqe back, when more
+    /// completions are posted than requests were taken
+    pub fn complete(&mut self, tag: Tag, cqe: C) -> Result<(), C> {
+        match self.inner.cq.alloc_uninit() {
+            Some(loc) => {
+                loc.write((tag, cqe));
+                // Checked not full above
+                let _ = self.inner.cq.commit();
+                Ok(())
+            }
+            None => Err(cqe),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_order_completion() {
+        let queue: IoQueue<u32, i32, &str, 2> = IoQueue::new();
+        let (mut initiator, mut device) = queue.split().unwrap();
+        assert!(queue.split_device().is_err());
+
+        let t0 = initiator.submit(100, "read").unwrap();
+        let t1 = initiator.submit(200, "write").unwrap();
+        assert!(initiator.submit(300, "flush") == Err(IoQueueError::NoFreeTag));
+        assert!(initiator.outstanding() == 2);
+
+        assert!(device.next_request() == Some((t0, 100)));
+        assert!(device.next_request() == Some((t1, 200)));
+        assert!(device.next_request().is_none());
+
+        // Complete the second request first
+        assert!(device.complete(t1, -2).is_ok());
+        assert!(initiator.reap() == Some(Ok(("write", -2))));
+
+        // Freed tag is reused
+        let t2 = initiator.submit(300, "flush").unwrap();
+        assert!(t2 == t1);
+        assert!(device.complete(t0, -1).is_ok());
+        assert!(device.next_request() == Some((t2, 300)));
+        assert!(device.complete(t2, -3).is_ok());
+        assert!(device.complete(t2, -4).is_err());
+
+        assert!(initiator.reap() == Some(Ok(("read", -1))));
+        assert!(initiator.reap() == Some(Ok(("flush", -3))));
+        assert!(initiator.reap().is_none());
+        assert!(initiator.outstanding() == 0);
+
+        // Completion of a tag that is not outstanding
+        assert!(device.complete(t0, -5).is_ok());
+        assert!(initiator.reap() == Some(Err(IoQueueError::UnknownTag)));
+    }
+}
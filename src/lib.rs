@@ -1,6 +1,103 @@
-// Include std only in cargo test
-#![cfg_attr(not(test), no_std)]
+// Include std only in cargo test or with the std feature
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+// Crate internal helpers of the index core are unused in the loom build
+#![cfg_attr(loom, allow(dead_code))]
+// Only the index core is built for the loom model tests, see tests/loom.rs
 pub mod ringbuf_ref;
+mod sync;
+#[cfg(not(loom))]
 pub mod shared_singleton;
+#[cfg(not(loom))]
+pub mod mailbox;
+#[cfg(not(loom))]
+pub mod triple_buffer;
+#[cfg(not(loom))]
+pub mod ping_pong;
+#[cfg(not(loom))]
+pub mod seqlock;
+#[cfg(not(loom))]
 pub mod ringbuf;
+#[cfg(not(loom))]
 pub mod shared_pool;
+#[cfg(not(loom))]
+pub mod pool_alloc;
+#[cfg(not(loom))]
+pub mod pool;
+#[cfg(not(loom))]
+pub mod multi_pool;
+#[cfg(not(loom))]
+pub mod ordered_pool;
+#[cfg(not(loom))]
+pub mod duplex;
+#[cfg(not(loom))]
+pub mod heapless_spsc;
+#[cfg(all(not(loom), feature = "alloc"))]
+pub mod boxed;
+#[cfg(not(loom))]
+pub mod request;
+#[cfg(not(loom))]
+pub mod select;
+#[cfg(not(loom))]
+pub mod seq_ring;
+#[cfg(not(loom))]
+pub mod backend;
+#[cfg(not(loom))]
+pub mod versioned;
+#[cfg(not(loom))]
+pub mod config_cell;
+#[cfg(not(loom))]
+pub mod clock;
+#[cfg(not(loom))]
+pub mod notify;
+#[cfg(not(loom))]
+pub mod router;
+#[cfg(not(loom))]
+pub mod event_counter;
+#[cfg(not(loom))]
+pub mod history;
+#[cfg(not(loom))]
+pub mod io_queue;
+#[cfg(not(loom))]
+pub mod dma;
+#[cfg(not(loom))]
+pub mod bip;
+#[cfg(not(loom))]
+pub mod overwrite;
+#[cfg(not(loom))]
+pub mod policy;
+#[cfg(not(loom))]
+pub mod mpsc;
+#[cfg(not(loom))]
+pub mod broadcast;
+#[cfg(not(loom))]
+pub mod mpmc;
+#[cfg(not(loom))]
+pub mod wait;
+#[cfg(not(loom))]
+pub mod view;
+#[cfg(not(loom))]
+pub mod shmem;
+#[cfg(all(not(loom), feature = "ffi"))]
+pub mod ffi;
+#[cfg(all(not(loom), feature = "stats"))]
+pub mod stats;
+#[cfg(all(not(loom), feature = "async"))]
+pub mod async_ring;
+#[cfg(all(not(loom), feature = "async"))]
+pub mod channel;
+#[cfg(all(not(loom), any(feature = "embedded-io", feature = "std")))]
+pub mod bytes;
+#[cfg(not(loom))]
+pub mod crc;
+#[cfg(not(loom))]
+pub mod frame;
+#[cfg(not(loom))]
+pub mod timestamped;
+#[cfg(not(loom))]
+pub mod checked;
+#[cfg(all(not(loom), feature = "log"))]
+pub mod ringlog;
+#[cfg(not(loom))]
+pub mod trace;
+#[cfg(not(loom))]
+pub mod static_ring;
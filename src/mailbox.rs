@@ -0,0 +1,194 @@
+//! Latest-value mailbox, the overwriting counterpart of `SharedSingleton`.
+//!
+//! The writer may post at any time, replacing a value the reader has not
+//! taken yet, and the reader always takes the most recent one. Three slots
+//! are kept: the writer fills its own spare slot and swaps it with the
+//! middle one, the reader swaps its emptied slot with the middle one when a
+//! new value is flagged. Hence neither side ever waits for the other.
+//!
+//! The swaps use atomic read-modify-write, not available on targets without
+//! CAS such as thumbv6m unless the `critical-section` feature is on.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use crate::sync::{AtomicRmw, AtomicU8, Ordering};
+
+// Set in middle when its slot holds a value not taken yet
+const NEW: u8 = 0b100;
+const SLOT_MASK: u8 = 0b011;
+
+pub struct Mailbox<T> {
+    slots: [UnsafeCell<MaybeUninit<T>>; 3],
+    // Slot in the middle and the NEW flag
+    middle: AtomicU8,
+    has_split: Cell<bool>,
+}
+
+// Values are moved from the writer to the reader context
+unsafe impl<T: Send> Sync for Mailbox<T> {}
+
+/// Writer handle, once in life time
+pub struct MailboxWriter<'a, T> {
+    inner: &'a Mailbox<T>,
+    // Slot owned by the writer, always empty
+    back: u8,
+}
+
+/// Reader handle, once in life time
+pub struct MailboxReader<'a, T> {
+    inner: &'a Mailbox<T>,
+    // Slot owned by the reader, always empty
+    front: u8,
+}
+
+impl<T> Default for Mailbox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Mailbox<T> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_U: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+
+    pub const fn new() -> Self {
+        Mailbox {
+            slots: [Self::INIT_U; 3],
+            middle: AtomicU8::new(0),
+            has_split: Cell::new(false),
+        }
+    }
+
+    /// Return the writer and reader, once in life time
+    pub fn split(&self) -> Option<(MailboxWriter<'_, T>, MailboxReader<'_, T>)> {
+        if self.has_split.get() {
+            None
+        } else {
+            self.has_split.set(true);
+            Some((MailboxWriter { inner: self, back: 1 }, MailboxReader { inner: self, front: 2 }))
+        }
+    }
+
+    /// A value was posted and not taken yet
+    #[inline]
+    pub fn has_new(&self) -> bool {
+        self.middle.load(Ordering::Acquire) & NEW != 0
+    }
+
+    #[inline(always)]
+    fn slot(&self, idx: u8) -> *mut T {
+        self.slots[idx as usize].get() as *mut T
+    }
+}
+
+impl<T> Drop for Mailbox<T> {
+    fn drop(&mut self) {
+        let middle = *self.middle.get_mut();
+        if middle & NEW != 0 {
+            unsafe { self.slot(middle & SLOT_MASK).drop_in_place() };
+        }
+    }
+}
+
+impl<'a, T> MailboxWriter<'a, T> {
+
+    /// Post val, dropping the previous value if not taken yet
+    pub fn post(&mut self, val: T) {
+        let mbox = self.inner;
+        unsafe { mbox.slot(self.back).write(val) };
+        let old = mbox.middle.rmw_swap(self.back | NEW, Ordering::AcqRel);
+        self.back = old & SLOT_MASK;
+        if old & NEW != 0 {
+            // Overwritten before the reader got to it
+            unsafe { mbox.slot(self.back).drop_in_place() };
+        }
+    }
+}
+
+impl<'a, T> MailboxReader<'a, T> {
+
+    /// Take the latest value if one was posted since the last take
+    pub fn take(&mut self) -> Option<T> {
+        let mbox = self.inner;
+        if !mbox.has_new() {
+            return None;
+        }
+        // Only the writer sets NEW, still set at the swap
+        let old = mbox.middle.rmw_swap(self.front, Ordering::AcqRel);
+        self.front = old & SLOT_MASK;
+        Some(unsafe { mbox.slot(self.front).read() })
+    }
+
+    #[inline]
+    pub fn has_new(&self) -> bool {
+        self.inner.has_new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SENSOR: Mailbox<(u32, u32)> = Mailbox::new();
+
+    #[test]
+    fn latest_value() {
+        let (mut writer, mut reader) = SENSOR.split().unwrap();
+        assert!(SENSOR.split().is_none());
+        assert!(reader.take().is_none());
+
+        writer.post((1, 1));
+        assert!(reader.take() == Some((1, 1)));
+        assert!(reader.take().is_none());
+
+        for i in 2..10 {
+            writer.post((i, i * 2));
+        }
+        assert!(reader.has_new());
+        assert!(reader.take() == Some((9, 18)));
+        assert!(!reader.has_new());
+    }
+
+    #[test]
+    fn drops_values() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        {
+            let mbox = Mailbox::new();
+            let (mut writer, mut reader) = mbox.split().unwrap();
+            writer.post(tracker.clone());
+            writer.post(tracker.clone());
+            // Overwritten value is dropped
+            assert!(Rc::strong_count(&tracker) == 2);
+            drop(reader.take());
+            writer.post(tracker.clone());
+            assert!(Rc::strong_count(&tracker) == 2);
+        }
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+
+    #[test]
+    fn threads() {
+        static MBOX: Mailbox<[u32; 8]> = Mailbox::new();
+        let (mut writer, mut reader) = MBOX.split().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            for i in 1..=10000 {
+                writer.post([i; 8]);
+            }
+        });
+        let mut last = 0;
+        while last != 10000 {
+            if let Some(val) = reader.take() {
+                // Never torn, never older than the last one taken
+                assert!(val.iter().all(|v| *v == val[0]));
+                assert!(val[0] > last);
+                last = val[0];
+            }
+        }
+        handle.join().unwrap();
+    }
+}
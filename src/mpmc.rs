@@ -0,0 +1,209 @@
+//! Bounded multi producer multi consumer queue.
+//!
+//! Vyukov's bounded queue: both sides claim their position with a
+//! compare-exchange and hand each slot over with its sequence stamp. No
+//! handles are split, any context pushes and pops through a shared
+//! reference. Use it where the SPSC premise cannot be kept; the ring
+//! buffers of this crate are cheaper otherwise.
+//!
+//! A context preempted between claim and hand over holds back the other
+//! side at that slot until it resumes. N must be a power of two so that
+//! the positions wrap over the full u32 range. Compare-exchange is not
+//! available on thumbv6m without the `critical-section` feature.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use crate::ringbuf_ref::CachePadded;
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+struct Slot<T> {
+    // Position when vacant for the producer claiming it, position + 1 once
+    // written for the consumer claiming it
+    seq: AtomicU32,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct MpmcQueue<T, const N: usize> {
+    wr_pos: CachePadded<AtomicU32>,
+    rd_pos: CachePadded<AtomicU32>,
+    slots: [Slot<T>; N],
+}
+
+// Each slot is owned by the context that claimed its position until handed
+// over by a release store of its stamp
+unsafe impl<T: Send, const N: usize> Sync for MpmcQueue<T, N> {}
+
+impl<T, const N: usize> Default for MpmcQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> MpmcQueue<T, N> {
+
+    const OK: () = assert!(N.is_power_of_two() && N < (1 << 31), "MPMC queue capacity must be a power of two");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_SLOT: Slot<T> = Slot {
+        seq: AtomicU32::new(0),
+        val: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = MpmcQueue::<T, N>::OK;
+
+        // Slot i is vacant for position i
+        let mut slots = [Self::INIT_SLOT; N];
+        let mut i = 0;
+        while i < N {
+            slots[i].seq = AtomicU32::new(i as u32);
+            i += 1;
+        }
+        MpmcQueue {
+            wr_pos: CachePadded(AtomicU32::new(0)),
+            rd_pos: CachePadded(AtomicU32::new(0)),
+            slots,
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, pos: u32) -> &Slot<T> {
+        &self.slots[pos as usize & (N - 1)]
+    }
+
+    /// Claimed items not popped yet, snapshot only
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        let wr = self.wr_pos.load(Ordering::Relaxed);
+        let rd = self.rd_pos.load(Ordering::Relaxed);
+        // A pop may claim between the two loads
+        (wr.wrapping_sub(rd) as i32).max(0) as u32
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    // Claim the next position of cursor whose slot stamp is at offset from
+    // the position, None if the slot is still held by the other side
+    #[inline(always)]
+    fn claim(&self, cursor: &AtomicU32, offset: u32) -> Option<u32> {
+        let mut pos = cursor.load(Ordering::Relaxed);
+        loop {
+            let seq = self.slot(pos).seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos.wrapping_add(offset)) as i32;
+            if diff == 0 {
+                match cursor.rmw_compare_exchange(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => return Some(pos),
+                    Err(cur) => pos = cur,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                // Claimed by another context of the same side meanwhile
+                pos = cursor.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Push from any context, handing val back when full
+    pub fn push(&self, val: T) -> Result<(), T> {
+        match self.claim(&self.wr_pos, 0) {
+            Some(pos) => {
+                let slot = self.slot(pos);
+                unsafe { (*slot.val.get()).write(val) };
+                slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                Ok(())
+            }
+            None => Err(val),
+        }
+    }
+
+    /// Pop from any context
+    pub fn pop(&self) -> Option<T> {
+        let pos = self.claim(&self.rd_pos, 1)?;
+        let slot = self.slot(pos);
+        let val = unsafe { (*slot.val.get()).assume_init_read() };
+        // Vacant for the producer one lap later
+        slot.seq.store(pos.wrapping_add(N as u32), Ordering::Release);
+        Some(val)
+    }
+}
+
+impl<T, const N: usize> Drop for MpmcQueue<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static QUEUE: MpmcQueue<u32, 2> = MpmcQueue::new();
+
+    #[test]
+    fn push_pop() {
+        assert!(QUEUE.pop().is_none());
+        for lap in 0..3 {
+            assert!(QUEUE.push(lap).is_ok());
+            assert!(QUEUE.push(lap + 10).is_ok());
+            assert!(QUEUE.push(99) == Err(99));
+            assert!(QUEUE.len() == 2);
+            assert!(QUEUE.pop() == Some(lap));
+            assert!(QUEUE.pop() == Some(lap + 10));
+            assert!(QUEUE.is_empty());
+        }
+    }
+
+    #[test]
+    fn threads() {
+        use std::sync::atomic::AtomicU64;
+        use std::sync::Arc;
+
+        let queue: Arc<MpmcQueue<u32, 8>> = Arc::new(MpmcQueue::new());
+        let sum = Arc::new(AtomicU64::new(0));
+        let producers: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for i in 1..=1000 {
+                        while queue.push(i).is_err() {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let (queue, sum) = (queue.clone(), sum.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        loop {
+                            if let Some(val) = queue.pop() {
+                                sum.fetch_add(val as u64, Ordering::Relaxed);
+                                break;
+                            }
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in producers.into_iter().chain(consumers) {
+            h.join().unwrap();
+        }
+        // Every item popped exactly once
+        assert!(sum.load(Ordering::Relaxed) == 2 * 500500);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! Multi producer single consumer ring buffer.
+//!
+//! Any number of contexts (ISRs, threads) push through a shared reference,
+//! the single consumer pops through its handle. A producer claims the
+//! write position with a compare-exchange and publishes the slot with its
+//! per-slot sequence stamp once the item is written, so that claiming and
+//! writing need no lock. The consumer takes items in claim order: a
+//! producer preempted between claim and publish holds back the items
+//! claimed after it until it resumes.
+//!
+//! N must be a power of two so that the positions wrap over the full u32
+//! range. Compare-exchange is not available on thumbv6m
+//! without the `critical-section` feature.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use crate::ringbuf_ref::CachePadded;
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+struct Slot<T> {
+    // Position when vacant for the producer claiming it, position + 1 once
+    // published for the consumer
+    seq: AtomicU32,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct MpscRingBuf<T, const N: usize> {
+    // Next position to claim, shared by the producers
+    wr_pos: CachePadded<AtomicU32>,
+    // Consumer position, only written by the consumer
+    rd_pos: CachePadded<AtomicU32>,
+    slots: [Slot<T>; N],
+    has_split_cons: Cell<bool>,
+}
+
+// Each slot is handed over by a release store of its stamp, the write
+// position only by compare-exchange
+unsafe impl<T: Send, const N: usize> Sync for MpscRingBuf<T, N> {}
+
+/// Consumer handle, once in life time
+pub struct MpscConsumer<'a, T, const N: usize> {
+    inner: &'a MpscRingBuf<T, N>,
+}
+
+impl<T, const N: usize> Default for MpscRingBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> MpscRingBuf<T, N> {
+
+    const OK: () = assert!(N.is_power_of_two() && N < (1 << 31), "MPSC ringbuf capacity must be a power of two");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_SLOT: Slot<T> = Slot {
+        seq: AtomicU32::new(0),
+        val: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = MpscRingBuf::<T, N>::OK;
+
+        // Slot i is vacant for position i
+        let mut slots = [Self::INIT_SLOT; N];
+        let mut i = 0;
+        while i < N {
+            slots[i].seq = AtomicU32::new(i as u32);
+            i += 1;
+        }
+        MpscRingBuf {
+            wr_pos: CachePadded(AtomicU32::new(0)),
+            rd_pos: CachePadded(AtomicU32::new(0)),
+            slots,
+            has_split_cons: Cell::new(false),
+        }
+    }
+
+    /// Return the consumer, once in life time
+    pub fn split_cons(&self) -> Option<MpscConsumer<'_, T, N>> {
+        if self.has_split_cons.get() {
+            None
+        } else {
+            self.has_split_cons.set(true);
+            Some(MpscConsumer { inner: self })
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, pos: u32) -> &Slot<T> {
+        &self.slots[pos as usize & (N - 1)]
+    }
+
+    /// Claimed items not popped yet, snapshot only
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.wr_pos.load(Ordering::Relaxed).wrapping_sub(self.rd_pos.load(Ordering::Relaxed))
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Push from any producer context, handing val back when full
+    pub fn push(&self, val: T) -> Result<(), T> {
+        let mut pos = self.wr_pos.load(Ordering::Relaxed);
+        loop {
+            let seq = self.slot(pos).seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos) as i32;
+            if diff == 0 {
+                // Vacant, claim it unless another producer got there first
+                match self.wr_pos.rmw_compare_exchange(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => break,
+                    Err(cur) => pos = cur,
+                }
+            } else if diff < 0 {
+                // Still holding the item of the previous lap
+                return Err(val);
+            } else {
+                // Claimed by another producer meanwhile
+                pos = self.wr_pos.load(Ordering::Relaxed);
+            }
+        }
+        let slot = self.slot(pos);
+        unsafe { (*slot.val.get()).write(val) };
+        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    // Consumer side, through the handle or on drop
+    #[inline]
+    fn pop_value(&self) -> Option<T> {
+        let pos = self.rd_pos.load(Ordering::Relaxed);
+        let slot = self.slot(pos);
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        let val = unsafe { (*slot.val.get()).assume_init_read() };
+        // Vacant for the producer one lap later
+        slot.seq.store(pos.wrapping_add(N as u32), Ordering::Release);
+        self.rd_pos.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(val)
+    }
+}
+
+impl<T, const N: usize> Drop for MpscRingBuf<T, N> {
+    fn drop(&mut self) {
+        // No producer left, every claimed item is published
+        while self.pop_value().is_some() {}
+    }
+}
+
+impl<'a, T, const N: usize> MpscConsumer<'a, T, N> {
+
+    /// Next item in claim order if published
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        let ring = self.inner;
+        let pos = ring.rd_pos.load(Ordering::Relaxed);
+        let slot = ring.slot(pos);
+        if slot.seq.load(Ordering::Acquire) == pos.wrapping_add(1) {
+            Some(unsafe { (*slot.val.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        self.inner.pop_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static QUEUE: MpscRingBuf<u32, 4> = MpscRingBuf::new();
+
+    #[test]
+    fn push_pop() {
+        let mut consumer = QUEUE.split_cons().unwrap();
+        assert!(QUEUE.split_cons().is_none());
+        assert!(consumer.pop().is_none());
+
+        for lap in 0..3 {
+            for i in 0..4 {
+                assert!(QUEUE.push(lap * 4 + i).is_ok());
+            }
+            assert!(QUEUE.push(99) == Err(99));
+            assert!(QUEUE.len() == 4);
+            assert!(consumer.peek() == Some(&(lap * 4)));
+            for i in 0..4 {
+                assert!(consumer.pop() == Some(lap * 4 + i));
+            }
+            assert!(QUEUE.is_empty());
+        }
+    }
+
+    #[test]
+    fn drops_remaining() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        let queue: MpscRingBuf<Rc<()>, 4> = MpscRingBuf::new();
+        for _ in 0..3 {
+            assert!(queue.push(tracker.clone()).is_ok());
+        }
+        drop(queue.split_cons().unwrap().pop());
+        drop(queue);
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+
+    #[test]
+    fn threads() {
+        use std::sync::Arc;
+
+        let queue: Arc<MpscRingBuf<(u32, u32), 8>> = Arc::new(MpscRingBuf::new());
+        let producers: Vec<_> = (0..3)
+            .map(|id| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        while queue.push((id, i)).is_err() {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut consumer = queue.split_cons().unwrap();
+        let mut next = [0; 3];
+        while next.iter().any(|n| *n < 1000) {
+            match consumer.pop() {
+                Some((id, i)) => {
+                    // In order per producer
+                    assert!(next[id as usize] == i);
+                    next[id as usize] += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        for p in producers {
+            p.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Command queue with several payload pools of different types attached.
+//!
+//! Each `PayloadPool` carries its own id, and messages record which pool
+//! their index refers to as a `PoolRef`. A queue of audio buffers and
+//! control blocks can then share one command ring without a union payload.
+//! The pools are split on their own and the halves passed to the command
+//! queue Producer and Consumer calls that need them.
+
+use core::cell::Cell;
+
+use crate::pool_alloc::{PoolAlloc, RingAlloc};
+use crate::ringbuf::{Consumer as RingBufConsumer, Producer as RingBufProducer, RingBuf};
+use crate::shared_pool::{PoolIndex, SharedPoolError};
+use crate::shared_singleton::SharedSingleton;
+
+/// Pool id and index within that pool, carried by the messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PoolRef {
+    pub(crate) pool: u8,
+    pub(crate) idx: u32,
+}
+
+// No payload
+impl Default for PoolRef {
+    fn default() -> Self {
+        PoolRef::NONE
+    }
+}
+
+impl PoolRef {
+    /// Message carries no payload
+    pub const NONE: PoolRef = PoolRef { pool: u8::MAX, idx: u32::MAX };
+
+    pub fn is_none(&self) -> bool {
+        self.pool == u8::MAX
+    }
+
+    /// Id of the pool referenced, None if there is no payload
+    pub fn pool_id(&self) -> Option<u8> {
+        if self.is_none() {
+            None
+        } else {
+            Some(self.pool)
+        }
+    }
+}
+
+/// Implemented by the messages carrying a pool reference. Staged in place
+/// like `HasPoolIdx` messages, from `Default`
+pub trait HasPoolRef: Default {
+    fn get_pool_ref(&self) -> PoolRef;
+    fn set_pool_ref(&mut self, pref: PoolRef);
+}
+
+/// Producer half of a PayloadPool, only takes free locations
+pub struct PoolProducer<'a, T, const N: usize, A: PoolAlloc<N> = RingAlloc<PoolIndex<N>, N>> {
+    id: u8,
+    pool_alloc: &'a A,
+    pool_ref: &'a [SharedSingleton<T>; N],
+}
+
+impl<'a, T, const N: usize, A: PoolAlloc<N>> PoolProducer<'a, T, N, A> {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn num_free(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+}
+
+/// Consumer half of a PayloadPool, only gives locations back
+pub struct PoolConsumer<'a, T, const N: usize, A: PoolAlloc<N> = RingAlloc<PoolIndex<N>, N>> {
+    id: u8,
+    pool_alloc: &'a A,
+    pool_ref: &'a [SharedSingleton<T>; N],
+}
+
+impl<'a, T, const N: usize, A: PoolAlloc<N>> PoolConsumer<'a, T, N, A> {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Payload referenced by pref, None if pref points to another pool
+    pub fn read_pool_item(&self, pref: PoolRef) -> Option<&'a SharedSingleton<T>> {
+        if pref.pool == self.id && pref.idx < N as u32 {
+            Some(&self.pool_ref[pref.idx as usize])
+        } else {
+            None
+        }
+    }
+
+    // Return a payload location back to the producer half
+    pub fn return_payload(&mut self, pref: PoolRef) -> Result<(), SharedPoolError> {
+        // Must be a valid location of this pool, released with read_done
+        let payload = self.read_pool_item(pref).ok_or(SharedPoolError::InvalidPoolIndex)?;
+        if !payload.is_vacant() {
+            return Err(SharedPoolError::PayloadNotVacant);
+        }
+
+        self.pool_alloc.give(PoolIndex(pref.idx))
+    }
+}
+
+/// Pool of N payloads T identified by id. The free locations are tracked
+/// by the allocator backend A, same as `SharedPool`.
+pub struct PayloadPool<T, const N: usize, A: PoolAlloc<N> = RingAlloc<PoolIndex<N>, N>> {
+    id: u8,
+    has_split: Cell<bool>,
+    pool_alloc: A,
+    pool: [SharedSingleton<T>; N],
+}
+
+// Same protocol as SharedPool, payloads move between the halves
+unsafe impl<T: Send, const N: usize, A: PoolAlloc<N> + Sync> Sync for PayloadPool<T, N, A> {}
+
+impl<T, const N: usize, A: PoolAlloc<N>> PayloadPool<T, N, A> {
+    /// Empty pool, id must be unique among the pools of a queue and
+    /// not u8::MAX
+    pub const fn new(id: u8) -> Self {
+        assert!(id != u8::MAX, "Pool id u8::MAX is reserved");
+        PayloadPool {
+            id,
+            has_split: Cell::new(false),
+            pool_alloc: A::INIT,
+            pool: [SharedSingleton::INIT_0; N],
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn num_free(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+
+    // Split both halves, once in life time
+    #[allow(clippy::type_complexity)]
+    pub fn split(&self) -> Result<(PoolProducer<'_, T, N, A>, PoolConsumer<'_, T, N, A>), SharedPoolError> {
+        if self.has_split.get() {
+            return Err(SharedPoolError::AlreadySplit);
+        }
+        self.has_split.set(true);
+
+        // Hand all the pool locations to the producer
+        self.pool_alloc.fill();
+
+        Ok((
+            PoolProducer {
+                id: self.id,
+                pool_alloc: &self.pool_alloc,
+                pool_ref: &self.pool,
+            },
+            PoolConsumer {
+                id: self.id,
+                pool_alloc: &self.pool_alloc,
+                pool_ref: &self.pool,
+            },
+        ))
+    }
+}
+
+pub struct Producer<'a, Q: HasPoolRef + 'a, const M: usize> {
+    // Producer handle for the command allocation
+    pub alloc_prod: RingBufProducer<'a, Q, M>,
+    // A message is staged and can be committed
+    staged: bool,
+}
+
+impl<'a, Q: HasPoolRef, const M: usize> Producer<'a, Q, M> {
+    // Stage item for write without payload
+    pub fn stage(&mut self) -> Option<&mut Q> {
+        // Messages are staged in place, see HasPoolRef
+        let item = self.alloc_prod.alloc_uninit()?.write(Q::default());
+        item.set_pool_ref(PoolRef::NONE);
+        self.staged = true;
+        Some(item)
+    }
+
+    // Stage a command buffer and a payload taken from pool
+    pub fn stage_with_payload<'p, T, const N: usize, A: PoolAlloc<N>>(
+        &mut self,
+        pool: &mut PoolProducer<'p, T, N, A>,
+    ) -> Result<(&mut Q, &'p SharedSingleton<T>), SharedPoolError> {
+        // Check the command queue first so that a pool item is never
+        // taken without a message to carry it
+        if self.alloc_prod.alloc_uninit().is_none() {
+            return Err(SharedPoolError::AllocBufFull);
+        }
+        let idx = pool.pool_alloc.take().ok_or(SharedPoolError::PoolFull)?;
+        let idx = usize::try_from(idx).map_err(|_| SharedPoolError::InvalidPoolIndex)?;
+        let pool_ref: &'p [SharedSingleton<T>; N] = pool.pool_ref;
+        let payload = &pool_ref[idx];
+
+        // Location indicated as free must actually be vacant
+        if !payload.is_vacant() {
+            return Err(SharedPoolError::PayloadNotVacant);
+        }
+
+        // Checked above, cannot fail
+        let item = self.alloc_prod.alloc_uninit().unwrap().write(Q::default());
+        item.set_pool_ref(PoolRef { pool: pool.id, idx: idx as u32 });
+        self.staged = true;
+
+        Ok((item, payload))
+    }
+
+    // Commit the staged command. The payload is not checked here since its
+    // pool is not known, write_done it before committing.
+    pub fn commit(&mut self) -> Result<(), SharedPoolError> {
+        if !self.staged {
+            return Err(SharedPoolError::NotStaged);
+        }
+        self.alloc_prod
+            .commit()
+            .map_err(|_| SharedPoolError::AllocBufFull)?;
+        self.staged = false;
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, Q: HasPoolRef + 'a, const M: usize> {
+    // Consumer handle for the command allocation
+    pub alloc_cons: RingBufConsumer<'a, Q, M>,
+}
+
+impl<'a, Q: HasPoolRef, const M: usize> Consumer<'a, Q, M> {
+    pub fn peek(&self) -> Option<&Q> {
+        self.alloc_cons.reader_front()
+    }
+
+    /// Message at the head and its payload if it belongs to pool
+    pub fn peek_with_payload<'p, T, const N: usize, A: PoolAlloc<N>>(
+        &self,
+        pool: &PoolConsumer<'p, T, N, A>,
+    ) -> (Option<&Q>, Option<&'p SharedSingleton<T>>) {
+        match self.alloc_cons.reader_front() {
+            Some(message) => (Some(message), pool.read_pool_item(message.get_pool_ref())),
+            None => (None, None),
+        }
+    }
+
+    pub fn pop(&mut self) -> Result<(), SharedPoolError> {
+        self.alloc_cons
+            .pop()
+            .map_err(|_| SharedPoolError::AllocBufEmpty)
+    }
+}
+
+/// Command ring of M messages Q, carrying payloads of any number of
+/// `PayloadPool`s
+pub struct MultiPool<Q: HasPoolRef, const M: usize> {
+    alloc_rbuf: RingBuf<Q, M>,
+}
+
+impl<Q: HasPoolRef, const M: usize> Default for MultiPool<Q, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Q: HasPoolRef, const M: usize> MultiPool<Q, M> {
+    pub const fn new() -> Self {
+        MultiPool { alloc_rbuf: RingBuf::new() }
+    }
+
+    // Split both producer and consumer handle together
+    pub fn split(&self) -> Result<(Producer<'_, Q, M>, Consumer<'_, Q, M>), SharedPoolError> {
+        match self.alloc_rbuf.split() {
+            Ok((alloc_prod, alloc_cons)) => Ok((Producer { alloc_prod, staged: false }, Consumer { alloc_cons })),
+            Err(_) => Err(SharedPoolError::AlreadySplit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Message {
+        kind: u8,
+        pref: PoolRef,
+    }
+
+    impl HasPoolRef for Message {
+        fn get_pool_ref(&self) -> PoolRef {
+            self.pref
+        }
+        fn set_pool_ref(&mut self, pref: PoolRef) {
+            self.pref = pref;
+        }
+    }
+
+    const AUDIO: u8 = 0;
+    const CONTROL: u8 = 1;
+
+    #[test]
+    fn two_pools() {
+        let queue: MultiPool<Message, 8> = MultiPool::new();
+        let audio: PayloadPool<[i16; 16], 2> = PayloadPool::new(AUDIO);
+        let control: PayloadPool<u32, 4> = PayloadPool::new(CONTROL);
+
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        let (mut audio_prod, mut audio_cons) = audio.split().unwrap();
+        let (mut control_prod, mut control_cons) = control.split().unwrap();
+        assert!(audio.split().is_err());
+
+        let (msg, payload) = producer.stage_with_payload(&mut audio_prod).unwrap();
+        msg.kind = AUDIO;
+        *payload.try_write().unwrap() = [7; 16];
+        payload.write_done().unwrap();
+        producer.commit().unwrap();
+
+        let (msg, payload) = producer.stage_with_payload(&mut control_prod).unwrap();
+        msg.kind = CONTROL;
+        *payload.try_write().unwrap() = 0xdead;
+        payload.write_done().unwrap();
+        producer.commit().unwrap();
+
+        producer.stage().unwrap().kind = 2;
+        producer.commit().unwrap();
+
+        assert!(audio_prod.num_free() == 1);
+        assert!(control_prod.num_free() == 3);
+
+        for _ in 0..3 {
+            let msg = consumer.peek().unwrap();
+            let pref = msg.get_pool_ref();
+            match msg.kind {
+                AUDIO => {
+                    assert!(pref.pool_id() == Some(AUDIO));
+                    // Not a control pool reference
+                    assert!(consumer.peek_with_payload(&control_cons).1.is_none());
+                    let payload = consumer.peek_with_payload(&audio_cons).1.unwrap();
+                    assert!(*payload.try_read().unwrap() == [7; 16]);
+                    payload.read_done().unwrap();
+                    audio_cons.return_payload(pref).unwrap();
+                }
+                CONTROL => {
+                    let payload = control_cons.read_pool_item(pref).unwrap();
+                    assert!(*payload.try_read().unwrap() == 0xdead);
+                    // Still held by the consumer, or not of this pool
+                    assert!(control_cons.return_payload(pref) == Err(SharedPoolError::PayloadNotVacant));
+                    assert!(audio_cons.return_payload(pref) == Err(SharedPoolError::InvalidPoolIndex));
+                    payload.read_done().unwrap();
+                    control_cons.return_payload(pref).unwrap();
+                }
+                _ => assert!(pref.is_none()),
+            }
+            consumer.pop().unwrap();
+        }
+
+        assert!(audio.num_free() == 2);
+        assert!(control.num_free() == 4);
+    }
+}
@@ -0,0 +1,203 @@
+//! Notification hooks fired towards the other side of a queue, e.g. pending
+//! an interrupt or ringing an inter-processor doorbell.
+
+use crate::clock::Clock;
+use crate::sync::{AtomicU32, Ordering};
+
+/// Hook to signal the other side that the queue state changed
+pub trait Notifier {
+    fn notify(&self);
+}
+
+/// No-op notifier
+pub struct NoNotify;
+
+impl Notifier for NoNotify {
+    #[inline(always)]
+    fn notify(&self) {}
+}
+
+impl<F: Fn()> Notifier for F {
+    #[inline]
+    fn notify(&self) {
+        self()
+    }
+}
+
+/// Wakes the other core out of WFE. The DSB completes the index store
+/// before the event is sent
+#[cfg(all(feature = "cortex-m", target_arch = "arm"))]
+pub struct Sev;
+
+#[cfg(all(feature = "cortex-m", target_arch = "arm"))]
+impl Notifier for Sev {
+    #[inline(always)]
+    fn notify(&self) {
+        cortex_m::asm::dsb();
+        cortex_m::asm::sev();
+    }
+}
+
+/// Writes val to a memory mapped doorbell register, e.g. the IPC or FIFO
+/// interrupt trigger of the other core
+pub struct Doorbell {
+    reg: *mut u32,
+    val: u32,
+}
+
+// Only the register is shared, writes to it are single volatile stores
+unsafe impl Send for Doorbell {}
+unsafe impl Sync for Doorbell {}
+
+impl Doorbell {
+    /// # Safety
+    /// reg must stay valid for volatile writes as long as the doorbell is
+    /// in use
+    pub const unsafe fn new(reg: *mut u32, val: u32) -> Self {
+        Doorbell { reg, val }
+    }
+}
+
+impl Notifier for Doorbell {
+    #[inline(always)]
+    fn notify(&self) {
+        // Complete the index store before the other core is interrupted
+        #[cfg(all(feature = "cortex-m", target_arch = "arm"))]
+        cortex_m::asm::dsb();
+        unsafe { self.reg.write_volatile(self.val) };
+    }
+}
+
+/// Coalescing policy in front of another notifier. The inner notifier only
+/// fires once `every` notifications accumulated, or on the first notification
+/// at least `max_ticks` after the last time it fired. Notifications still
+/// pending at the end of a burst are fired by `flush`, e.g. from an idle loop.
+///
+/// Meant to be owned by one side only, like the Producer. The counters are
+/// atomics updated by plain loads and stores, so that it is Sync as
+/// `Producer::set_on_commit` and shared rings require.
+pub struct Coalesce<Nf: Notifier, C: Clock> {
+    inner: Nf,
+    clock: C,
+    every: u32,
+    max_ticks: u32,
+    pending: AtomicU32,
+    last_fired: AtomicU32,
+}
+
+impl<Nf: Notifier, C: Clock> Coalesce<Nf, C> {
+
+    pub const fn new(inner: Nf, clock: C, every: u32, max_ticks: u32) -> Self {
+        Coalesce {
+            inner,
+            clock,
+            every,
+            max_ticks,
+            pending: AtomicU32::new(0),
+            last_fired: AtomicU32::new(0),
+        }
+    }
+
+    /// Number of notifications not passed on yet
+    pub fn pending(&self) -> u32 {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Fire the inner notifier if anything is pending
+    pub fn flush(&self) {
+        if self.pending.load(Ordering::Relaxed) != 0 {
+            self.fire(self.clock.now());
+        }
+    }
+
+    #[inline]
+    fn fire(&self, now: u32) {
+        self.pending.store(0, Ordering::Relaxed);
+        self.last_fired.store(now, Ordering::Relaxed);
+        self.inner.notify();
+    }
+}
+
+impl<Nf: Notifier, C: Clock> Notifier for Coalesce<Nf, C> {
+    #[inline]
+    fn notify(&self) {
+        let pending = self.pending.load(Ordering::Relaxed) + 1;
+        let now = self.clock.now();
+        if pending >= self.every || now.wrapping_sub(self.last_fired.load(Ordering::Relaxed)) >= self.max_ticks {
+            self.fire(now);
+        } else {
+            self.pending.store(pending, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct TestClock(Cell<u32>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn coalesce_count_and_time() {
+        let fired = Cell::new(0);
+        let clock = TestClock(Cell::new(0));
+        let doorbell = || fired.set(fired.get() + 1);
+        let coalesce = Coalesce::new(doorbell, &clock, 4, 100);
+
+        // Burst within the time threshold fires every 4th
+        for _ in 0..8 {
+            coalesce.notify();
+        }
+        assert!(fired.get() == 2);
+
+        coalesce.notify();
+        assert!(fired.get() == 2 && coalesce.pending() == 1);
+
+        // Time threshold exceeded since last fired
+        clock.0.set(100);
+        coalesce.notify();
+        assert!(fired.get() == 3 && coalesce.pending() == 0);
+
+        coalesce.notify();
+        coalesce.flush();
+        assert!(fired.get() == 4);
+        coalesce.flush();
+        assert!(fired.get() == 4);
+    }
+
+    #[test]
+    fn coalesce_is_sync() {
+        struct Ticks;
+        impl Clock for Ticks {
+            fn now(&self) -> u32 {
+                0
+            }
+        }
+        fn assert_sync<S: Sync>() {}
+        assert_sync::<Coalesce<Doorbell, Ticks>>();
+    }
+
+    #[test]
+    fn doorbell_on_commit() {
+        use crate::ringbuf::RingBuf;
+
+        let reg = Cell::new(0u32);
+        let doorbell = unsafe { Doorbell::new(reg.as_ptr(), 1 << 3) };
+        let ring: RingBuf<u8, 4> = RingBuf::new();
+        let (mut producer, mut consumer) = ring.split().unwrap();
+        producer.set_on_commit(Some(&doorbell));
+
+        assert!(producer.push_slice(&[]) == 0);
+        assert!(reg.get() == 0);
+        assert!(producer.try_push(1).is_ok());
+        assert!(reg.get() == 1 << 3);
+        assert!(consumer.pop_value() == Some(1));
+    }
+}
@@ -0,0 +1,208 @@
+//! SharedPool variant with the payloads in FIFO order.
+//!
+//! When the consumer always processes the messages in order, the payloads
+//! can be kept in a second ring instead of an indexed pool: the payload of a
+//! message is always at the head of the payload ring. There are no pool
+//! indices in the messages and no return ring, a payload location is freed
+//! by popping its message.
+
+use core::mem::MaybeUninit;
+
+use crate::ringbuf::{Consumer as RingBufConsumer, Producer as RingBufProducer, RingBuf, SplitError};
+use crate::shared_pool::SharedPoolError;
+
+// Message slot, flagged when the next payload in the payload ring is its own
+type Slot<Q> = (bool, Q);
+
+pub struct Producer<'a, T: 'a, Q: 'a, const N: usize, const M: usize> {
+    msg_prod: RingBufProducer<'a, Slot<Q>, M>,
+    payload_prod: RingBufProducer<'a, T, N>,
+    // Payload flag of the staged message, None if nothing is staged
+    staged: Option<bool>,
+}
+
+impl<T, Q: Default, const N: usize, const M: usize> Producer<'_, T, Q, N, M> {
+
+    /// Payload locations free for staging
+    pub fn free_payloads(&self) -> u32 {
+        self.payload_prod.free_len()
+    }
+
+    // Message slot at the write index with its flag set. Messages are
+    // staged in place from Default like SharedPool ones
+    fn stage_slot(&mut self, has_payload: bool) -> Option<&mut Q> {
+        let (_, item) = self.msg_prod.alloc_uninit()?.write((has_payload, Q::default()));
+        self.staged = Some(has_payload);
+        Some(item)
+    }
+
+    // Stage a message without payload
+    pub fn stage(&mut self) -> Option<&mut Q> {
+        self.stage_slot(false)
+    }
+
+    // Stage a message and the payload location following the previous
+    // one, initialized in place by f. As with `push_with`, f returns the
+    // reference obtained from `MaybeUninit::write`, any other is rejected
+    // with InvalidState. Both are published together by commit.
+    pub fn stage_with_payload<F>(&mut self, f: F) -> Result<&mut Q, SharedPoolError>
+    where
+        F: for<'s> FnOnce(&'s mut MaybeUninit<T>) -> &'s mut T,
+    {
+        if self.msg_prod.alloc_uninit().is_none() {
+            return Err(SharedPoolError::AllocBufFull);
+        }
+        let payload = self.payload_prod.alloc_uninit().ok_or(SharedPoolError::PoolFull)?;
+        let ptr = payload.as_mut_ptr();
+        if !core::ptr::eq(f(payload), ptr) {
+            return Err(SharedPoolError::InvalidState);
+        }
+        // Checked vacant above
+        self.stage_slot(true).ok_or(SharedPoolError::AllocBufFull)
+    }
+
+    // Commit the staged message, its payload first so that the consumer
+    // never sees a message without its payload
+    pub fn commit(&mut self) -> Result<(), SharedPoolError> {
+        let has_payload = self.staged.ok_or(SharedPoolError::NotStaged)?;
+        if has_payload {
+            self.payload_prod
+                .commit()
+                .map_err(|_| SharedPoolError::PoolFull)?;
+        }
+        self.msg_prod
+            .commit()
+            .map_err(|_| SharedPoolError::AllocBufFull)?;
+        self.staged = None;
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, T: 'a, Q: 'a, const N: usize, const M: usize> {
+    msg_cons: RingBufConsumer<'a, Slot<Q>, M>,
+    payload_cons: RingBufConsumer<'a, T, N>,
+}
+
+impl<T, Q, const N: usize, const M: usize> Consumer<'_, T, Q, N, M> {
+
+    /// Payloads queued with their messages
+    pub fn in_flight(&self) -> u32 {
+        self.payload_cons.len()
+    }
+
+    // Message at the head and its payload, if any
+    pub fn peek(&self) -> Option<(&Q, Option<&T>)> {
+        let (has_payload, item) = self.msg_cons.reader_front()?;
+        // Committed before the message, cannot be missing
+        let payload = if *has_payload { self.payload_cons.reader_front() } else { None };
+
+        Some((item, payload))
+    }
+
+    // Pop the message at the head, freeing its payload location
+    pub fn pop(&mut self) -> Result<(), SharedPoolError> {
+        let (has_payload, _) = self.msg_cons.reader_front().ok_or(SharedPoolError::AllocBufEmpty)?;
+        if *has_payload {
+            self.payload_cons
+                .pop()
+                .map_err(|_| SharedPoolError::InvalidState)?;
+        }
+        self.msg_cons
+            .pop()
+            .map_err(|_| SharedPoolError::AllocBufEmpty)
+    }
+
+    // Receive the message at the head in one call, see
+    // `shared_pool::Consumer::recv`
+    pub fn recv<R, F: FnOnce(&Q, Option<&T>) -> R>(&mut self, f: F) -> Result<R, SharedPoolError> {
+        let (item, payload) = self.peek().ok_or(SharedPoolError::AllocBufEmpty)?;
+        let ret = f(item, payload);
+        self.pop()?;
+        Ok(ret)
+    }
+}
+
+/// Producer and Consumer pair returned by OrderedPool::split
+pub type OrderedPoolHandles<'a, T, Q, const N: usize, const M: usize> =
+    (Producer<'a, T, Q, N, M>, Consumer<'a, T, Q, N, M>);
+
+/// Command ring of M messages Q with a ring of N payloads T, consumed in
+/// the order they were committed
+pub struct OrderedPool<T, Q, const N: usize, const M: usize> {
+    msg_rbuf: RingBuf<Slot<Q>, M>,
+    payload_rbuf: RingBuf<T, N>,
+}
+
+impl<T, Q, const N: usize, const M: usize> Default for OrderedPool<T, Q, N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Q, const N: usize, const M: usize> OrderedPool<T, Q, N, M> {
+
+    pub const fn new() -> Self {
+        OrderedPool {
+            msg_rbuf: RingBuf::new(),
+            payload_rbuf: RingBuf::new(),
+        }
+    }
+
+    // Return the producer, once in life time
+    pub fn split_prod(&self) -> Result<Producer<'_, T, Q, N, M>, SplitError> {
+        let msg_prod = self.msg_rbuf.split_prod()?;
+        // Split along with the message ring, cannot fail
+        let payload_prod = self.payload_rbuf.split_prod().unwrap();
+        Ok(Producer { msg_prod, payload_prod, staged: None })
+    }
+
+    // Return the consumer, once in life time
+    pub fn split_cons(&self) -> Result<Consumer<'_, T, Q, N, M>, SplitError> {
+        let msg_cons = self.msg_rbuf.split_cons()?;
+        // Split along with the message ring, cannot fail
+        let payload_cons = self.payload_rbuf.split_cons().unwrap();
+        Ok(Consumer { msg_cons, payload_cons })
+    }
+
+    // Split both handles, see `SharedPool::split`
+    pub fn split(&self) -> Result<OrderedPoolHandles<'_, T, Q, N, M>, SplitError> {
+        if self.msg_rbuf.has_split_prod() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.msg_rbuf.has_split_cons() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_prod()?, self.split_cons()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_in_order() {
+        let pool: OrderedPool<[u8; 8], u32, 2, 4> = OrderedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+        assert!(pool.split().err() == Some(SplitError::AlreadySplitProd));
+
+        assert!(producer.commit() == Err(SharedPoolError::NotStaged));
+        for i in 0..2 {
+            *producer.stage_with_payload(|payload| payload.write([i as u8; 8])).unwrap() = i;
+            assert!(producer.commit().is_ok());
+        }
+        // Payload ring full, messages without payload still go through
+        assert!(producer.stage_with_payload(|payload| payload.write([0; 8])).err() == Some(SharedPoolError::PoolFull));
+        *producer.stage().unwrap() = 2;
+        assert!(producer.commit().is_ok());
+
+        assert!(consumer.in_flight() == 2);
+        assert!(consumer.recv(|item, payload| *item == 0 && payload == Some(&[0; 8])).unwrap());
+        assert!(producer.free_payloads() == 1);
+        assert!(consumer.peek() == Some((&1, Some(&[1; 8]))));
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.peek() == Some((&2, None)));
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.pop().err() == Some(SharedPoolError::AllocBufEmpty));
+    }
+}
@@ -0,0 +1,221 @@
+//! Ring buffer whose producer overwrites the oldest item when full.
+//!
+//! Meant for telemetry and logs where the latest data matters more than
+//! completeness. When full, the producer drops the oldest item by advancing
+//! the read index itself, racing the consumer with a compare-exchange. The
+//! consumer copies an item out before claiming it with a compare-exchange
+//! of its own and retries when the producer got there first, hence items
+//! are `Copy` and returned by value.
+//!
+//! N must be a power of two so that the indices wrap over the full u32
+//! range. A consumer preempted for 2^32 pushes in the middle of a pop could
+//! otherwise mistake a recycled index for its own. Compare-exchange is not
+//! available on thumbv6m without the `critical-section` feature.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+pub struct OverwriteRingBufRef<T: Copy, const N: usize> {
+    // Advanced by the consumer, or by the producer when overwriting
+    rd_idx: AtomicU32,
+    // Only written by the producer
+    wr_idx: AtomicU32,
+    // Number of items dropped by push_overwrite, only written by the producer
+    overwritten: AtomicU32,
+    // Value of overwritten at the last reset, only written by the consumer
+    dropped_base: AtomicU32,
+    buffer_ucell: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+// Delcare this is thread safe due to the SPSC premise, see the module doc
+// for the read index protocol.
+unsafe impl<T: Copy + Send, const N: usize> Sync for OverwriteRingBufRef<T, N> {}
+
+impl<T: Copy, const N: usize> Default for OverwriteRingBufRef<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> OverwriteRingBufRef<T, N> {
+
+    const OK: () = assert!(N.is_power_of_two() && N < (1 << 31), "Overwrite ringbuf capacity must be a power of two");
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_U: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: OverwriteRingBufRef<T, N> = Self::new();
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = OverwriteRingBufRef::<T, N>::OK;
+        OverwriteRingBufRef {
+            rd_idx: AtomicU32::new(0),
+            wr_idx: AtomicU32::new(0),
+            overwritten: AtomicU32::new(0),
+            dropped_base: AtomicU32::new(0),
+            buffer_ucell: [Self::INIT_U; N],
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, idx: u32) -> *mut T {
+        self.buffer_ucell[idx as usize & (N - 1)].get() as *mut T
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.wr_idx.load(Ordering::Acquire).wrapping_sub(self.rd_idx.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of items dropped by `push_overwrite` so far (wrapping)
+    #[inline(always)]
+    pub fn overwritten(&self) -> u32 {
+        self.overwritten.load(Ordering::Relaxed)
+    }
+
+    /// Items dropped since the last `reset_dropped_count`, for reporting
+    /// data loss to the host
+    #[inline(always)]
+    pub fn dropped_count(&self) -> u32 {
+        self.overwritten().wrapping_sub(self.dropped_base.load(Ordering::Relaxed))
+    }
+
+    /// Restart `dropped_count` from zero and return the count it had.
+    /// Consumer only; the producer counter is left alone, so no drop is
+    /// lost between reading and resetting
+    #[inline(always)]
+    pub fn reset_dropped_count(&self) -> u32 {
+        let now = self.overwritten();
+        let count = now.wrapping_sub(self.dropped_base.load(Ordering::Relaxed));
+        self.dropped_base.store(now, Ordering::Relaxed);
+        count
+    }
+
+    /// Push val, dropping the oldest item if full. Producer only. Returns
+    /// whether an item was dropped
+    #[inline]
+    pub fn push_overwrite(&self, val: T) -> bool {
+        let wr = self.wr_idx.load(Ordering::Relaxed);
+        let rd = self.rd_idx.load(Ordering::Acquire);
+        let mut dropped = false;
+        // Failing means the consumer just freed the slot instead
+        if wr.wrapping_sub(rd) as usize == N
+            && self.rd_idx.rmw_compare_exchange(rd, rd.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire).is_ok()
+        {
+            let count = self.overwritten.load(Ordering::Relaxed);
+            self.overwritten.store(count.wrapping_add(1), Ordering::Relaxed);
+            dropped = true;
+        }
+        unsafe { self.slot(wr).write(val) };
+        self.wr_idx.store(wr.wrapping_add(1), Ordering::Release);
+        dropped
+    }
+
+    /// Push val unless full, handing it back then. Producer only
+    #[inline]
+    pub fn try_push(&self, val: T) -> Result<(), T> {
+        if self.len() as usize == N {
+            return Err(val);
+        }
+        unsafe { self.write_unpublished(val) };
+        self.publish();
+        Ok(())
+    }
+
+    // Write the slot at the write index without publishing it. Caller
+    // must make sure the ring is not full, the slot is the oldest item's
+    // otherwise
+    #[inline(always)]
+    pub(crate) unsafe fn write_unpublished(&self, val: T) {
+        self.slot(self.wr_idx.load(Ordering::Relaxed)).write(val);
+    }
+
+    // Publish the slot at the write index
+    #[inline(always)]
+    pub(crate) fn publish(&self) {
+        let wr = self.wr_idx.load(Ordering::Relaxed);
+        self.wr_idx.store(wr.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Copy the oldest item out and consume it. Consumer only
+    #[inline]
+    pub fn pop(&self) -> Option<T> {
+        loop {
+            let rd = self.rd_idx.load(Ordering::Acquire);
+            if rd == self.wr_idx.load(Ordering::Acquire) {
+                return None;
+            }
+            // May be torn by a concurrent overwrite, discarded below then.
+            // Kept uninit until the claim succeeds, a torn copy is no valid T
+            let val = unsafe { core::ptr::read_volatile(self.slot(rd) as *const MaybeUninit<T>) };
+            if self.rd_idx.rmw_compare_exchange(rd, rd.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                // Not overwritten while copied, the producer only writes a
+                // slot after claiming it off the read index
+                return Some(unsafe { val.assume_init() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest() {
+        let ring: OverwriteRingBufRef<u32, 4> = OverwriteRingBufRef::new();
+        for i in 0..4 {
+            assert!(!ring.push_overwrite(i));
+        }
+        assert!(ring.push_overwrite(4));
+        assert!(ring.push_overwrite(5));
+        assert!(ring.overwritten() == 2 && ring.len() == 4);
+        assert!(ring.dropped_count() == 2);
+        assert!(ring.reset_dropped_count() == 2);
+        assert!(ring.dropped_count() == 0);
+        assert!(ring.push_overwrite(6));
+        assert!(ring.dropped_count() == 1 && ring.overwritten() == 3);
+        for i in 3..7 {
+            assert!(ring.pop() == Some(i));
+        }
+        assert!(ring.pop().is_none());
+    }
+
+    #[test]
+    fn concurrent_overwrite() {
+        static RING: OverwriteRingBufRef<(u32, u32), 8> = OverwriteRingBufRef::new();
+        const COUNT: u32 = 100_000;
+
+        let producer = std::thread::spawn(|| {
+            for i in 0..COUNT {
+                RING.push_overwrite((i, !i));
+            }
+        });
+        // Items arrive in order and untorn, some may be skipped
+        let mut last = None;
+        let mut received = 0;
+        while !producer.is_finished() || !RING.is_empty() {
+            if let Some((v, check)) = RING.pop() {
+                assert!(check == !v);
+                assert!(last.is_none_or(|l| v > l));
+                last = Some(v);
+                received += 1;
+            }
+        }
+        producer.join().unwrap();
+        assert!(received + RING.overwritten() == COUNT);
+    }
+}
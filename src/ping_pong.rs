@@ -0,0 +1,199 @@
+//! Double buffer with an explicit swap, for DMA ping-pong patterns.
+//!
+//! Both halves are always allocated. The writer owns one of them and the
+//! reader the other, each releases its half with `done` once finished with
+//! it. `swap` then exchanges the halves, and only succeeds once both were
+//! released, so a half is never accessed by both sides. Either side may
+//! call it, e.g. the DMA complete ISR or the processing loop.
+//!
+//! The state is updated with atomic read-modify-write, not available on
+//! targets without CAS such as thumbv6m unless the `critical-section`
+//! feature is on.
+
+use core::cell::{Cell, UnsafeCell};
+
+use crate::sync::{AtomicRmw, AtomicU8, Ordering};
+
+// Half owned by the writer, the reader owns the other one
+const WRITE_IDX: u8 = 0b001;
+const WRITER_DONE: u8 = 0b010;
+const READER_DONE: u8 = 0b100;
+
+pub struct PingPong<T> {
+    bufs: UnsafeCell<[T; 2]>,
+    state: AtomicU8,
+    has_split: Cell<bool>,
+}
+
+// Halves are handed between the writer and the reader context
+unsafe impl<T: Send> Sync for PingPong<T> {}
+
+/// Writer handle, once in life time
+pub struct PingPongWriter<'a, T> {
+    inner: &'a PingPong<T>,
+}
+
+/// Reader handle, once in life time
+pub struct PingPongReader<'a, T> {
+    inner: &'a PingPong<T>,
+}
+
+impl<T> PingPong<T> {
+
+    /// Writer starts with bufs[0]. The reader's half holds nothing written
+    /// yet, hence starts released
+    pub const fn new(bufs: [T; 2]) -> Self {
+        PingPong {
+            bufs: UnsafeCell::new(bufs),
+            state: AtomicU8::new(READER_DONE),
+            has_split: Cell::new(false),
+        }
+    }
+
+    /// Return the writer and reader, once in life time
+    pub fn split(&self) -> Option<(PingPongWriter<'_, T>, PingPongReader<'_, T>)> {
+        if self.has_split.get() {
+            None
+        } else {
+            self.has_split.set(true);
+            Some((PingPongWriter { inner: self }, PingPongReader { inner: self }))
+        }
+    }
+
+    /// Exchange the halves if both sides are done with theirs
+    pub fn swap(&self) -> bool {
+        let state = self.state.load(Ordering::Acquire);
+        if state & (WRITER_DONE | READER_DONE) != WRITER_DONE | READER_DONE {
+            return false;
+        }
+        // Both released, only a concurrent swap can change the state
+        self.state
+            .rmw_compare_exchange(state, (state ^ WRITE_IDX) & WRITE_IDX, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    fn buf(&self, idx: u8) -> *mut T {
+        // Only ever a pointer to one element, the sides own distinct ones
+        unsafe { (self.bufs.get() as *mut T).add(idx as usize) }
+    }
+
+    #[inline(always)]
+    fn state(&self) -> u8 {
+        self.state.load(Ordering::Acquire)
+    }
+}
+
+impl<'a, T> PingPongWriter<'a, T> {
+
+    /// Half owned by the writer, None once released until the next swap
+    #[inline]
+    pub fn buf_mut(&mut self) -> Option<&mut T> {
+        let state = self.inner.state();
+        if state & WRITER_DONE != 0 {
+            None
+        } else {
+            Some(unsafe { &mut *self.inner.buf(state & WRITE_IDX) })
+        }
+    }
+
+    /// Release the written half to the reader on the next swap
+    #[inline]
+    pub fn done(&mut self) {
+        self.inner.state.rmw_fetch_or(WRITER_DONE, Ordering::AcqRel);
+    }
+
+    #[inline]
+    pub fn swap(&mut self) -> bool {
+        self.inner.swap()
+    }
+}
+
+impl<'a, T> PingPongReader<'a, T> {
+
+    /// Half owned by the reader, None once released until the next swap
+    #[inline]
+    pub fn buf(&self) -> Option<&T> {
+        let state = self.inner.state();
+        if state & READER_DONE != 0 {
+            None
+        } else {
+            Some(unsafe { &*self.inner.buf((state & WRITE_IDX) ^ WRITE_IDX) })
+        }
+    }
+
+    /// Release the read half to the writer on the next swap
+    #[inline]
+    pub fn done(&mut self) {
+        self.inner.state.rmw_fetch_or(READER_DONE, Ordering::AcqRel);
+    }
+
+    #[inline]
+    pub fn swap(&mut self) -> bool {
+        self.inner.swap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_halves() {
+        let pp = PingPong::new([[0u8; 4]; 2]);
+        let (mut writer, mut reader) = pp.split().unwrap();
+        assert!(pp.split().is_none());
+        assert!(reader.buf().is_none());
+
+        writer.buf_mut().unwrap().fill(1);
+        // Writer not done yet
+        assert!(!reader.swap());
+        writer.done();
+        assert!(writer.buf_mut().is_none());
+        assert!(reader.swap());
+        assert!(*reader.buf().unwrap() == [1; 4]);
+
+        // Writer fills the other half while the reader holds this one
+        writer.buf_mut().unwrap().fill(2);
+        writer.done();
+        assert!(!writer.swap());
+        reader.done();
+        assert!(reader.buf().is_none());
+        assert!(writer.swap());
+        assert!(*reader.buf().unwrap() == [2; 4]);
+        // Writer got the first half back
+        assert!(*writer.buf_mut().unwrap() == [1; 4]);
+    }
+
+    #[test]
+    fn threads() {
+        static PP: PingPong<[u32; 8]> = PingPong::new([[0; 8]; 2]);
+        let (mut writer, mut reader) = PP.split().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut i = 1;
+            while i <= 1000 {
+                if let Some(buf) = writer.buf_mut() {
+                    buf.fill(i);
+                    writer.done();
+                    i += 1;
+                }
+                if !writer.swap() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 1;
+        while expected <= 1000 {
+            if let Some(buf) = reader.buf() {
+                assert!(*buf == [expected; 8]);
+                expected += 1;
+                reader.done();
+            }
+            if !reader.swap() {
+                std::thread::yield_now();
+            }
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+//! Ring buffer with the behavior on full selected by a type parameter.
+//!
+//! `PolicyRingBufRef<T, N, P>` builds on the index protocol of
+//! `OverwriteRingBufRef`, so that queues of the same firmware can pick
+//! reject or lossy semantics with one type:
+//!
+//! - `Reject`: the item pushed to a full ring is handed back.
+//! - `OverwriteOldest`: the oldest queued item is dropped.
+//! - `OverwriteNewest`: the newest item is replaced. The consumer may be
+//!   reading any published slot, so the newest item is kept unpublished in
+//!   the spare slot while the ring is full and published once the consumer
+//!   made room, by the next push or by `flush`.
+//!
+//! Same requirements as `OverwriteRingBufRef`: T is Copy and N a power of
+//! two.
+
+use core::cell::Cell;
+
+use crate::overwrite::OverwriteRingBufRef;
+use crate::sync::{AtomicU32, Ordering};
+
+/// Behavior of a push to a full ring
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OnFull {
+    Reject,
+    OverwriteOldest,
+    OverwriteNewest,
+}
+
+/// Type level selection of OnFull
+pub trait FullPolicy {
+    const ON_FULL: OnFull;
+}
+
+pub struct Reject;
+pub struct OverwriteOldest;
+pub struct OverwriteNewest;
+
+impl FullPolicy for Reject {
+    const ON_FULL: OnFull = OnFull::Reject;
+}
+
+impl FullPolicy for OverwriteOldest {
+    const ON_FULL: OnFull = OnFull::OverwriteOldest;
+}
+
+impl FullPolicy for OverwriteNewest {
+    const ON_FULL: OnFull = OnFull::OverwriteNewest;
+}
+
+pub struct PolicyRingBufRef<T: Copy, const N: usize, P: FullPolicy> {
+    ring: OverwriteRingBufRef<T, N>,
+    // Spare slot holds the newest item, OverwriteNewest only. Only
+    // accessed by the producer
+    pending: Cell<bool>,
+    // Items rejected or overwritten, only written by the producer
+    dropped: AtomicU32,
+    _policy: core::marker::PhantomData<P>,
+}
+
+// Sync through the inner ring, pending is only accessed by the producer
+// and the policy is a marker
+unsafe impl<T: Copy + Send, const N: usize, P: FullPolicy> Sync for PolicyRingBufRef<T, N, P> {}
+
+impl<T: Copy, const N: usize, P: FullPolicy> Default for PolicyRingBufRef<T, N, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize, P: FullPolicy> PolicyRingBufRef<T, N, P> {
+
+    // The spare slot of OverwriteNewest leaves N - 1 slots to publish
+    const OK: () = assert!(N > 1 || !matches!(P::ON_FULL, OnFull::OverwriteNewest), "OverwriteNewest needs a capacity of 2 or more");
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = PolicyRingBufRef::<T, N, P>::OK;
+        PolicyRingBufRef {
+            ring: OverwriteRingBufRef::new(),
+            pending: Cell::new(false),
+            dropped: AtomicU32::new(0),
+            _policy: core::marker::PhantomData,
+        }
+    }
+
+    /// Policy applied on full
+    pub const fn on_full(&self) -> OnFull {
+        P::ON_FULL
+    }
+
+    /// Number of items available to the consumer
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Items rejected or overwritten so far (wrapping)
+    #[inline(always)]
+    pub fn dropped(&self) -> u32 {
+        match P::ON_FULL {
+            OnFull::OverwriteOldest => self.ring.overwritten(),
+            _ => self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count_drop(&self) {
+        let count = self.dropped.load(Ordering::Relaxed);
+        self.dropped.store(count.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    /// Push val applying the policy when full. Producer only. Err(val)
+    /// with Reject only
+    #[inline]
+    pub fn push(&self, val: T) -> Result<(), T> {
+        match P::ON_FULL {
+            OnFull::Reject => self.ring.try_push(val).inspect_err(|_| self.count_drop()),
+            OnFull::OverwriteOldest => {
+                self.ring.push_overwrite(val);
+                Ok(())
+            }
+            OnFull::OverwriteNewest => {
+                if self.flush() {
+                    // N - 1 published, the spare slot is the newest one
+                    if self.pending.get() {
+                        self.count_drop();
+                    }
+                    unsafe { self.ring.write_unpublished(val) };
+                    self.pending.set(true);
+                } else {
+                    unsafe { self.ring.write_unpublished(val) };
+                    self.ring.publish();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Publish the newest item held back by OverwriteNewest if the
+    /// consumer made room. Producer only. Returns whether the ring is still
+    /// full, i.e. N - 1 items published
+    #[inline]
+    pub fn flush(&self) -> bool {
+        if self.ring.len() as usize >= N - 1 {
+            return true;
+        }
+        if self.pending.get() {
+            self.ring.publish();
+            self.pending.set(false);
+        }
+        self.ring.len() as usize >= N - 1
+    }
+
+    /// Copy the oldest item out and consume it. Consumer only
+    #[inline]
+    pub fn pop(&self) -> Option<T> {
+        self.ring.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill<P: FullPolicy>(ring: &PolicyRingBufRef<u32, 4, P>) -> [Option<u32>; 5] {
+        for i in 0..6 {
+            let _ = ring.push(i);
+        }
+        core::array::from_fn(|_| ring.pop())
+    }
+
+    #[test]
+    fn policies() {
+        let reject: PolicyRingBufRef<u32, 4, Reject> = PolicyRingBufRef::new();
+        assert!(fill(&reject) == [Some(0), Some(1), Some(2), Some(3), None]);
+        assert!(reject.dropped() == 2 && reject.on_full() == OnFull::Reject);
+
+        let oldest: PolicyRingBufRef<u32, 4, OverwriteOldest> = PolicyRingBufRef::new();
+        assert!(fill(&oldest) == [Some(2), Some(3), Some(4), Some(5), None]);
+        assert!(oldest.dropped() == 2);
+
+        // Newest held back until the consumer makes room
+        let newest: PolicyRingBufRef<u32, 4, OverwriteNewest> = PolicyRingBufRef::new();
+        assert!(fill(&newest) == [Some(0), Some(1), Some(2), None, None]);
+        assert!(!newest.flush());
+        assert!(newest.pop() == Some(5));
+        assert!(newest.dropped() == 2);
+    }
+}
@@ -0,0 +1,170 @@
+//! Fixed object pool recycling buffers between two contexts.
+//!
+//! The payload pool of `SharedPool` without the command queue: the producer
+//! takes a free location by index, fills it and hands the index over by any
+//! means, e.g. a `RingBuf<PoolIndex<N>, M>` or a register. The consumer reads
+//! the location and frees it, its index going back to the producer through
+//! the allocator backend A, a free index ring by default.
+
+use core::cell::Cell;
+
+use crate::pool_alloc::{PoolAlloc, RingAlloc};
+use crate::ringbuf::SplitError;
+use crate::shared_pool::{PoolIndex, SharedPoolError};
+use crate::shared_singleton::SharedSingleton;
+
+pub struct Producer<'a, T, const N: usize, A: PoolAlloc<N>> {
+    pool_alloc: &'a A,
+    slots: &'a [SharedSingleton<T>; N],
+}
+
+impl<'a, T, const N: usize, A: PoolAlloc<N>> Producer<'a, T, N, A> {
+
+    /// Locations free for allocation
+    pub fn free_len(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+
+    // Take a free location, vacant until written with `try_write` and
+    // passed to the consumer with `write_done`
+    pub fn alloc(&mut self) -> Option<(PoolIndex<N>, &'a SharedSingleton<T>)> {
+        let pidx = self.pool_alloc.take()?;
+        Some((pidx, &self.slots[usize::try_from(pidx).ok()?]))
+    }
+}
+
+pub struct Consumer<'a, T, const N: usize, A: PoolAlloc<N>> {
+    pool_alloc: &'a A,
+    slots: &'a [SharedSingleton<T>; N],
+}
+
+impl<'a, T, const N: usize, A: PoolAlloc<N>> Consumer<'a, T, N, A> {
+
+    /// Locations free for allocation
+    pub fn free_len(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+
+    // Location of an index received from the producer, None if invalid
+    pub fn get(&self, pidx: PoolIndex<N>) -> Option<&'a SharedSingleton<T>> {
+        usize::try_from(pidx).ok().map(|idx| &self.slots[idx])
+    }
+
+    // Return a location released with `read_done` to the producer
+    pub fn free(&mut self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError> {
+        match self.get(pidx) {
+            Some(slot) if slot.is_vacant() => self.pool_alloc.give(pidx),
+            _ => Err(SharedPoolError::InvalidState),
+        }
+    }
+}
+
+/// Producer and Consumer pair returned by Pool::split
+pub type PoolHandles<'a, T, const N: usize, A> = (Producer<'a, T, N, A>, Consumer<'a, T, N, A>);
+
+/// Pool of N locations of T, free locations tracked by A
+pub struct Pool<T, const N: usize, A: PoolAlloc<N> = RingAlloc<PoolIndex<N>, N>> {
+    pool_alloc: A,
+    slots: [SharedSingleton<T>; N],
+    has_split_prod: Cell<bool>,
+    has_split_cons: Cell<bool>,
+}
+
+// Locations move between the sides under the owner protocol of
+// SharedSingleton, the allocator is shared as in SharedPool
+unsafe impl<T: Send, const N: usize, A: PoolAlloc<N> + Sync> Sync for Pool<T, N, A> {}
+
+impl<T, const N: usize, A: PoolAlloc<N>> Default for Pool<T, N, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, A: PoolAlloc<N>> Pool<T, N, A> {
+
+    // Allocator starts with no free location, filled when the consumer is
+    // split
+    pub const fn new() -> Self {
+        Pool {
+            pool_alloc: A::INIT,
+            slots: [SharedSingleton::INIT_0; N],
+            has_split_prod: Cell::new(false),
+            has_split_cons: Cell::new(false),
+        }
+    }
+
+    pub fn num_free(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+
+    // Return the producer, once in life time
+    pub fn split_prod(&self) -> Result<Producer<'_, T, N, A>, SplitError> {
+        if self.has_split_prod.get() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        self.has_split_prod.set(true);
+        Ok(Producer { pool_alloc: &self.pool_alloc, slots: &self.slots })
+    }
+
+    // Return the consumer, once in life time
+    pub fn split_cons(&self) -> Result<Consumer<'_, T, N, A>, SplitError> {
+        if self.has_split_cons.get() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        self.has_split_cons.set(true);
+        // Hand all the locations to the producer
+        self.pool_alloc.fill();
+        Ok(Consumer { pool_alloc: &self.pool_alloc, slots: &self.slots })
+    }
+
+    // Split both handles, see `SharedPool::split`
+    pub fn split(&self) -> Result<PoolHandles<'_, T, N, A>, SplitError> {
+        if self.has_split_prod.get() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.has_split_cons.get() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_prod()?, self.split_cons()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool_alloc::{bitmap_words, BitmapAlloc};
+
+    #[test]
+    fn recycle() {
+        let pool: Pool<[u8; 4], 2> = Pool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+        assert!(producer.free_len() == 2);
+
+        let (first, slot) = producer.alloc().unwrap();
+        *slot.try_write().unwrap() = [1; 4];
+        assert!(slot.write_done().is_ok());
+        let (second, _) = producer.alloc().unwrap();
+        assert!(producer.alloc().is_none());
+
+        // Still owned by the consumer
+        let slot = consumer.get(first).unwrap();
+        assert!(consumer.free(first) == Err(SharedPoolError::InvalidState));
+        assert!(*slot.try_read().unwrap() == [1; 4]);
+        assert!(slot.read_done().is_ok());
+        assert!(consumer.free(first).is_ok());
+        assert!(consumer.free(second).is_ok());
+        assert!(pool.num_free() == 2);
+    }
+
+    #[test]
+    fn bitmap_backend() {
+        let pool: Pool<u32, 40, BitmapAlloc<{ bitmap_words(40) }>> = Pool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+        assert!(pool.split().err() == Some(SplitError::AlreadySplitProd));
+        for _ in 0..40 {
+            let (pidx, _) = producer.alloc().unwrap();
+            assert!(consumer.free(pidx).is_ok());
+        }
+        assert!(consumer.free_len() == 40);
+    }
+}
@@ -0,0 +1,276 @@
+//! Free location tracking backends for the SharedPool payload pool.
+//!
+//! The default `RingAlloc` passes free pool indices back to the producer
+//! through a return ring buffer. `BitmapAlloc` trades a find-first-set scan
+//! for the RAM of that ring: two bits per pool location instead of one ring
+//! item (of the message type) per location.
+
+use crate::sync::{AtomicU32, Ordering};
+
+use crate::ringbuf_ref::{RingBufRef, RingCheckpoint};
+use crate::shared_pool::{HasPoolIdx, PoolIndex, SharedPoolError};
+
+/// Tracks which locations of a pool of depth N are free.
+/// `take` is only called from the producer side and `give` only from
+/// the consumer side, following the same SPSC premise as the ring buffer.
+pub trait PoolAlloc<const N: usize> {
+    /// Initial value, all locations owned by nobody until `fill` is called
+    const INIT: Self;
+
+    /// Mark every pool location as free. Called once when the consumer
+    /// is split from the SharedPool
+    fn fill(&self);
+
+    /// Producer side - take a free location if there is one
+    fn take(&self) -> Option<PoolIndex<N>>;
+
+    /// Consumer side - return a location back to the producer
+    fn give(&self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError>;
+
+    /// Number of free locations
+    fn num_free(&self) -> u32;
+
+    /// Plain-old-data copy of the allocator state
+    type Checkpoint: Copy;
+
+    /// Export the allocator state
+    fn checkpoint(&self) -> Self::Checkpoint;
+
+    /// Re-import a state exported by `checkpoint`
+    ///
+    /// # Safety
+    /// Any storage of the allocator must be retained since the checkpoint
+    /// and neither side may be using the pool during the restore
+    unsafe fn restore(&self, cp: &Self::Checkpoint) -> Result<(), SharedPoolError>;
+}
+
+/// Return ring buffer backend. Free locations are passed back through a ring
+/// of M messages, each carrying the pool index.
+pub struct RingAlloc<Q, const M: usize> {
+    ring: RingBufRef<Q, M>,
+}
+
+impl<Q: HasPoolIdx<N>, const N: usize, const M: usize> PoolAlloc<N> for RingAlloc<Q, M> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = {
+        assert!(M >= N, "Ringbuf capacity (M) must be >= Pool Capacity (N)");
+        RingAlloc { ring: RingBufRef::new() }
+    };
+
+    fn fill(&self) {
+        // Pre-fill the return queue with all the pool indices
+        // M >= N is checked at compile time, every index fits
+        for i in 0..N {
+            // Indices are staged in place, see HasPoolIdx
+            if let Some(slot) = self.ring.alloc_uninit() {
+                slot.write(Q::default()).set_pool_idx(PoolIndex(i as u32));
+                let _ = self.ring.commit();
+            }
+        }
+    }
+
+    #[inline]
+    fn take(&self) -> Option<PoolIndex<N>> {
+        let pidx = self.ring.reader_front()?.get_pool_idx();
+        // Pop the return queue
+        self.ring.pop().ok()?;
+        Some(pidx)
+    }
+
+    #[inline]
+    fn give(&self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError> {
+        // Allocation a location in the return queue
+        if let Some(slot) = self.ring.alloc_uninit() {
+            slot.write(Q::default()).set_pool_idx(pidx);
+            self.ring
+                .commit()
+                .map_err(|_| SharedPoolError::ReturnBufFull)
+        } else {
+            Err(SharedPoolError::ReturnBufFull)
+        }
+    }
+
+    #[inline]
+    fn num_free(&self) -> u32 {
+        self.ring.len()
+    }
+
+    type Checkpoint = RingCheckpoint;
+
+    fn checkpoint(&self) -> RingCheckpoint {
+        self.ring.checkpoint()
+    }
+
+    unsafe fn restore(&self, cp: &RingCheckpoint) -> Result<(), SharedPoolError> {
+        self.ring.restore(cp).map_err(|_| SharedPoolError::InvalidState)
+    }
+}
+
+/// Number of u32 words needed by a `BitmapAlloc` for a pool of depth n
+pub const fn bitmap_words(n: usize) -> usize {
+    n.div_ceil(32)
+}
+
+/// Plain-old-data copy of the BitmapAlloc state
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitmapCheckpoint<const W: usize> {
+    pub taken: [u32; W],
+    pub freed: [u32; W],
+}
+
+/// Bitmap backend using W 32-bit words, W must be at least `bitmap_words(N)`.
+///
+/// Each location has a "taken" bit only flipped by the producer and a "freed"
+/// bit only flipped by the consumer. A location is free when both bits are
+/// equal, hence neither side ever writes a word owned by the other. The
+/// consumer publishes a freed bit with Release once done with the location,
+/// acquired by the producer before reusing it.
+pub struct BitmapAlloc<const W: usize> {
+    taken: [AtomicU32; W],
+    freed: [AtomicU32; W],
+}
+
+impl<const W: usize> BitmapAlloc<W> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+
+    // Bits of word w that map to a pool location in [0, N-1]
+    #[inline(always)]
+    const fn valid_mask<const N: usize>(w: usize) -> u32 {
+        let base = w * 32;
+        if base + 32 <= N {
+            u32::MAX
+        } else if base >= N {
+            0
+        } else {
+            (1 << (N - base)) - 1
+        }
+    }
+}
+
+impl<const N: usize, const W: usize> PoolAlloc<N> for BitmapAlloc<W> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = {
+        assert!(W >= bitmap_words(N), "Bitmap words (W) must cover Pool Capacity (N)");
+        let mut taken = [Self::ZERO; W];
+        // Start with every location taken so nothing can be allocated
+        // before the consumer is split, same as the return ring
+        let mut w = 0;
+        while w < W {
+            taken[w] = AtomicU32::new(Self::valid_mask::<N>(w));
+            w += 1;
+        }
+        BitmapAlloc { taken, freed: [Self::ZERO; W] }
+    };
+
+    fn fill(&self) {
+        for w in 0..W {
+            self.freed[w].store(Self::valid_mask::<N>(w), Ordering::Release);
+        }
+    }
+
+    #[inline]
+    fn take(&self) -> Option<PoolIndex<N>> {
+        for w in 0..W {
+            let taken = self.taken[w].load(Ordering::Relaxed);
+            // Consumer accesses of the freed locations happen before
+            let free = !(taken ^ self.freed[w].load(Ordering::Acquire)) & Self::valid_mask::<N>(w);
+            if free != 0 {
+                // Find first set
+                let bit = free.trailing_zeros();
+                self.taken[w].store(taken ^ (1 << bit), Ordering::Release);
+                return Some(PoolIndex((w * 32) as u32 + bit));
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn give(&self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError> {
+        if !pidx.is_valid() {
+            return Err(SharedPoolError::InvalidPoolIndex);
+        }
+        let (w, bit) = ((pidx.0 / 32) as usize, pidx.0 % 32);
+        let freed = self.freed[w].load(Ordering::Relaxed);
+        // Location must currently be taken
+        if (self.taken[w].load(Ordering::Acquire) ^ freed) & (1 << bit) == 0 {
+            return Err(SharedPoolError::InvalidState);
+        }
+        // Done with the location before the producer can take it again
+        self.freed[w].store(freed ^ (1 << bit), Ordering::Release);
+        Ok(())
+    }
+
+    fn num_free(&self) -> u32 {
+        (0..W)
+            .map(|w| {
+                let (taken, freed) = (self.taken[w].load(Ordering::Relaxed), self.freed[w].load(Ordering::Acquire));
+                (!(taken ^ freed) & Self::valid_mask::<N>(w)).count_ones()
+            })
+            .sum()
+    }
+
+    type Checkpoint = BitmapCheckpoint<W>;
+
+    fn checkpoint(&self) -> BitmapCheckpoint<W> {
+        BitmapCheckpoint {
+            taken: core::array::from_fn(|w| self.taken[w].load(Ordering::Acquire)),
+            freed: core::array::from_fn(|w| self.freed[w].load(Ordering::Acquire)),
+        }
+    }
+
+    unsafe fn restore(&self, cp: &BitmapCheckpoint<W>) -> Result<(), SharedPoolError> {
+        // Bits beyond the pool capacity are never flipped
+        for w in 0..W {
+            if (cp.taken[w] | cp.freed[w]) & !Self::valid_mask::<N>(w) != 0 {
+                return Err(SharedPoolError::InvalidState);
+            }
+        }
+        for w in 0..W {
+            self.taken[w].store(cp.taken[w], Ordering::Release);
+            self.freed[w].store(cp.freed[w], Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitmap_take_give() {
+        let bitmap: BitmapAlloc<{ bitmap_words(40) }> = PoolAlloc::<40>::INIT;
+
+        // Nothing to take before fill
+        assert!(PoolAlloc::<40>::take(&bitmap).is_none());
+        PoolAlloc::<40>::fill(&bitmap);
+        assert!(PoolAlloc::<40>::num_free(&bitmap) == 40);
+
+        // Allocates lowest free location first, across word boundary
+        for i in 0..40 {
+            let pidx: PoolIndex<40> = bitmap.take().unwrap();
+            assert!(pidx.0 == i);
+        }
+        assert!(PoolAlloc::<40>::take(&bitmap).is_none());
+
+        assert!(bitmap.give(PoolIndex::<40>(33)).is_ok());
+        assert!(bitmap.give(PoolIndex::<40>(5)).is_ok());
+        assert!(PoolAlloc::<40>::num_free(&bitmap) == 2);
+
+        let pidx: PoolIndex<40> = bitmap.take().unwrap();
+        assert!(pidx.0 == 5);
+        let pidx: PoolIndex<40> = bitmap.take().unwrap();
+        assert!(pidx.0 == 33);
+    }
+
+    #[test]
+    fn bitmap_size() {
+        // 2 bits per location instead of one return message per location
+        assert!(core::mem::size_of::<BitmapAlloc<{ bitmap_words(64) }>>() == 2 * 8);
+    }
+}
@@ -0,0 +1,170 @@
+//! Request/response channel correlating replies with their requests.
+//!
+//! Pairs a request ring towards the responder with a response ring back to
+//! the requester. `send` tags each request with a `Token`, the sequence
+//! number of the request ring, which the responder echoes with its
+//! response. Responses are expected in request order: `poll_response`
+//! drops the responses to older requests the requester gave up on.
+
+use crate::ringbuf::{Consumer, Producer, RingBuf, SplitError};
+
+/// Correlation token of a request, unique over the life of the channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Token(u64);
+
+impl Token {
+    /// Sequence number of the request
+    pub fn seq(&self) -> u64 {
+        self.0
+    }
+}
+
+pub struct Requester<'a, Req: 'a, Resp: 'a, const N: usize, const M: usize> {
+    req_prod: Producer<'a, (Token, Req), N>,
+    resp_cons: Consumer<'a, (Token, Resp), M>,
+}
+
+impl<Req, Resp, const N: usize, const M: usize> Requester<'_, Req, Resp, N, M> {
+
+    /// Requests sent and not answered yet, including the abandoned ones.
+    /// Extra responses from a misbehaving responder count as none pending
+    pub fn pending(&self) -> u64 {
+        self.req_prod.seq().saturating_sub(self.resp_cons.seq())
+    }
+
+    // Queue a request, the request is handed back if the ring is full
+    pub fn send(&mut self, req: Req) -> Result<Token, Req> {
+        let token = Token(self.req_prod.seq());
+        self.req_prod.try_push((token, req)).map_err(|(_, req)| req)?;
+        Ok(token)
+    }
+
+    // Response to the request of token. Responses to older requests at the
+    // head are dropped, None until the response arrives
+    pub fn poll_response(&mut self, token: Token) -> Option<Resp> {
+        loop {
+            let (head, _) = self.resp_cons.reader_front()?;
+            if *head > token {
+                return None;
+            }
+            let (head, resp) = self.resp_cons.pop_value()?;
+            if head == token {
+                return Some(resp);
+            }
+        }
+    }
+
+    // Next response whatever its request
+    pub fn recv_response(&mut self) -> Option<(Token, Resp)> {
+        self.resp_cons.pop_value()
+    }
+}
+
+pub struct Responder<'a, Req: 'a, Resp: 'a, const N: usize, const M: usize> {
+    req_cons: Consumer<'a, (Token, Req), N>,
+    resp_prod: Producer<'a, (Token, Resp), M>,
+}
+
+impl<Req, Resp, const N: usize, const M: usize> Responder<'_, Req, Resp, N, M> {
+
+    // Next request and the token to respond with
+    pub fn recv(&mut self) -> Option<(Token, Req)> {
+        self.req_cons.pop_value()
+    }
+
+    // Answer the request of token, the response is handed back if the
+    // ring is full
+    pub fn respond(&mut self, token: Token, resp: Resp) -> Result<(), Resp> {
+        self.resp_prod.try_push((token, resp)).map_err(|(_, resp)| resp)
+    }
+}
+
+/// Requester and Responder pair returned by RequestChannel::split
+pub type RequestHandles<'a, Req, Resp, const N: usize, const M: usize> =
+    (Requester<'a, Req, Resp, N, M>, Responder<'a, Req, Resp, N, M>);
+
+/// Ring of N requests Req and ring of M responses Resp
+pub struct RequestChannel<Req, Resp, const N: usize, const M: usize> {
+    req_rbuf: RingBuf<(Token, Req), N>,
+    resp_rbuf: RingBuf<(Token, Resp), M>,
+}
+
+impl<Req, Resp, const N: usize, const M: usize> Default for RequestChannel<Req, Resp, N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Req, Resp, const N: usize, const M: usize> RequestChannel<Req, Resp, N, M> {
+
+    pub const fn new() -> Self {
+        RequestChannel {
+            req_rbuf: RingBuf::new(),
+            resp_rbuf: RingBuf::new(),
+        }
+    }
+
+    // Return the requester, once in life time
+    pub fn split_requester(&self) -> Result<Requester<'_, Req, Resp, N, M>, SplitError> {
+        let req_prod = self.req_rbuf.split_prod()?;
+        // Split along with the request ring, cannot fail
+        let resp_cons = self.resp_rbuf.split_cons().unwrap();
+        Ok(Requester { req_prod, resp_cons })
+    }
+
+    // Return the responder, once in life time
+    pub fn split_responder(&self) -> Result<Responder<'_, Req, Resp, N, M>, SplitError> {
+        let req_cons = self.req_rbuf.split_cons()?;
+        // Split along with the request ring, cannot fail
+        let resp_prod = self.resp_rbuf.split_prod().unwrap();
+        Ok(Responder { req_cons, resp_prod })
+    }
+
+    // Split both handles, see `SharedPool::split`
+    pub fn split(&self) -> Result<RequestHandles<'_, Req, Resp, N, M>, SplitError> {
+        if self.req_rbuf.has_split_prod() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.req_rbuf.has_split_cons() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_requester()?, self.split_responder()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correlated_responses() {
+        let channel: RequestChannel<u32, u64, 2, 4> = RequestChannel::new();
+        let (mut requester, mut responder) = channel.split().unwrap();
+        assert!(channel.split().err() == Some(SplitError::AlreadySplitProd));
+
+        let first = requester.send(1).unwrap();
+        let second = requester.send(2).unwrap();
+        assert!(requester.send(3) == Err(3));
+        assert!(requester.poll_response(first).is_none());
+
+        while let Some((token, req)) = responder.recv() {
+            assert!(responder.respond(token, u64::from(req) * 10).is_ok());
+        }
+        // Response to the first request dropped, given up on
+        assert!(requester.pending() == 2);
+        assert!(requester.poll_response(second) == Some(20));
+        assert!(requester.pending() == 0);
+
+        let third = requester.send(3).unwrap();
+        assert!(third > second);
+        let (token, req) = responder.recv().unwrap();
+        assert!(responder.respond(token, u64::from(req)).is_ok());
+        assert!(requester.recv_response() == Some((third, 3)));
+
+        // Answered twice
+        assert!(responder.respond(token, 0).is_ok());
+        assert!(requester.recv_response() == Some((third, 0)));
+        assert!(requester.pending() == 0);
+    }
+}
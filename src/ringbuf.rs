@@ -1,79 +1,970 @@
-use crate::ringbuf_ref::{ErrCode, RingBufRef};
+use crate::backend::{Backend, IndexPair, RingOps};
+use crate::ringbuf_ref::{ErrCode, IndexWidth, RingBufRef, RingCheckpoint, SavedState, WriteGrant};
+use crate::notify::{NoNotify, Notifier};
+#[cfg(feature = "stats")]
+use crate::stats::{RingStats, StatsSnapshot};
+use crate::wait::WaitStrategy;
+use crate::clock::Clock;
 use core::cell::Cell;
+use core::fmt;
+use core::mem::MaybeUninit;
 
+// defmt counterpart of ring_fields, name being the type name
+#[cfg(feature = "defmt")]
+fn ring_format<T, R: RingOps<T>>(f: defmt::Formatter, name: &str, ring: &R, capacity: u32) {
+    let (rd_idx, wr_idx) = ring.indices();
+    defmt::write!(f, "{=str} {{ capacity: {}, rd_idx: {}, wr_idx: {}, len: {}, is_full: {}, is_empty: {} }}",
+        name, capacity, rd_idx, wr_idx, ring.len(), ring.len() >= capacity, ring.is_empty())
+}
+
+// Common Debug fields of the RingBuf and its handles, capacity being the
+// effective one, see RingBuf::set_capacity
+fn ring_fields<T, R: RingOps<T>>(d: &mut fmt::DebugStruct<'_, '_>, ring: &R, capacity: u32) {
+    let (rd_idx, wr_idx) = ring.indices();
+    d.field("capacity", &capacity)
+        .field("rd_idx", &rd_idx)
+        .field("wr_idx", &wr_idx)
+        .field("len", &ring.len())
+        .field("is_full", &(ring.len() >= capacity))
+        .field("is_empty", &ring.is_empty());
+}
+
+// The handles keep a count of the slots (items) known to be writable
+// (readable) and only load the index of the other side once it runs out,
+// saving the cache line transfer on every operation.
+//
+// Both handles are Send for T: Send, through the Sync rings they borrow,
+// and can be moved to the thread or ISR context of their side.
 
-pub struct Producer <'a,T, const N: usize> {
+pub struct Producer <'a,T, const N: usize, B: Backend = IndexPair, Nf: Notifier = NoNotify> {
 
-    inner: &'a RingBufRef<T, N>
+    inner: &'a B::Ring<T, N>,
+    // Vacant slots seen at the last check
+    cached_writable: u32,
+    // Slots beyond the capacity set with RingBuf::set_capacity
+    reserved: u32,
+    watermarks: Watermarks,
+    // Notifier of the ring, fired after items are published
+    notifier: &'a Nf,
+    // Signals the consumer after items are published, see set_on_commit
+    on_commit: Option<&'a (dyn Notifier + Sync)>,
+    // Items ever committed through this handle
+    seq: u64,
+    #[cfg(feature = "stats")]
+    stats: &'a RingStats,
+
+}
+
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> fmt::Debug for Producer<'a, T, N, B, Nf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Producer");
+        ring_fields(&mut d, self.inner, self.capacity());
+        d.field("cached_writable", &self.cached_writable).finish()
+    }
+}
 
+#[cfg(feature = "defmt")]
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> defmt::Format for Producer<'a, T, N, B, Nf> {
+    fn format(&self, f: defmt::Formatter) {
+        ring_format(f, "Producer", self.inner, self.capacity())
+    }
 }
 
-impl<'a, T, const N: usize> Producer<'a, T, N> {
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> Producer<'a, T, N, B, Nf> {
 
+    // Refresh the writable count only when exhausted
     #[inline(always)]
-    pub fn writer_front(&mut self) -> Option<&mut T> { 
-        self.inner.writer_front()
+    fn writable(&mut self) -> u32 {
+        if self.cached_writable == 0 {
+            self.cached_writable = self.inner.writable().saturating_sub(self.reserved);
+        }
+        self.cached_writable
+    }
+
+    // Sequence, statistics and notification hooks
+    #[inline(always)]
+    fn on_push(&mut self, n: u32) {
+        self.seq += u64::from(n);
+        #[cfg(feature = "stats")]
+        self.stats.record_push(n, self.inner.len());
+        if n != 0 {
+            self.notifier.notify();
+            if let Some(hook) = self.on_commit {
+                hook.notify();
+            }
+        }
+    }
+    #[inline(always)]
+    fn on_failed_push(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.record_failed_push();
+    }
+
+    /// Drop the cached writable count, e.g. after `RingBuf::restore`
+    pub fn refresh(&mut self) {
+        self.cached_writable = 0;
+    }
+
+    /// Notify hook after every publication of the write index, grants
+    /// included, e.g. `notify::Sev` to wake the other core or a
+    /// `notify::Doorbell`
+    pub fn set_on_commit(&mut self, hook: Option<&'a (dyn Notifier + Sync)>) {
+        self.on_commit = hook;
+    }
+
+    /// Number of items ever committed by this producer. Unlike the
+    /// indices it does not wrap, stamp it into the items so the consumer
+    /// can detect losses and resets
+    #[inline(always)]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Counters shared with the Consumer
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Number of queued items
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.inner.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Effective capacity, see `RingBuf::set_capacity`
+    #[inline(always)]
+    pub fn capacity(&self) -> u32 {
+        N as u32 - self.reserved
+    }
+
+    /// Vacant slots, capacity minus len
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        self.inner.free_len().saturating_sub(self.reserved)
+    }
+
+    /// Fill level reached the high watermark, time to shed load
+    #[inline(always)]
+    pub fn is_above_high_watermark(&self) -> bool {
+        self.inner.len() >= self.watermarks.high
+    }
+
+    /// Fill level at or below the low watermark
+    #[inline(always)]
+    pub fn is_below_low_watermark(&self) -> bool {
+        self.inner.len() <= self.watermarks.low
+    }
+
+    /// Slot at the write index, published by `commit`. See `alloc` for the
+    /// typestate alternative.
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[inline(always)]
+    pub unsafe fn writer_front(&mut self) -> Option<&mut T> {
+        self.alloc_uninit().map(|m| &mut *m.as_mut_ptr())
+    }
+
+    /// Vacant slot at the write index, to be initialized before `commit`
+    #[inline(always)]
+    pub fn alloc_uninit(&mut self) -> Option<&mut MaybeUninit<T>> {
+        if self.writable() == 0 {
+            None
+        } else {
+            Some(unsafe { self.inner.writer_slot_unchecked() })
+        }
+    }
+
+    /// Initialize the slot at the write index in place and commit it, see
+    /// `RingBufRef::push_with`
+    #[inline(always)]
+    pub fn push_with<F>(&mut self, f: F) -> Result<(), ErrCode>
+    where
+        F: for<'s> FnOnce(&'s mut MaybeUninit<T>) -> &'s mut T,
+    {
+        let Some(slot) = self.alloc_uninit() else {
+            self.on_failed_push();
+            return Err(ErrCode::BufFull);
+        };
+        let ptr = slot.as_mut_ptr();
+        if !core::ptr::eq(f(slot), ptr) {
+            return Err(ErrCode::InvalidState);
+        }
+        unsafe { self.inner.commit_unchecked() };
+        self.cached_writable -= 1;
+        self.on_push(1);
+        Ok(())
     }
 
 
     #[inline(always)]
     pub fn commit(&mut self) -> Result<(), ErrCode> { 
-        self.inner.commit()
+        if self.writable() == 0 {
+            self.on_failed_push();
+            Err(ErrCode::BufFull)
+        } else {
+            unsafe { self.inner.commit_unchecked() };
+            self.cached_writable -= 1;
+            self.on_push(1);
+            Ok(())
+        }
+    }
+
+    /// Move val in and commit it, handing it back when the ring is full
+    #[inline(always)]
+    pub fn try_push(&mut self, val: T) -> Result<(), T> {
+        if self.writable() == 0 {
+            self.on_failed_push();
+            Err(val)
+        } else {
+            unsafe {
+                self.inner.writer_slot_unchecked().write(val);
+                self.inner.commit_unchecked();
+            }
+            self.cached_writable -= 1;
+            self.on_push(1);
+            Ok(())
+        }
+    }
+
+    /// Push val, calling wait while the ring is full
+    #[inline]
+    pub fn push_blocking<W: WaitStrategy>(&mut self, val: T, wait: &mut W) {
+        while self.writable() == 0 {
+            wait.wait();
+            self.cached_writable = self.inner.writable().saturating_sub(self.reserved);
+        }
+        wait.reset();
+        unsafe {
+            self.inner.writer_slot_unchecked().write(val);
+            self.inner.commit_unchecked();
+        }
+        self.cached_writable -= 1;
+        self.on_push(1);
+    }
+
+    /// Push val, waiting with wait while the ring is full for at most
+    /// timeout ticks of clock. The item is handed back on timeout
+    pub fn push_timeout<C: Clock, W: WaitStrategy>(&mut self, val: T, clock: &C, timeout: u32, wait: &mut W) -> Result<(), T> {
+        let start = clock.now();
+        while self.writable() == 0 {
+            if clock.now().wrapping_sub(start) >= timeout {
+                wait.reset();
+                self.on_failed_push();
+                return Err(val);
+            }
+            wait.wait();
+            self.cached_writable = self.inner.writable().saturating_sub(self.reserved);
+        }
+        wait.reset();
+        self.try_push(val)
+    }
+
+    /// Copy as many items of src as fit and commit them, see
+    /// `RingBufRef::push_slice`. Returns the number pushed
+    #[inline(always)]
+    pub fn push_slice(&mut self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let fit = src.len().min(self.free_len() as usize);
+        let n = self.inner.push_slice(&src[..fit]);
+        self.cached_writable = self.cached_writable.saturating_sub(n as u32);
+        if n > 0 {
+            self.on_push(n as u32);
+        }
+        if n < src.len() {
+            self.on_failed_push();
+        }
+        n
     }
 }
 
-pub struct Consumer <'a,T, const N: usize> {
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> Producer<'a, T, N, B, Nf> {
 
-    inner: &'a RingBufRef<T, N>
+    /// Typestate alternative to `writer_front`/`commit`: the vacant slot at
+    /// the write index, to be filled with `WriteSlot::write` and published
+    /// with `FilledSlot::commit`. The slot borrows the Producer, so a second
+    /// alloc cannot alias it and nothing can be committed without a write
+    #[inline(always)]
+    pub fn alloc(&mut self) -> Option<WriteSlot<'_, 'a, T, N, B, Nf>> {
+        if self.writable() == 0 {
+            None
+        } else {
+            Some(WriteSlot { producer: self })
+        }
+    }
+}
+
+/// Vacant slot at the write index, see `Producer::alloc`
+#[must_use = "the slot is only published by write and commit"]
+pub struct WriteSlot<'p, 'a, T, const N: usize, B: Backend = IndexPair, Nf: Notifier = NoNotify> {
+    producer: &'p mut Producer<'a, T, N, B, Nf>,
+}
+
+impl<'p, 'a, T, const N: usize, B: Backend, Nf: Notifier> WriteSlot<'p, 'a, T, N, B, Nf> {
 
+    /// Move val into the slot, not visible to the consumer before commit
+    #[inline(always)]
+    pub fn write(self, val: T) -> FilledSlot<'p, 'a, T, N, B, Nf> {
+        unsafe { self.producer.inner.writer_slot_unchecked().write(val) };
+        FilledSlot { producer: self.producer }
+    }
+}
+
+/// Written slot at the write index. Dropping it without commit drops the
+/// value and leaves the slot vacant
+#[must_use = "the value is dropped unless committed"]
+pub struct FilledSlot<'p, 'a, T, const N: usize, B: Backend = IndexPair, Nf: Notifier = NoNotify> {
+    producer: &'p mut Producer<'a, T, N, B, Nf>,
+}
+
+impl<'p, 'a, T, const N: usize, B: Backend, Nf: Notifier> FilledSlot<'p, 'a, T, N, B, Nf> {
+
+    /// Publish the value to the consumer
+    #[inline(always)]
+    pub fn commit(self) {
+        // The value now belongs to the ring, skip the drop
+        let this = core::mem::ManuallyDrop::new(self);
+        let producer = unsafe { core::ptr::read(&this.producer) };
+        unsafe { producer.inner.commit_unchecked() };
+        producer.cached_writable -= 1;
+        producer.on_push(1);
+    }
 }
 
-impl<'a, T, const N: usize> Consumer<'a, T, N> {
+impl<T, const N: usize, B: Backend, Nf: Notifier> core::ops::Deref for FilledSlot<'_, '_, T, N, B, Nf> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { self.producer.inner.writer_slot_unchecked().assume_init_ref() }
+    }
+}
+
+impl<T, const N: usize, B: Backend, Nf: Notifier> core::ops::DerefMut for FilledSlot<'_, '_, T, N, B, Nf> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.producer.inner.writer_slot_unchecked().assume_init_mut() }
+    }
+}
+
+impl<T, const N: usize, B: Backend, Nf: Notifier> Drop for FilledSlot<'_, '_, T, N, B, Nf> {
+    fn drop(&mut self) {
+        unsafe { self.producer.inner.writer_slot_unchecked().assume_init_drop() };
+    }
+}
+
+/// Contiguous vacant slots granted by `Producer::grant_exact` or
+/// `grant_max`. Committing publishes through the Producer, like `commit`
+pub struct ProducerGrant<'p, 'a, T: 'a, const N: usize, I: IndexWidth + 'a, const P2: bool, Nf: Notifier> {
+    producer: &'p mut Producer<'a, T, N, IndexPair<I, P2>, Nf>,
+    grant: WriteGrant<'p, T, N, I>,
+}
+
+impl<T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> ProducerGrant<'_, '_, T, N, I, P2, Nf> {
+
+    /// Commit the first used items of the grant
+    #[inline]
+    pub fn commit(self, used: usize) {
+        let used = used.min(self.grant.len());
+        self.grant.commit(used);
+        self.producer.on_push(used as u32);
+    }
+
+    /// Copy src to the start of the grant, see `WriteGrant::write_slice`
+    #[inline]
+    pub fn write_slice(&mut self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        self.grant.write_slice(src)
+    }
+}
+
+impl<T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> core::ops::Deref for ProducerGrant<'_, '_, T, N, I, P2, Nf> {
+    type Target = [MaybeUninit<T>];
+
+    fn deref(&self) -> &[MaybeUninit<T>] {
+        &self.grant
+    }
+}
+
+impl<T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> core::ops::DerefMut for ProducerGrant<'_, '_, T, N, I, P2, Nf> {
+    fn deref_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        &mut self.grant
+    }
+}
+
+impl<'a, T: Copy, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> Producer<'a, T, N, IndexPair<I, P2>, Nf> {
+
+    /// Grant exactly n contiguous vacant slots, see `RingBufRef::grant_exact`
+    #[inline(always)]
+    pub fn grant_exact(&mut self, n: usize) -> Result<ProducerGrant<'_, 'a, T, N, I, P2, Nf>, ErrCode> {
+        // The grant commits on the ring directly
+        self.cached_writable = 0;
+        if n > self.free_len() as usize {
+            return Err(ErrCode::BufFull);
+        }
+        let grant = <RingBufRef<T, N, I>>::grant_exact(self.inner, n)?;
+        Ok(ProducerGrant { producer: self, grant })
+    }
+
+    /// Grant up to n contiguous vacant slots, see `RingBufRef::grant_max`
+    #[inline(always)]
+    pub fn grant_max(&mut self, n: usize) -> Result<ProducerGrant<'_, 'a, T, N, I, P2, Nf>, ErrCode> {
+        self.cached_writable = 0;
+        let n = n.min(self.free_len() as usize);
+        if n == 0 {
+            return Err(ErrCode::BufFull);
+        }
+        let grant = <RingBufRef<T, N, I>>::grant_max(self.inner, n)?;
+        Ok(ProducerGrant { producer: self, grant })
+    }
+}
+
+impl<'a, T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> Producer<'a, T, N, IndexPair<I, P2>, Nf> {
+
+    /// Stage n contiguous slots, see `RingBufRef::alloc_n`
+    #[inline(always)]
+    pub fn alloc_n(&mut self, n: usize) -> Option<&mut [MaybeUninit<T>]> {
+        if n > self.free_len() as usize {
+            return None;
+        }
+        <RingBufRef<T, N, I>>::alloc_n(self.inner, n)
+    }
+
+    /// Publish n staged slots with one index update
+    #[inline(always)]
+    pub fn commit_n(&mut self, n: usize) -> Result<(), ErrCode> {
+        if n > self.free_len() as usize {
+            self.on_failed_push();
+            return Err(ErrCode::BufFull);
+        }
+        if let Err(e) = <RingBufRef<T, N, I>>::commit_n(self.inner, n) {
+            self.on_failed_push();
+            return Err(e);
+        }
+        self.cached_writable = self.cached_writable.saturating_sub(n as u32);
+        self.on_push(n as u32);
+        Ok(())
+    }
+}
+
+pub struct Consumer <'a,T, const N: usize, B: Backend = IndexPair, Nf: Notifier = NoNotify> {
+
+    inner: &'a B::Ring<T, N>,
+    // Committed items seen at the last check, a Cell as peeking takes &self
+    cached_readable: Cell<u32>,
+    // Slots beyond the capacity set with RingBuf::set_capacity
+    reserved: u32,
+    watermarks: Watermarks,
+    // Notifier of the ring, fired after items are consumed
+    notifier: &'a Nf,
+    // Items ever popped through this handle
+    seq: u64,
+    #[cfg(feature = "stats")]
+    stats: &'a RingStats,
+
+}
+
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> fmt::Debug for Consumer<'a, T, N, B, Nf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Consumer");
+        ring_fields(&mut d, self.inner, self.capacity());
+        d.field("cached_readable", &self.cached_readable.get()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> defmt::Format for Consumer<'a, T, N, B, Nf> {
+    fn format(&self, f: defmt::Formatter) {
+        ring_format(f, "Consumer", self.inner, self.capacity())
+    }
+}
+
+impl<'a, T, const N: usize, B: Backend, Nf: Notifier> Consumer<'a, T, N, B, Nf> {
+
+    // Refresh the readable count only when exhausted
+    #[inline(always)]
+    fn readable(&self) -> u32 {
+        if self.cached_readable.get() == 0 {
+            self.cached_readable.set(self.inner.readable());
+        }
+        self.cached_readable.get()
+    }
+
+    // Sequence, statistics and notification hooks
+    #[inline(always)]
+    fn on_pop(&mut self, n: u32) {
+        self.seq += u64::from(n);
+        #[cfg(feature = "stats")]
+        self.stats.record_pop(n);
+        if n != 0 {
+            self.notifier.notify();
+        }
+    }
+
+    /// Drop the cached readable count, e.g. after `RingBuf::restore`
+    pub fn refresh(&mut self) {
+        self.cached_readable.set(0);
+    }
+
+    /// Number of items ever popped by this consumer, the `Producer::seq`
+    /// expected in the item at the head
+    #[inline(always)]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Counters shared with the Producer
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Number of queued items
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.inner.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Effective capacity, see `RingBuf::set_capacity`
+    #[inline(always)]
+    pub fn capacity(&self) -> u32 {
+        N as u32 - self.reserved
+    }
+
+    /// Vacant slots, capacity minus len
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        self.inner.free_len().saturating_sub(self.reserved)
+    }
+
+    /// Fill level reached the high watermark
+    #[inline(always)]
+    pub fn is_above_high_watermark(&self) -> bool {
+        self.inner.len() >= self.watermarks.high
+    }
+
+    /// Fill level at or below the low watermark, e.g. to resume a
+    /// throttled producer
+    #[inline(always)]
+    pub fn is_below_low_watermark(&self) -> bool {
+        self.inner.len() <= self.watermarks.low
+    }
 
     #[inline(always)]
     pub fn reader_front(&self) -> Option<&T> {
-        self.inner.reader_front()
+        if self.readable() == 0 {
+            None
+        } else {
+            Some(unsafe { self.inner.reader_front_unchecked() })
+        }
 
     }
     
+    /// Reference to the nth queued item relative to the head, for looking
+    /// ahead before popping
+    #[inline(always)]
+    pub fn peek_at(&self, n: u32) -> Option<&T> {
+        self.inner.peek_at(n)
+    }
+
     #[inline(always)]
     pub fn reader_front_mut(&mut self) -> Option<&mut T> {
 
-        self.inner.reader_front_mut()
+        if self.readable() == 0 {
+            None
+        } else {
+            Some(unsafe { self.inner.reader_front_unchecked() })
+        }
 
     }
 
 
     #[inline(always)]
     pub fn pop(&mut self) -> Result<(), ErrCode> {
-        self.inner.pop()
+        if self.readable() == 0 {
+            Err(ErrCode::BufEmpty)
+        } else {
+            unsafe { self.inner.pop_unchecked() };
+            self.cached_readable.set(self.cached_readable.get() - 1);
+            self.on_pop(1);
+            Ok(())
+        }
+    }
+
+    /// Move the item at the head out and pop it
+    #[inline(always)]
+    pub fn pop_value(&mut self) -> Option<T> {
+        if self.readable() == 0 {
+            None
+        } else {
+            let val = unsafe { core::ptr::read(self.inner.reader_front_unchecked()) };
+            unsafe { self.inner.pop_unchecked() };
+            self.cached_readable.set(self.cached_readable.get() - 1);
+            self.on_pop(1);
+            Some(val)
+        }
+    }
+
+    /// Clone of the item at the head, left in the ring
+    #[inline(always)]
+    pub fn peek_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.reader_front().cloned()
+    }
+
+    /// Clone of the item at the head, then pop it. The queued item itself
+    /// is dropped, see `pop_value` to move it out instead
+    #[inline(always)]
+    pub fn pop_cloned(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let val = self.peek_cloned()?;
+        // Head checked above, the original is dropped here
+        drop(self.pop_value());
+        Some(val)
+    }
+
+    /// Move the item at the head out, calling wait while the ring is empty
+    #[inline]
+    pub fn pop_blocking<W: WaitStrategy>(&mut self, wait: &mut W) -> T {
+        loop {
+            if let Some(val) = self.pop_value() {
+                wait.reset();
+                return val;
+            }
+            wait.wait();
+        }
+    }
+
+    /// Pop the item at the head, waiting with wait while the ring is empty
+    /// for at most timeout ticks of clock. None on timeout
+    pub fn pop_timeout<C: Clock, W: WaitStrategy>(&mut self, clock: &C, timeout: u32, wait: &mut W) -> Option<T> {
+        let start = clock.now();
+        loop {
+            if let Some(val) = self.pop_value() {
+                wait.reset();
+                return Some(val);
+            }
+            if clock.now().wrapping_sub(start) >= timeout {
+                wait.reset();
+                return None;
+            }
+            wait.wait();
+        }
+    }
+
+    /// Copy up to dst.len() items out and pop them, see
+    /// `RingBufRef::pop_slice`. Returns the number popped
+    #[inline(always)]
+    pub fn pop_slice(&mut self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let n = self.inner.pop_slice(dst);
+        self.cached_readable.set(self.cached_readable.get().saturating_sub(n as u32));
+        self.on_pop(n as u32);
+        n
+    }
+
+    /// Copy up to dst.len() items from the head out without popping them,
+    /// see `RingBufRef::copy_to_slice`
+    #[inline(always)]
+    pub fn copy_to_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        self.inner.copy_to_slice(dst)
+    }
+
+    /// Drop all queued items and pop them in one step. Returns the number
+    /// cleared
+    #[inline(always)]
+    pub fn clear(&mut self) -> u32 {
+        self.cached_readable.set(0);
+        let n = self.inner.clear();
+        self.on_pop(n);
+        n
+    }
+
+    /// Drop up to n items at the head and pop them, e.g. to resynchronize
+    /// after a corrupted frame. Returns the number skipped
+    #[inline(always)]
+    pub fn skip(&mut self, n: u32) -> u32 {
+        let n = self.inner.skip(n);
+        self.cached_readable.set(self.cached_readable.get().saturating_sub(n));
+        self.on_pop(n);
+        n
+    }
+
+    /// First queued item matching pred and its position from the head,
+    /// without popping anything
+    pub fn find<P: FnMut(&T) -> bool>(&self, mut pred: P) -> Option<(u32, &T)> {
+        (0..self.len())
+            .filter_map(|n| self.peek_at(n).map(|item| (n, item)))
+            .find(|(_, item)| pred(item))
+    }
+
+    /// Drop and pop the items at the head while pred holds, e.g. until a
+    /// sync byte. Returns the number skipped
+    pub fn skip_while<P: FnMut(&T) -> bool>(&mut self, mut pred: P) -> u32 {
+        let n = (0..self.len())
+            .map_while(|n| self.peek_at(n))
+            .take_while(|item| pred(item))
+            .count();
+        self.skip(n as u32)
+    }
+
+    /// Process up to max items at the head with f and pop them afterwards,
+    /// bounding the work done per call. Returns the number processed
+    #[inline(always)]
+    pub fn process_n<F: FnMut(&mut T)>(&mut self, max: u32, f: F) -> u32 {
+        let n = self.inner.process_n(max, f);
+        self.cached_readable.set(self.cached_readable.get().saturating_sub(n));
+        self.on_pop(n);
+        n
+    }
+}
+
+impl<'a, T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> Consumer<'a, T, N, IndexPair<I, P2>, Nf> {
+
+    /// Queued items as up to two contiguous slices, see
+    /// `RingBufRef::read_slices`
+    #[inline(always)]
+    pub fn read_slices(&self) -> (&[T], &[T]) {
+        <RingBufRef<T, N, I>>::read_slices(self.inner)
+    }
+
+    /// Pop n items at once after reading them through `read_slices`.
+    /// Returns the number released
+    #[inline(always)]
+    pub fn release(&mut self, n: usize) -> usize {
+        let n = <RingBufRef<T, N, I>>::release(self.inner, n);
+        self.cached_readable.set(self.cached_readable.get().saturating_sub(n as u32));
+        self.on_pop(n as u32);
+        n
+    }
+}
+
+/// Plain-old-data copy of the RingBuf control state
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingBufCheckpoint {
+    pub ring: RingCheckpoint,
+    pub has_split_prod: bool,
+    pub has_split_cons: bool,
+}
+
+/// Fill levels checked by `is_above_high_watermark` (len >= high) and
+/// `is_below_low_watermark` (len <= low)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Watermarks {
+    pub low: u32,
+    pub high: u32,
+}
+
+/// Snapshot of the RingBuf control state for failure reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingState {
+    pub rd_idx: u32,
+    pub wr_idx: u32,
+    pub len: u32,
+    pub capacity: u32,
+    pub watermarks: Watermarks,
+    pub has_split_prod: bool,
+    pub has_split_cons: bool,
+}
+
+/// Handle already handed out by an earlier split
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SplitError {
+    AlreadySplitProd,
+    AlreadySplitCons,
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SplitError::AlreadySplitProd => "producer already split",
+            SplitError::AlreadySplitCons => "consumer already split",
+        })
     }
 }
 
+impl core::error::Error for SplitError {}
+
+/// Error together with the state it occurred in, see `RingBuf::diagnose`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Diagnosed<E, S> {
+    pub error: E,
+    pub state: S,
+}
+
+/// Producer and Consumer pair returned by RingBuf::split
+pub type RingBufHandles<'a, T, const N: usize, B, Nf = NoNotify> = (Producer<'a, T, N, B, Nf>, Consumer<'a, T, N, B, Nf>);
+
+/// Producer of a `static` RingBuf, can be moved into threads or tasks
+pub type StaticProducer<T, const N: usize, B = IndexPair, Nf = NoNotify> = Producer<'static, T, N, B, Nf>;
 
-pub struct RingBuf<T, const N: usize> {
+/// Consumer of a `static` RingBuf, can be moved into threads or tasks
+pub type StaticConsumer<T, const N: usize, B = IndexPair, Nf = NoNotify> = Consumer<'static, T, N, B, Nf>;
 
-    ringbuf_ref: RingBufRef<T, N>,
+/// RingBuf rejecting capacities other than a power of two at compile time,
+/// for hot paths that must never take the wrapping branch of other N
+///
+/// ```compile_fail
+/// use spsc_ringbuf_core::ringbuf::RingBufP2;
+/// static RING: RingBufP2<u32, 12> = RingBufP2::new();
+/// ```
+pub type RingBufP2<T, const N: usize, I = u32> = RingBuf<T, N, IndexPair<I, true>>;
+
+/// Ring buffer wrapper handing out a single Producer and Consumer. The inner
+/// ring buffer algorithm is selected by B, see `backend`.
+pub struct RingBuf<T, const N: usize, B: Backend = IndexPair, Nf: Notifier = NoNotify> {
+
+    ringbuf_ref: B::Ring<T, N>,
     has_split_prod: Cell<bool>,
-    has_split_cons: Cell<bool>
+    has_split_cons: Cell<bool>,
+    // Effective capacity, N unless lowered before the producer is split
+    capacity: Cell<u32>,
+    // Clamped to the effective capacity by set_capacity
+    watermarks: Cell<Watermarks>,
+    // Fired towards the consumer after commit and towards the producer
+    // after pop
+    on_commit: Nf,
+    on_pop: Nf,
+    #[cfg(feature = "stats")]
+    stats: RingStats,
 
 }
 
 // Delcare this is thread safe due to the owner protection
 // sequence (Producer-> consumer , consumer -> owner)
-unsafe impl<T, const N: usize> Sync for RingBuf<T, N> {}
+unsafe impl<T: Send, const N: usize, B: Backend, Nf: Notifier + Sync> Sync for RingBuf<T, N, B, Nf> {}
 
-impl<T, const N: usize> RingBuf<T, N> {
+impl<T, const N: usize, B: Backend, Nf: Notifier> fmt::Debug for RingBuf<T, N, B, Nf> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RingBuf");
+        ring_fields(&mut d, &self.ringbuf_ref, self.capacity.get());
+        d.field("has_split_prod", &self.has_split_prod.get())
+            .field("has_split_cons", &self.has_split_cons.get())
+            .finish()
+    }
+}
 
-    pub const INIT_0: RingBuf<T, N> = Self::new();
+/// Queued items as a sequence, oldest first. Same consistency caveat as
+/// `RingBuf::copy_to_slice`
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize, B: Backend, Nf: Notifier> serde::Serialize for RingBuf<T, N, B, Nf> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let ring = &self.ringbuf_ref;
+        serializer.collect_seq((0..ring.len()).filter_map(|n| ring.peek_at(n)))
+    }
+}
 
+#[cfg(feature = "defmt")]
+impl<T, const N: usize, B: Backend, Nf: Notifier> defmt::Format for RingBuf<T, N, B, Nf> {
+    fn format(&self, f: defmt::Formatter) {
+        let ring = &self.ringbuf_ref;
+        let (rd_idx, wr_idx) = ring.indices();
+        let capacity = self.capacity.get();
+        defmt::write!(f, "RingBuf {{ capacity: {}, rd_idx: {}, wr_idx: {}, len: {}, is_full: {}, is_empty: {}, has_split_prod: {}, has_split_cons: {} }}",
+            capacity, rd_idx, wr_idx, ring.len(), ring.len() >= capacity, ring.is_empty(), self.has_split_prod.get(), self.has_split_cons.get())
+    }
+}
+
+impl<T, const N: usize, B: Backend> Default for RingBuf<T, N, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, B: Backend> RingBuf<T, N, B> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: RingBuf<T, N, B> = Self::new();
+
+    /// Watermarks default to empty (low) and full (high)
     pub const fn new() -> Self {
+        Self::with_watermarks(0, N as u32)
+    }
+
+    /// Ring with low and high watermarks, low <= high <= N
+    pub const fn with_watermarks(low: u32, high: u32) -> Self {
+        RingBuf::with_notifiers_and_watermarks(NoNotify, NoNotify, low, high)
+    }
+}
+
+impl<T, const N: usize, B: Backend, Nf: Notifier> RingBuf<T, N, B, Nf> {
+
+    const OK: () = assert!(!B::POWER_OF_TWO || N.is_power_of_two(), "Ringbuf capacity must be a power of two");
+
+    /// Ring calling on_commit after each publication of the write index and
+    /// on_pop after each publication of the read index, e.g. to pend the
+    /// interrupt of the other side
+    pub const fn with_notifiers(on_commit: Nf, on_pop: Nf) -> Self {
+        Self::with_notifiers_and_watermarks(on_commit, on_pop, 0, N as u32)
+    }
+
+    /// See `with_notifiers` and `with_watermarks`
+    #[allow(clippy::let_unit_value)]
+    pub const fn with_notifiers_and_watermarks(on_commit: Nf, on_pop: Nf, low: u32, high: u32) -> Self {
+        let _: () = RingBuf::<T, N, B, Nf>::OK;
+        assert!(low <= high && high as usize <= N, "Watermarks must satisfy low <= high <= N");
         RingBuf {
-            ringbuf_ref: RingBufRef::new(),
+            ringbuf_ref: <B::Ring<T, N> as RingOps<T>>::INIT,
             has_split_prod: Cell::new(false),
-            has_split_cons: Cell::new(false)
+            has_split_cons: Cell::new(false),
+            capacity: Cell::new(N as u32),
+            watermarks: Cell::new(Watermarks { low, high }),
+            on_commit,
+            on_pop,
+            #[cfg(feature = "stats")]
+            stats: RingStats::new(),
         }
     }
+    pub fn watermarks(&self) -> Watermarks {
+        self.watermarks.get()
+    }
+    /// Limit the ring to capacity items, e.g. from a configuration read at
+    /// boot, the storage staying allocated for N. Watermarks above it are
+    /// lowered to it. InvalidState once a handle is split or if capacity
+    /// is 0 or above N
+    pub fn set_capacity(&self, capacity: u32) -> Result<(), ErrCode> {
+        if self.has_split() || capacity == 0 || capacity as usize > N {
+            return Err(ErrCode::InvalidState);
+        }
+        self.capacity.set(capacity);
+        let Watermarks { low, high } = self.watermarks.get();
+        self.watermarks.set(Watermarks { low: low.min(capacity), high: high.min(capacity) });
+        Ok(())
+    }
+    /// Effective capacity, see `set_capacity`
+    pub fn capacity(&self) -> u32 {
+        self.capacity.get()
+    }
     pub fn has_split_prod(&self) -> bool {
         self.has_split_prod.get()
     }
@@ -85,41 +976,185 @@ impl<T, const N: usize> RingBuf<T, N> {
     }
 
     
-    pub fn split_prod(&self) -> Result<Producer<'_, T, N>, ()> {
+    pub fn split_prod(&self) -> Result<Producer<'_, T, N, B, Nf>, SplitError> {
 
         if self.has_split_prod.get() {
             // Can only split once in life time
-            Err(())
+            Err(SplitError::AlreadySplitProd)
         }
         else {
-            let producer = Producer {inner: &self.ringbuf_ref};
+            let producer = Producer {
+                inner: &self.ringbuf_ref,
+                cached_writable: 0,
+                reserved: N as u32 - self.capacity.get(),
+                watermarks: self.watermarks.get(),
+                notifier: &self.on_commit,
+                on_commit: None,
+                seq: 0,
+                #[cfg(feature = "stats")]
+                stats: &self.stats,
+            };
             self.has_split_prod.set(true);
             Ok(producer)
         }
     }
-    pub fn split_cons(&self) -> Result<Consumer<'_, T, N>, ()> {
+    pub fn split_cons(&self) -> Result<Consumer<'_, T, N, B, Nf>, SplitError> {
 
         if self.has_split_cons.get() {
             // Can only split once in life time
-            Err(())
+            Err(SplitError::AlreadySplitCons)
         }
         else {
-            let consumer = Consumer {inner: &self.ringbuf_ref};
+            let consumer = Consumer {
+                inner: &self.ringbuf_ref,
+                cached_readable: Cell::new(0),
+                reserved: N as u32 - self.capacity.get(),
+                watermarks: self.watermarks.get(),
+                notifier: &self.on_pop,
+                seq: 0,
+                #[cfg(feature = "stats")]
+                stats: &self.stats,
+            };
             self.has_split_cons.set(true);
             Ok(consumer)
         }
     }
-    pub fn split(&self) -> Result<(Producer<'_, T, N>, Consumer<'_, T, N>), ()> {
+    /// Split both handles, or neither if one has been split before
+    pub fn split(&self) -> Result<RingBufHandles<'_, T, N, B, Nf>, SplitError> {
+        if self.has_split_prod.get() {
+            return Err(SplitError::AlreadySplitProd);
+        }
+        if self.has_split_cons.get() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_prod()?, self.split_cons()?))
+    }
+    /// Give the producer back, e.g. on a task restart, so that it can be
+    /// split again. Queued items are kept. A producer of another ring is
+    /// handed back
+    pub fn unsplit_prod<'a>(&'a self, producer: Producer<'a, T, N, B, Nf>) -> Result<(), Producer<'a, T, N, B, Nf>> {
+        if !core::ptr::eq(producer.inner, &self.ringbuf_ref) {
+            return Err(producer);
+        }
+        self.has_split_prod.set(false);
+        Ok(())
+    }
 
-        match (self.split_prod(), self.split_cons())  {
-            (Ok(prod), Ok(cons)) => Ok((prod, cons)),
-            _ => Err(())
+    /// Give the consumer back, see `unsplit_prod`
+    pub fn unsplit_cons<'a>(&'a self, consumer: Consumer<'a, T, N, B, Nf>) -> Result<(), Consumer<'a, T, N, B, Nf>> {
+        if !core::ptr::eq(consumer.inner, &self.ringbuf_ref) {
+            return Err(consumer);
         }
+        self.has_split_cons.set(false);
+        Ok(())
+    }
+
+    /// Give both handles back, or neither if one is of another ring
+    pub fn unsplit<'a>(&'a self, producer: Producer<'a, T, N, B, Nf>, consumer: Consumer<'a, T, N, B, Nf>) -> Result<(), RingBufHandles<'a, T, N, B, Nf>> {
+        if !core::ptr::eq(producer.inner, &self.ringbuf_ref) || !core::ptr::eq(consumer.inner, &self.ringbuf_ref) {
+            return Err((producer, consumer));
+        }
+        self.has_split_prod.set(false);
+        self.has_split_cons.set(false);
+        Ok(())
+    }
+
+    /// Split a `static` RingBuf into handles not tied to any stack frame
+    pub fn split_static(&'static self) -> Result<RingBufHandles<'static, T, N, B, Nf>, SplitError> {
+        self.split()
     }
     pub fn len(&self) -> u32 {
         self.ringbuf_ref.len()
     }
+    pub fn free_len(&self) -> u32 {
+        self.ringbuf_ref.free_len().saturating_sub(N as u32 - self.capacity.get())
+    }
+    // Inner ring for wrappers with their own producer or consumer handle
+    pub(crate) fn ring(&self) -> &B::Ring<T, N> {
+        &self.ringbuf_ref
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ringbuf_ref.is_empty()
+    }
+
+    /// Snapshot of the queued items, oldest first, for black-box recorders
+    /// persisting the contents on a fault. Only consistent while the
+    /// handles are idle, e.g. from a fault handler. Returns the number
+    /// copied
+    pub fn copy_to_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        self.ringbuf_ref.copy_to_slice(dst)
+    }
+
+    /// Push/pop counters maintained by the handles
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Capture the control state, a handful of loads
+    pub fn state(&self) -> RingState {
+        let (rd_idx, wr_idx) = self.ringbuf_ref.indices();
+        RingState {
+            rd_idx,
+            wr_idx,
+            len: self.ringbuf_ref.len(),
+            capacity: self.capacity.get(),
+            watermarks: self.watermarks.get(),
+            has_split_prod: self.has_split_prod.get(),
+            has_split_cons: self.has_split_cons.get(),
+        }
+    }
+
+    /// Attach the current state to error, e.g.
+    /// `producer.commit().map_err(|e| RINGBUF.diagnose(e))`
+    pub fn diagnose<E>(&self, error: E) -> Diagnosed<E, RingState> {
+        Diagnosed { error, state: self.state() }
+    }
+
+}
+
+impl<T, const N: usize, I: IndexWidth, const P2: bool, Nf: Notifier> RingBuf<T, N, IndexPair<I, P2>, Nf> {
+
+    /// Export indices and split flags, e.g. to backup RAM before entering
+    /// a low power mode
+    pub fn checkpoint(&self) -> RingBufCheckpoint {
+        RingBufCheckpoint {
+            ring: self.ringbuf_ref.checkpoint(),
+            has_split_prod: self.has_split_prod.get(),
+            has_split_cons: self.has_split_cons.get(),
+        }
+    }
+
+    /// Re-import a checkpoint, see `RingBufRef::restore`
+    ///
+    /// # Safety
+    /// The buffer must hold the items written before the checkpoint and no
+    /// handle may be in use during the restore. Handles split before must
+    /// `refresh` their cached counts afterwards.
+    pub unsafe fn restore(&self, cp: &RingBufCheckpoint) -> Result<(), ErrCode> {
+        self.ringbuf_ref.restore(&cp.ring)?;
+        self.has_split_prod.set(cp.has_split_prod);
+        self.has_split_cons.set(cp.has_split_cons);
+        Ok(())
+    }
+
+    /// Indices with a CRC, see `RingBufRef::save_state`. The split flags
+    /// are not saved, the handles are split again after a reset
+    pub fn save_state(&self) -> SavedState {
+        self.ringbuf_ref.save_state()
+    }
 
+    /// Resume the indices saved by `save_state`, see
+    /// `RingBufRef::restore_state`
+    ///
+    /// # Safety
+    /// Same as `restore`.
+    pub unsafe fn restore_state(&self, state: &SavedState) -> Result<(), ErrCode> {
+        self.ringbuf_ref.restore_state(state)
+    }
 }
 
 #[cfg(test)]
@@ -134,10 +1169,10 @@ mod tests {
         // as stage, commit and pop
         if let Ok((mut producer, mut consumer)) = ringbuf.split() {
             
-            let loc = producer.writer_front();
+            let loc = producer.alloc_uninit();
 
             if let Some(v) = loc {
-                *v = 42;
+                v.write(42);
 
                 assert!(producer.commit().is_ok());
             }
@@ -153,6 +1188,619 @@ mod tests {
             panic!("first split failed!");
         }
 
-        assert!(ringbuf.split().is_err());
+        assert!(ringbuf.split().err() == Some(SplitError::AlreadySplitProd));
+    }
+
+    #[test]
+    fn test_notifiers() {
+        let commits = Cell::new(0);
+        let pops = Cell::new(0);
+        let on_commit = || commits.set(commits.get() + 1);
+        let on_pop = || pops.set(pops.get() + 1);
+        let ringbuf = RingBuf::<u32, 4, IndexPair, &dyn Fn()>::with_notifiers(&on_commit, &on_pop);
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        assert!(producer.push_slice(&[1, 2, 3]) == 3);
+        assert!(producer.try_push(4).is_ok());
+        assert!(producer.try_push(5).is_err());
+        assert!(commits.get() == 2 && pops.get() == 0);
+
+        assert!(consumer.pop_value() == Some(1));
+        assert!(consumer.clear() == 3);
+        assert!(consumer.pop().is_err());
+        assert!(commits.get() == 2 && pops.get() == 2);
+    }
+
+    #[test]
+    fn test_unsplit() {
+        let ringbuf: RingBuf<u32, 4> = RingBuf::new();
+        let other: RingBuf<u32, 4> = RingBuf::new();
+        let (mut producer, consumer) = ringbuf.split().unwrap();
+        assert!(producer.try_push(1).is_ok());
+
+        let (other_producer, other_consumer) = other.split().unwrap();
+        let (producer, _) = ringbuf.unsplit(producer, other_consumer).unwrap_err();
+        assert!(ringbuf.unsplit_prod(other_producer).is_err());
+        assert!(ringbuf.unsplit(producer, consumer).is_ok());
+
+        // Queued items survive the restart
+        let (mut producer, consumer) = ringbuf.split().unwrap();
+        assert!(producer.try_push(2).is_ok());
+        assert!(ringbuf.unsplit_cons(consumer).is_ok());
+        assert!(ringbuf.split().err() == Some(SplitError::AlreadySplitProd));
+        let mut consumer2 = ringbuf.split_cons().unwrap();
+        assert!(consumer2.pop_value() == Some(1) && consumer2.pop_value() == Some(2));
+    }
+
+    #[test]
+    fn test_cloned() {
+        let ringbuf: RingBuf<[u8; 2], 2> = RingBuf::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(consumer.peek_cloned().is_none() && consumer.pop_cloned().is_none());
+
+        assert!(producer.push_slice(&[[1, 2], [3, 4]]) == 2);
+        let head = consumer.peek_cloned().unwrap();
+        assert!(consumer.pop().is_ok());
+        assert!(head == [1, 2]);
+        assert!(consumer.pop_cloned() == Some([3, 4]));
+        assert!(consumer.is_empty());
+
+        // The popped original is dropped, only the clone is left
+        use std::rc::Rc;
+        let tracker = Rc::new(());
+        let ringbuf = RingBuf::<Rc<()>, 2>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(producer.try_push(tracker.clone()).is_ok());
+        let clone = consumer.pop_cloned().unwrap();
+        assert!(Rc::strong_count(&tracker) == 2);
+        drop(clone);
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+
+    #[test]
+    fn test_resync() {
+        let ringbuf: RingBuf<u8, 8> = RingBuf::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(producer.push_slice(&[0x13, 0x37, 0x7e, 1, 2, 0x7e, 3]) == 7);
+
+        assert!(consumer.find(|&b| b == 0x7e) == Some((2, &0x7e)));
+        assert!(consumer.find(|&b| b == 0x55).is_none());
+        assert!(consumer.len() == 7);
+
+        // Skip to the sync byte, then past the frame
+        assert!(consumer.skip_while(|&b| b != 0x7e) == 2);
+        assert!(consumer.pop_value() == Some(0x7e));
+        assert!(consumer.skip_while(|&b| b != 0x7e) == 2);
+        assert!(consumer.skip_while(|_| true) == 2);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_runtime_capacity() {
+        let ringbuf: RingBuf<u8, 8> = RingBuf::new();
+        assert!(ringbuf.set_capacity(9) == Err(ErrCode::InvalidState));
+        assert!(ringbuf.set_capacity(3).is_ok() && ringbuf.capacity() == 3);
+        assert!(ringbuf.watermarks() == Watermarks { low: 0, high: 3 });
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(ringbuf.set_capacity(8) == Err(ErrCode::InvalidState));
+
+        for round in 0..4 {
+            assert!(producer.try_push(round).is_ok());
+            assert!(producer.push_slice(&[round; 4]) == 2);
+            assert!(producer.try_push(round).is_err() && producer.free_len() == 0);
+            assert!(producer.grant_max(2).err() == Some(ErrCode::BufFull));
+            // Full at the effective capacity
+            assert!(producer.is_above_high_watermark() && ringbuf.state().capacity == 3);
+            assert!(format!("{:?}", ringbuf).starts_with("RingBuf { capacity: 3,"));
+            assert!(format!("{:?}", producer).contains("len: 3, is_full: true"));
+            assert!(format!("{:?}", consumer).starts_with("Consumer { capacity: 3,"));
+            assert!(consumer.free_len() == 0);
+            assert!(consumer.len() == 3 && consumer.clear() == 3);
+        }
+        assert!(producer.grant_exact(4).err() == Some(ErrCode::BufFull));
+        assert!(producer.grant_exact(3).is_ok());
+    }
+
+    #[test]
+    fn test_seq() {
+        let ringbuf: RingBuf<u64, 3> = RingBuf::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        // Items stamped with the producer sequence, the consumer skipping
+        // some of them
+        for _ in 0..5 {
+            let seq = producer.seq();
+            assert!(producer.push_slice(&[seq, seq + 1, seq + 2]) == 3);
+            assert!(consumer.reader_front() == Some(&consumer.seq()));
+            assert!(consumer.skip(2) == 2);
+            let expected = consumer.seq();
+            assert!(consumer.pop_value() == Some(expected));
+        }
+        assert!(producer.try_push(0).is_ok());
+        assert!(producer.seq() == 16 && consumer.seq() == 15);
+    }
+
+    #[test]
+    fn test_split_error() {
+        let ringbuf: RingBuf<u32, 4> = RingBuf::new();
+        let _consumer = ringbuf.split_cons().unwrap();
+        // Failed split leaves the producer side available
+        assert!(ringbuf.split().err() == Some(SplitError::AlreadySplitCons));
+        assert!(ringbuf.split_prod().is_ok());
+        assert!(ringbuf.split_prod().err() == Some(SplitError::AlreadySplitProd));
+    }
+
+    #[test]
+    fn test_slot_seq_backend() {
+        use crate::backend::SlotSeq;
+
+        // Same call sites as the default backend
+        let ringbuf = RingBuf::<u32, 3, SlotSeq>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        for i in 0..7 {
+            producer.alloc_uninit().unwrap().write(i);
+            assert!(producer.commit().is_ok());
+            assert!(*consumer.reader_front().unwrap() == i);
+            assert!(consumer.pop().is_ok());
+        }
+        assert!(ringbuf.is_empty());
+    }
+
+    #[test]
+    fn test_process_n() {
+        let ringbuf = RingBuf::<u32, 5>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        let mut expected = 0;
+        for round in 0..4 {
+            for i in 0..4 {
+                producer.alloc_uninit().unwrap().write(round * 4 + i);
+                assert!(producer.commit().is_ok());
+            }
+            // Bounded batches across the wrap
+            assert!(consumer.process_n(3, |v| { assert!(*v == expected); expected += 1; }) == 3);
+            assert!(consumer.process_n(3, |v| { assert!(*v == expected); expected += 1; }) == 1);
+            assert!(ringbuf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let ringbuf = RingBuf::<u32, 3>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        for i in 0..5 {
+            producer.alloc_uninit().unwrap().write(i);
+            assert!(producer.commit().is_ok());
+            assert!(consumer.pop().is_ok());
+        }
+        producer.alloc_uninit().unwrap().write(42);
+        assert!(producer.commit().is_ok());
+
+        let cp = ringbuf.checkpoint();
+        assert!(cp.has_split_prod && cp.has_split_cons);
+
+        // Control state moves on, buffer is retained
+        assert!(consumer.pop().is_ok());
+        assert!(unsafe { ringbuf.restore(&cp) }.is_ok());
+        producer.refresh();
+        consumer.refresh();
+        assert!(*consumer.reader_front().unwrap() == 42);
+
+        // Inconsistent indices are rejected
+        let mut bad = cp;
+        bad.ring = RingCheckpoint { rd_idx: 0, wr_idx: 4 };
+        assert!(unsafe { ringbuf.restore(&bad) }.is_err());
+        bad.ring = RingCheckpoint { rd_idx: 6, wr_idx: 6 };
+        assert!(unsafe { ringbuf.restore(&bad) }.is_err());
+        assert!(ringbuf.checkpoint() == cp);
+    }
+
+    #[test]
+    fn test_state() {
+        let ringbuf = RingBuf::<u32, 2>::new();
+        let mut producer = ringbuf.split_prod().unwrap();
+
+        assert!(producer.commit().is_ok());
+        assert!(producer.commit().is_ok());
+        let diag = producer.commit().map_err(|e| ringbuf.diagnose(e)).unwrap_err();
+        assert!(matches!(diag.error, ErrCode::BufFull));
+        assert!(diag.state == RingState {
+            rd_idx: 0,
+            wr_idx: 2,
+            len: 2,
+            capacity: 2,
+            watermarks: Watermarks { low: 0, high: 2 },
+            has_split_prod: true,
+            has_split_cons: false,
+        });
+    }
+
+    #[test]
+    fn test_watermarks() {
+        let ringbuf = RingBuf::<u32, 5>::with_watermarks(1, 4);
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        assert!(producer.is_below_low_watermark() && !consumer.is_above_high_watermark());
+        for i in 0..4 {
+            assert!(!producer.is_above_high_watermark());
+            assert!(producer.push_slice(&[i]) == 1);
+            assert!(producer.is_below_low_watermark() == (i == 0));
+        }
+        assert!(producer.is_above_high_watermark() && consumer.is_above_high_watermark());
+        assert!(producer.free_len() == 1 && consumer.len() == 4 && ringbuf.free_len() == 1);
+
+        while !consumer.is_below_low_watermark() {
+            assert!(consumer.pop().is_ok());
+        }
+        assert!(ringbuf.len() == 1 && !producer.is_above_high_watermark());
+        assert!(ringbuf.state().watermarks == Watermarks { low: 1, high: 4 });
+    }
+
+    #[test]
+    fn test_pop_value_clear() {
+        use std::rc::Rc;
+
+        let ringbuf = RingBuf::<Rc<u32>, 2>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        let tracker = Rc::new(7);
+
+        for _ in 0..3 {
+            unsafe { core::ptr::write(producer.writer_front().unwrap(), tracker.clone()) };
+            assert!(producer.commit().is_ok());
+            // Ownership is moved out, no clone or leak
+            let val = consumer.pop_value().unwrap();
+            assert!(*val == 7 && Rc::strong_count(&tracker) == 2);
+            drop(val);
+            assert!(Rc::strong_count(&tracker) == 1);
+        }
+        assert!(consumer.pop_value().is_none());
+
+        // Clear drops what is left
+        for _ in 0..2 {
+            unsafe { core::ptr::write(producer.writer_front().unwrap(), tracker.clone()) };
+            assert!(producer.commit().is_ok());
+        }
+        assert!(Rc::strong_count(&tracker) == 3);
+        assert!(consumer.clear() == 2);
+        assert!(Rc::strong_count(&tracker) == 1);
+        assert!(consumer.reader_front().is_none());
+    }
+
+    #[test]
+    fn test_debug() {
+        let ringbuf = RingBuf::<u32, 4>::new();
+        let (mut producer, consumer) = ringbuf.split().unwrap();
+        assert!(producer.commit().is_ok());
+        assert!(format!("{:?}", ringbuf) == "RingBuf { capacity: 4, rd_idx: 0, wr_idx: 1, len: 1, \
+            is_full: false, is_empty: false, has_split_prod: true, has_split_cons: true }");
+        assert!(format!("{:?}", producer).ends_with("cached_writable: 3 }"));
+        assert!(format!("{:?}", consumer).starts_with("Consumer { capacity: 4, rd_idx: 0"));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        use crate::backend::SlotSeq;
+
+        fn check<B: Backend>() {
+            let ringbuf = RingBuf::<u32, 4, B>::new();
+            let (mut producer, mut consumer) = ringbuf.split().unwrap();
+            assert!(producer.push_slice(&[1, 2, 3]) == 3);
+            assert!(consumer.pop().is_ok());
+            assert!(producer.push_slice(&[4, 5]) == 2);
+
+            let mut out = [0; 8];
+            assert!(ringbuf.copy_to_slice(&mut out) == 4);
+            assert!(out[..4] == [2, 3, 4, 5]);
+            assert!(consumer.copy_to_slice(&mut out[..2]) == 2 && ringbuf.len() == 4);
+            #[cfg(feature = "serde")]
+            assert!(serde_json::to_string(&ringbuf).unwrap() == "[2,3,4,5]");
+        }
+        check::<IndexPair>();
+        check::<SlotSeq>();
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        use crate::stats::StatsSnapshot;
+
+        let ringbuf = RingBuf::<u32, 4>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(producer.push_slice(&[1, 2, 3]) == 3);
+        assert!(consumer.pop_value() == Some(1));
+        assert!(producer.push_slice(&[4, 5, 6]) == 2);
+        assert!(producer.commit().is_err());
+        assert!(consumer.skip(2) == 2);
+
+        let expected = StatsSnapshot { pushes: 5, pops: 3, failed_pushes: 2, high_water: 4 };
+        assert!(ringbuf.stats() == expected);
+        assert!(producer.stats() == expected && consumer.stats() == expected);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        use crate::backend::SlotSeq;
+
+        fn assert_send<S: Send>() {}
+        fn assert_sync<S: Sync>() {}
+        assert_sync::<RingBuf<std::boxed::Box<u32>, 4>>();
+        assert_sync::<RingBuf<std::boxed::Box<u32>, 4, SlotSeq>>();
+        assert_send::<Producer<'static, std::boxed::Box<u32>, 4>>();
+        assert_send::<Consumer<'static, std::boxed::Box<u32>, 4>>();
+        assert_send::<Producer<'static, std::boxed::Box<u32>, 4, SlotSeq>>();
+        assert_send::<Consumer<'static, std::boxed::Box<u32>, 4, SlotSeq>>();
+    }
+
+    #[test]
+    fn test_try_push() {
+        use std::boxed::Box;
+
+        let ringbuf = RingBuf::<Box<u32>, 1>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        assert!(producer.try_push(Box::new(1)).is_ok());
+        // Handed back for the retry
+        let val = producer.try_push(Box::new(2)).unwrap_err();
+        assert!(*consumer.pop_value().unwrap() == 1);
+        assert!(producer.try_push(val).is_ok());
+        assert!(*consumer.pop_value().unwrap() == 2);
+    }
+
+    #[test]
+    fn test_skip() {
+        use std::rc::Rc;
+        use crate::backend::SlotSeq;
+
+        fn check<B: Backend>() {
+            let ringbuf = RingBuf::<Rc<u32>, 4, B>::new();
+            let (mut producer, mut consumer) = ringbuf.split().unwrap();
+            let tracker = Rc::new(0);
+            for _ in 0..3 {
+                unsafe { core::ptr::write(producer.writer_front().unwrap(), tracker.clone()) };
+                assert!(producer.commit().is_ok());
+            }
+            // Skipped items are dropped
+            assert!(consumer.skip(2) == 2);
+            assert!(Rc::strong_count(&tracker) == 2);
+            assert!(consumer.reader_front().is_some());
+            assert!(consumer.skip(5) == 1);
+            assert!(consumer.skip(1) == 0 && consumer.is_empty());
+            assert!(Rc::strong_count(&tracker) == 1);
+        }
+        check::<IndexPair>();
+        check::<SlotSeq>();
+    }
+
+    #[test]
+    fn test_push_pop_slice() {
+        let ringbuf = RingBuf::<u8, 6>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        assert!(producer.push_slice(b"abcd") == 4);
+        assert!(consumer.process_n(3, |_| {}) == 3);
+        // Wraps around the end of the buffer, truncated to the free space
+        assert!(producer.push_slice(b"efghijk") == 5);
+        assert!(producer.push_slice(b"x") == 0);
+        let mut out = [0u8; 8];
+        assert!(consumer.pop_slice(&mut out[..2]) == 2);
+        // Wraps around as well, limited to the queued items
+        assert!(consumer.pop_slice(&mut out[2..]) == 4);
+        assert!(&out[..6] == b"defghi");
+        assert!(consumer.pop_slice(&mut out) == 0);
+    }
+
+    #[test]
+    fn test_write_grant() {
+        let ringbuf = RingBuf::<u8, 8>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        let mut grant = producer.grant_exact(5).unwrap();
+        assert!(grant.write_slice(b"hello!") == 5);
+        grant.commit(5);
+        // Published like commit
+        assert!(producer.seq() == 5);
+        assert!(consumer.pop_slice(&mut [0; 4]) == 4);
+
+        // 7 vacant but only 3 contiguous up to the buffer end
+        assert!(producer.grant_exact(4).is_err());
+        let mut grant = producer.grant_max(4).unwrap();
+        assert!(grant.len() == 3);
+        grant[0].write(b'!');
+        grant.commit(1);
+        // Uncommitted grant is discarded
+        assert!(producer.grant_max(8).unwrap().len() == 2);
+        assert!(ringbuf.len() == 2);
+
+        let mut out = [0; 2];
+        assert!(consumer.pop_slice(&mut out) == 2);
+        assert!(&out == b"o!");
+    }
+
+    #[test]
+    fn test_grant_hooks() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        let notified = Cell::new(0);
+        let notifier = || notified.set(notified.get() + 1);
+        let commits = AtomicU32::new(0);
+        let on_commit = || {
+            commits.fetch_add(1, Ordering::Relaxed);
+        };
+        let ringbuf = RingBuf::<u8, 8, IndexPair, &dyn Fn()>::with_notifiers(&notifier, &notifier);
+        let (mut producer, _consumer) = ringbuf.split().unwrap();
+        producer.set_on_commit(Some(&on_commit));
+
+        producer.grant_exact(3).unwrap().commit(3);
+        // Nothing published, nothing signaled
+        producer.grant_max(4).unwrap().commit(0);
+        assert!(producer.seq() == 3 && ringbuf.len() == 3);
+        assert!(notified.get() == 1 && commits.load(Ordering::Relaxed) == 1);
+    }
+
+    #[test]
+    fn test_read_slices() {
+        let ringbuf = RingBuf::<u8, 6>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        assert!(consumer.read_slices() == (&[][..], &[][..]));
+        assert!(producer.push_slice(b"abcd") == 4);
+        assert!(consumer.read_slices() == (&b"abcd"[..], &[][..]));
+        assert!(consumer.release(3) == 3);
+
+        assert!(producer.push_slice(b"efghi") == 5);
+        assert!(consumer.read_slices() == (&b"def"[..], &b"ghi"[..]));
+        assert!(consumer.release(10) == 6);
+        assert!(consumer.reader_front().is_none());
+    }
+
+    #[test]
+    fn test_peek_at() {
+        use crate::backend::SlotSeq;
+
+        let ringbuf = RingBuf::<u32, 4>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        let seq_ringbuf = RingBuf::<u32, 4, SlotSeq>::new();
+        let (mut seq_producer, mut seq_consumer) = seq_ringbuf.split().unwrap();
+
+        for i in 0..6 {
+            assert!(producer.push_slice(&[i]) == 1);
+            assert!(seq_producer.push_slice(&[i]) == 1);
+            if i < 3 {
+                assert!(consumer.pop().is_ok());
+                assert!(seq_consumer.pop().is_ok());
+            }
+        }
+        // Items 3..6 queued across the buffer end
+        for n in 0..3 {
+            assert!(*consumer.peek_at(n).unwrap() == 3 + n);
+            assert!(*seq_consumer.peek_at(n).unwrap() == 3 + n);
+        }
+        assert!(consumer.peek_at(3).is_none());
+        assert!(seq_consumer.peek_at(3).is_none());
+        assert!(seq_consumer.peek_at(7).is_none());
+    }
+
+    #[test]
+    fn test_split_static() {
+        static RINGBUF: RingBuf<u32, 4> = RingBuf::new();
+
+        let (mut producer, mut consumer) = RINGBUF.split_static().unwrap();
+        assert!(RINGBUF.split_static().is_err());
+
+        let p_handle = std::thread::spawn(move || {
+            for i in 0..100 {
+                producer.push_blocking(i, &mut std::thread::yield_now);
+            }
+        });
+        let c_handle = std::thread::spawn(move || {
+            let mut wait = crate::wait::SpinThen::new(100, std::thread::yield_now);
+            for i in 0..100 {
+                assert!(consumer.pop_blocking(&mut wait) == i);
+            }
+        });
+        p_handle.join().unwrap();
+        c_handle.join().unwrap();
+    }
+
+    struct TestClock(Cell<u32>);
+
+    impl crate::clock::Clock for TestClock {
+        fn now(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let ringbuf = RingBuf::<u32, 1>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+        // Each wait advances the clock by 10 ticks, wrapping on the way
+        let clock = TestClock(Cell::new(u32::MAX - 15));
+        let mut waits = 0;
+        let mut wait = || {
+            waits += 1;
+            clock.0.set(clock.0.get().wrapping_add(10));
+        };
+
+        assert!(consumer.pop_timeout(&clock, 25, &mut wait).is_none());
+        assert!(producer.push_timeout(1, &clock, 0, &mut wait).is_ok());
+        assert!(producer.push_timeout(2, &clock, 30, &mut wait) == Err(2));
+        assert!(consumer.pop_timeout(&clock, 0, &mut wait) == Some(1));
+        assert!(waits == 6);
+    }
+
+    #[test]
+    fn test_narrow_index() {
+        let ringbuf = RingBuf::<u32, 4, IndexPair<u16>>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        for i in 0..70000 {
+            assert!(producer.push_slice(&[i, i + 1]) == 2);
+            assert!(consumer.pop_value() == Some(i));
+            assert!(consumer.pop_value() == Some(i + 1));
+        }
+        assert!(ringbuf.state().wr_idx < u16::MAX as u32);
+    }
+
+    #[test]
+    fn test_write_slot() {
+        use std::rc::Rc;
+
+        let tracker = Rc::new(());
+        let ringbuf = RingBuf::<Rc<()>, 2>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        producer.alloc().unwrap().write(tracker.clone()).commit();
+        // Abandoned after the write, the value is dropped and nothing queued
+        let slot = producer.alloc().unwrap().write(tracker.clone());
+        assert!(Rc::strong_count(&*slot) == 3);
+        drop(slot);
+        assert!(Rc::strong_count(&tracker) == 2 && consumer.len() == 1);
+
+        producer.alloc().unwrap().write(tracker.clone()).commit();
+        assert!(producer.alloc().is_none());
+        assert!(consumer.pop_value().is_some() && consumer.pop_value().is_some());
+        assert!(Rc::strong_count(&tracker) == 1);
+    }
+
+    #[test]
+    fn test_push_with() {
+        let ringbuf = RingBuf::<[u32; 4], 2>::new();
+        let (mut producer, mut consumer) = ringbuf.split().unwrap();
+
+        assert!(producer.push_with(|slot| slot.write([1, 2, 3, 4])).is_ok());
+        let slot = producer.alloc_uninit().unwrap();
+        slot.write([5; 4]);
+        assert!(producer.commit().is_ok());
+        assert!(producer.push_with(|slot| slot.write([0; 4])) == Err(ErrCode::BufFull));
+
+        assert!(consumer.pop_value() == Some([1, 2, 3, 4]));
+        // Only a reference to the slot proves the write
+        static OTHER: RingBufRef<[u32; 4], 1> = RingBufRef::new();
+        assert!(producer.push_with(|_| OTHER.alloc_uninit().unwrap().write([9; 4])) == Err(ErrCode::InvalidState));
+        assert!(consumer.pop_value() == Some([5; 4]));
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_power_of_two() {
+        static RINGBUF: RingBufP2<u32, 8> = RingBufP2::new();
+        // Start just short of the u32 wrap
+        let start = u32::MAX - 20;
+        assert!(unsafe { RINGBUF.restore(&RingBufCheckpoint {
+            ring: RingCheckpoint { rd_idx: start, wr_idx: start },
+            has_split_prod: false,
+            has_split_cons: false,
+        }) }.is_ok());
+        let (mut producer, mut consumer) = RINGBUF.split().unwrap();
+        let mut out = [0; 5];
+        for i in 0..100 {
+            assert!(producer.push_slice(&[i; 5]) == 5);
+            assert!(consumer.pop_slice(&mut out) == 5 && out == [i; 5]);
+        }
+        // Indices wrap over the full u32 range
+        assert!(RINGBUF.state().wr_idx == start.wrapping_add(500));
+        assert!(RINGBUF.is_empty());
     }
 }
@@ -189,4 +189,71 @@ struct RingBufRef {
             return BufEmpty;
         }
     }
-//}
\ No newline at end of file
+//}
+
+/// Shared memory ring, the C side of spsc_ringbuf_core::shmem::SharedMemRing.
+/// Assumes uint32 is 4 bytes wide on the target. Storage of capacity
+/// items of elem_size bytes follows the header, rounded up to the
+/// alignment of the item.
+#define SHM_MAGIC 0x53505343u
+#define SHM_VERSION 1u
+
+struct ShmRingHeader {
+    uint32 magic;
+    uint32 version;
+    uint32 capacity;
+    uint32 elem_size;
+    struct Index rd_idx;
+    struct Index wr_idx;
+};
+
+    STATIC_INLINE void shm_init(struct ShmRingHeader *hdr, uint32 capacity, uint32 elem_size) {
+        hdr->rd_idx.cell = 0;
+        hdr->wr_idx.cell = 0;
+        hdr->capacity = capacity;
+        hdr->elem_size = elem_size;
+        hdr->version = SHM_VERSION;
+        hdr->magic = SHM_MAGIC;
+    }
+
+    /// Layout validation, same checks as SharedMemRing::attach
+    STATIC_INLINE boolean shm_attach(struct ShmRingHeader *hdr, uint32 capacity, uint32 elem_size) {
+        uint32 n = capacity;
+        uint32 limit = is_power_of_two(n) ? 0 : 2 * n;
+        if (hdr->magic != SHM_MAGIC || hdr->version != SHM_VERSION ||
+            hdr->capacity != capacity || hdr->elem_size != elem_size) {
+            return 0;
+        }
+        if (limit && (hdr->rd_idx.cell >= limit || hdr->wr_idx.cell >= limit)) {
+            return 0;
+        }
+        return wrap_dist(&hdr->wr_idx, &hdr->rd_idx, n) <= n;
+    }
+
+    STATIC_INLINE enum ErrCode shm_push(struct ShmRingHeader *hdr, uint8 *storage, uint8 *val_bytes) {
+        uint32 n = hdr->capacity;
+        if (wrap_dist(&hdr->wr_idx, &hdr->rd_idx, n) == n) {
+            return BufFull;
+        }
+        uint32 wr_loc = mask(&hdr->wr_idx, n);
+        for (uint32 i=0; i< hdr->elem_size; i++) {
+            storage[wr_loc*hdr->elem_size+i] = val_bytes[i];
+        }
+        wrap_inc(&hdr->wr_idx, n);
+        return Ok;
+    }
+
+    STATIC_INLINE uint8 * shm_reader_front(struct ShmRingHeader *hdr, uint8 *storage) {
+        if (hdr->rd_idx.cell == hdr->wr_idx.cell) {
+            return 0;
+        }
+        return &storage[mask(&hdr->rd_idx, hdr->capacity)*hdr->elem_size];
+    }
+
+    STATIC_INLINE enum ErrCode shm_pop(struct ShmRingHeader *hdr) {
+        if (hdr->rd_idx.cell == hdr->wr_idx.cell) {
+            return BufEmpty;
+        }
+        wrap_inc(&hdr->rd_idx, hdr->capacity);
+        return Ok;
+    }
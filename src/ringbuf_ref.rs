@@ -1,33 +1,163 @@
 //! Fixed capacity Single Producer Single Consumer Ringbuffer with no mutex protection.
 //! Implementation based on https://www.snellman.net/blog/archive/2016-12-13-ring-buffers/
 
+//!
+//! Each index is only modified by its owning side and published with a
+//! release store. The other side reads it with an acquire load, so items
+//! written before a commit are visible after the consumer observes it, and
+//! items read before a pop are done with before the producer reuses the
+//! slot. Only atomic load/store is used, available on all targets
+//! including thumbv6m.
+
+use core::fmt;
 use core::mem::MaybeUninit;
-use core::{cell::Cell, cell::UnsafeCell};
+use core::ops::{Deref, DerefMut};
+
+#[cfg(not(loom))]
+use crate::crc::crc32;
+use crate::sync::{index_barrier, AtomicU16, AtomicU32, AtomicU8, Ordering, UnsafeCell};
+#[cfg(not(loom))]
+use crate::shmem::{check_region, ShmError};
+
+/// Index holder aligned to a cache line with the `cache-padded` feature, so
+/// that the producer and consumer indices do not share a line. Transparent
+/// otherwise
+#[cfg_attr(feature = "cache-padded", repr(align(64)))]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Integer type storing the ring indices: u8, u16 or u32 (the default).
+/// Narrower indices save RAM on small MCUs and match shared memory layouts
+/// with 16 bit indices, capacity is limited to half the type range
+pub trait IndexWidth {
+    type Atomic;
+    /// Zero valued index storage
+    #[cfg(not(loom))]
+    const ZERO: Self::Atomic;
+    /// loom atomics cannot be created in const context
+    #[cfg(loom)]
+    fn zero() -> Self::Atomic;
+    /// All ones of the width
+    const MASK: u32;
+
+    fn load(cell: &Self::Atomic, order: Ordering) -> u32;
+    fn store(cell: &Self::Atomic, val: u32, order: Ordering);
+}
+
+macro_rules! impl_index_width {
+    ($int:ty, $atomic:ty) => {
+        impl IndexWidth for $int {
+            type Atomic = $atomic;
+            #[cfg(not(loom))]
+            #[allow(clippy::declare_interior_mutable_const)]
+            const ZERO: $atomic = <$atomic>::new(0);
+            #[cfg(loom)]
+            fn zero() -> $atomic {
+                <$atomic>::new(0)
+            }
+            const MASK: u32 = <$int>::MAX as u32;
+
+            #[inline(always)]
+            fn load(cell: &$atomic, order: Ordering) -> u32 {
+                cell.load(order) as u32
+            }
+            #[inline(always)]
+            fn store(cell: &$atomic, val: u32, order: Ordering) {
+                cell.store(val as $int, order)
+            }
+        }
+    };
+}
+
+impl_index_width!(u8, AtomicU8);
+impl_index_width!(u16, AtomicU16);
+impl_index_width!(u32, AtomicU32);
 
 /// Internal Index struct emcapsulating masking and wrapping operations
 /// according to size const size N. Note that we deliberately use u32
-/// to limit the index to 4 bytes and max supported capacity to 2^31-1
-#[derive(Eq, PartialEq)]
-pub struct Index<const RANGE: usize> {
-    cell: Cell<u32>,
+/// to limit the index to 4 bytes and max supported capacity to 2^31-1,
+/// or narrower with I, see `IndexWidth`. Laid out as the bare integer
+#[repr(transparent)]
+pub struct Index<const RANGE: usize, I: IndexWidth = u32> {
+    cell: I::Atomic,
+}
+
+impl<const N: usize, I: IndexWidth> PartialEq for Index<N, I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<const N: usize, I: IndexWidth> Eq for Index<N, I> {}
+
+impl<const N: usize, I: IndexWidth> Default for Index<N, I> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ErrCode {
     BufFull,
     BufEmpty,
+    InvalidState,
+    /// Per-item CRC mismatch, see `checked`
+    CorruptEntry,
 }
 
-impl<const N: usize> Index<N> {
+impl fmt::Display for ErrCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrCode::BufFull => "ring buffer full",
+            ErrCode::BufEmpty => "ring buffer empty",
+            ErrCode::InvalidState => "invalid ring buffer state",
+            ErrCode::CorruptEntry => "ring buffer entry corrupt",
+        })
+    }
+}
 
-    const OK: () = assert!(N < (u32::MAX/2) as usize, "Ringbuf capacity must be < u32::MAX/2");
+impl core::error::Error for ErrCode {}
+
+/// Plain-old-data copy of the RingBufRef control state, see
+/// `RingBufRef::checkpoint`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RingCheckpoint {
+    pub rd_idx: u32,
+    pub wr_idx: u32,
+}
+
+/// Checkpoint sealed with a CRC-32, see `RingBufRef::save_state`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SavedState {
+    pub ring: RingCheckpoint,
+    pub crc: u32,
+}
+
+impl<const N: usize, I: IndexWidth> Index<N, I> {
+
+    const OK: () = assert!(N < (I::MASK/2) as usize, "Ringbuf capacity must be < half the index range");
 
     #[inline(always)]
     pub fn wrap_inc(&self) {
 
         let n = N as u32;
-        // Wrapping increment by 1 first
-        let val = self.cell.get().wrapping_add(1);
+        // Wrapping increment by 1 first. Only the owner modifies the index
+        let val = I::load(&self.cell, Ordering::Relaxed).wrapping_add(1);
+        // Slot accesses complete before the index is published
+        index_barrier();
 
         // Wrap index between [0, 2*N-1]
         // For power 2 of values, the natural overflow wrap
@@ -35,37 +165,62 @@ impl<const N: usize> Index<N> {
         // below is not required for power of 2 N
         if !n.is_power_of_two() && val > 2 * n - 1 {
             // val = val - 2*N
-            self.cell.set(val.wrapping_sub(2 * n));
+            I::store(&self.cell, val.wrapping_sub(2 * n), Ordering::Release);
         } else {
-            self.cell.set(val);
+            I::store(&self.cell, val, Ordering::Release);
         }
     }
     
+    /// Wrapping increment by n, n must not exceed N
     #[inline(always)]
-    pub fn wrap_dist(&self, val: &Index<N>) -> u32 {
+    pub fn wrap_add(&self, n: u32) {
+
+        let range = N as u32;
+        let val = I::load(&self.cell, Ordering::Relaxed).wrapping_add(n);
+        index_barrier();
+
+        // Same as wrap_inc, value is at most 3*N-2 before the wrap
+        if !range.is_power_of_two() && val > 2 * range - 1 {
+            I::store(&self.cell, val.wrapping_sub(2 * range), Ordering::Release);
+        } else {
+            I::store(&self.cell, val, Ordering::Release);
+        }
+    }
+
+    #[inline(always)]
+    pub fn wrap_dist(&self, val: &Index<N, I>) -> u32 {
         
-        let n = N as u32;
         // If N is power of two, just return wrapp_sub(val)
         // If N is not power of two, wrap value between [0, 2*N-1]
         // Assumes current value is in the range of [-2*N, 4*N-1]
         // Not asserting here since we only take Index, which cannot be
         // incremented beyong 2*N-1
-        let raw = self.cell.get().wrapping_sub(val.get());
+        Self::dist(self.get(), val.get())
+    }
+
+    // Distance of raw index values, see wrap_dist
+    #[inline(always)]
+    fn dist(to: u32, from: u32) -> u32 {
+        let n = N as u32;
+        let raw = to.wrapping_sub(from);
         if !n.is_power_of_two() {
             if (raw as i32) < 0 {
                 return raw.wrapping_add(2 * n);
             } else if raw > 2 * n - 1 {
                 return raw.wrapping_sub(2 * n);
             }
+            raw
+        } else {
+            // Natural wrap of the index width
+            raw & I::MASK
         }
-        raw
     }
 
     // Mask the value for indexing [0, N-1]
     #[inline(always)]
     pub fn mask(&self) -> u32 {
         let n = N as u32;
-        let val = self.cell.get();
+        let val = self.get();
         if n.is_power_of_two() {
             val & (n - 1)
         } else if val > n - 1 {
@@ -77,58 +232,199 @@ impl<const N: usize> Index<N> {
 
     #[inline(always)]
     pub fn get(&self) -> u32 {
-        self.cell.get()
+        let val = I::load(&self.cell, Ordering::Acquire);
+        // Index seen before the slots it covers are accessed
+        index_barrier();
+        val
+    }
+
+    // Set the raw value, caller must check it with is_valid
+    #[inline(always)]
+    pub(crate) fn set(&self, val: u32) {
+        index_barrier();
+        I::store(&self.cell, val, Ordering::Release)
+    }
+
+    /// Whether val is reachable by the index, i.e. any value of the index
+    /// width for power of two N, [0, 2*N-1] otherwise
+    #[inline(always)]
+    pub fn is_valid(val: u32) -> bool {
+        let n = N as u32;
+        if n.is_power_of_two() {
+            val <= I::MASK
+        } else {
+            val < 2 * n
+        }
+    }
+
+    /// Whether raw values wr and rd form a consistent index pair, i.e. at
+    /// most N apart
+    #[inline(always)]
+    pub fn is_valid_pair(wr: u32, rd: u32) -> bool {
+        Self::is_valid(wr) && Self::is_valid(rd) && Self::dist(wr, rd) as usize <= N
     }
     
+    #[cfg(not(loom))]
     #[allow(clippy::let_unit_value)]
     #[inline(always)]
-    pub const fn new(val: u32) -> Self {
-        let _: () = Index::<N>::OK;
+    pub const fn new() -> Self {
+        let _: () = Index::<N, I>::OK;
+        Index {
+            cell: I::ZERO,
+        }
+    }
+
+    #[cfg(loom)]
+    #[allow(clippy::let_unit_value)]
+    pub fn new() -> Self {
+        let _: () = Index::<N, I>::OK;
         Index {
-            cell: Cell::new(val),
+            cell: I::zero(),
         }
     }
 }
 
+/// Contiguous vacant region of a RingBufRef handed out for writing, see
+/// `RingBufRef::grant_max`. The slots are uninitialized, the used ones must
+/// be written before the commit. Dropping it without commit commits nothing
+pub struct WriteGrant<'a, T, const N: usize, I: IndexWidth = u32> {
+    wr_idx: &'a Index<N, I>,
+    buf: &'a mut [MaybeUninit<T>],
+}
+
+impl<'a, T, const N: usize, I: IndexWidth> WriteGrant<'a, T, N, I> {
+    /// Commit the first used items of the grant
+    #[inline]
+    pub fn commit(self, used: usize) {
+        self.wr_idx.wrap_add(used.min(self.buf.len()) as u32);
+    }
+
+    /// Copy src to the start of the grant, up to the grant length.
+    /// Returns the number of items written
+    #[inline]
+    pub fn write_slice(&mut self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let n = src.len().min(self.buf.len());
+        // MaybeUninit<T> has the layout of T
+        unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), self.buf.as_mut_ptr() as *mut T, n) };
+        n
+    }
+}
+
+impl<'a, T, const N: usize, I: IndexWidth> Deref for WriteGrant<'a, T, N, I> {
+    type Target = [MaybeUninit<T>];
+
+    fn deref(&self) -> &[MaybeUninit<T>] {
+        self.buf
+    }
+}
+
+impl<'a, T, const N: usize, I: IndexWidth> DerefMut for WriteGrant<'a, T, N, I> {
+    fn deref_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        self.buf
+    }
+}
+
 /// A ring buffer of capacity N holding items of type T.
-/// Non power-of-two N is supported but less efficient.
-pub struct RingBufRef<T, const N: usize> {
+/// Non power-of-two N is supported but less efficient. The index integer
+/// type is selected by I, see `IndexWidth`.
+/// A zero sized T such as `()` leaves only the indices, the ring then
+/// counts up to N pending signals.
+pub struct RingBufRef<T, const N: usize, I: IndexWidth = u32> {
     // this is from where we dequeue items
-    rd_idx: Index<N>,
+    rd_idx: CachePadded<Index<N, I>>,
     //  where we enqueue new items
-    wr_idx: Index<N>,
+    wr_idx: CachePadded<Index<N, I>>,
     // this is the backend array
     buffer_ucell: [UnsafeCell<MaybeUninit<T>>; N],
 }
 // Delcare this is thread safe due to the owner protection
 // sequence (Producer-> consumer , consumer -> owner)
-unsafe impl<T, const N: usize> Sync for RingBufRef<T, N> {}
+unsafe impl<T: Send, const N: usize, I: IndexWidth> Sync for RingBufRef<T, N, I> {}
 
-impl<T, const N: usize> RingBufRef<T, N> {
+impl<T, const N: usize, I: IndexWidth> Default for RingBufRef<T, N, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, I: IndexWidth> fmt::Debug for RingBufRef<T, N, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (rd_idx, wr_idx) = self.indices();
+        f.debug_struct("RingBufRef")
+            .field("capacity", &N)
+            .field("rd_idx", &rd_idx)
+            .field("wr_idx", &wr_idx)
+            .field("len", &self.len())
+            .field("is_full", &self.is_full())
+            .field("is_empty", &self.is_empty())
+            .finish()
+    }
+}
+
+/// Queued items as a sequence, oldest first
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize, I: IndexWidth> serde::Serialize for RingBufRef<T, N, I> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq((0..self.len()).filter_map(|n| self.peek_at(n)))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T, const N: usize, I: IndexWidth> defmt::Format for RingBufRef<T, N, I> {
+    fn format(&self, f: defmt::Formatter) {
+        let (rd_idx, wr_idx) = self.indices();
+        defmt::write!(f, "RingBufRef {{ capacity: {}, rd_idx: {}, wr_idx: {}, len: {}, is_full: {}, is_empty: {} }}",
+            N, rd_idx, wr_idx, self.len(), self.is_full(), self.is_empty())
+    }
+}
+
+impl<T, const N: usize, I: IndexWidth> RingBufRef<T, N, I> {
     // Need to prevent N = 0 instances since the code would compile but crash
     // on the 2*N-1 usize subtracts
     // https://users.rust-lang.org/t/how-do-i-static-assert-a-property-of-a-generic-u32-parameter/76307/2
     const OK: () = assert!(N > 0, "Ringbuf capacity must be larger than 0!");
 
+    // No storage behind the locations of a zero sized T
+    const IS_ZST: bool = core::mem::size_of::<T>() == 0;
+
+    #[cfg(not(loom))]
+    #[allow(clippy::declare_interior_mutable_const)]
     const INIT_U: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
-    pub const INIT_0: RingBufRef<T, N> = Self::new();
+    #[cfg(not(loom))]
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: RingBufRef<T, N, I> = Self::new();
 
+    #[cfg(not(loom))]
     #[allow(clippy::let_unit_value)]
     #[inline]
     pub const fn new() -> Self {
         // This dummy statement evaluates the assert to prevent 0 sized RingBufRef
         // from being compiled.
-        let _: () = RingBufRef::<T, N>::OK;
+        let _: () = RingBufRef::<T, N, I>::OK;
         RingBufRef {
-            rd_idx: Index::new(0),
-            wr_idx: Index::new(0),
+            rd_idx: CachePadded(Index::new()),
+            wr_idx: CachePadded(Index::new()),
             buffer_ucell: [Self::INIT_U; N],
         }
     }
 
+    #[cfg(loom)]
+    #[allow(clippy::let_unit_value)]
+    pub fn new() -> Self {
+        let _: () = RingBufRef::<T, N, I>::OK;
+        RingBufRef {
+            rd_idx: CachePadded(Index::new()),
+            wr_idx: CachePadded(Index::new()),
+            buffer_ucell: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+        }
+    }
+
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.rd_idx == self.wr_idx
+        *self.rd_idx == *self.wr_idx
     }
 
     #[inline(always)]
@@ -147,6 +443,151 @@ impl<T, const N: usize> RingBufRef<T, N> {
         N
     }
 
+    /// Vacant locations, capacity minus len
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        N as u32 - self.len()
+    }
+
+    /// Raw (read, write) indices
+    #[inline(always)]
+    pub fn indices(&self) -> (u32, u32) {
+        (self.rd_idx.get(), self.wr_idx.get())
+    }
+
+    /// Export the read and write indices
+    pub fn checkpoint(&self) -> RingCheckpoint {
+        RingCheckpoint {
+            rd_idx: self.rd_idx.get(),
+            wr_idx: self.wr_idx.get(),
+        }
+    }
+
+    /// Re-import indices exported by `checkpoint`. The indices are checked
+    /// for consistency, InvalidState is returned otherwise.
+    ///
+    /// # Safety
+    /// The buffer must hold the items written before the checkpoint, and
+    /// neither side may be accessing the ring during the restore.
+    pub unsafe fn restore(&self, cp: &RingCheckpoint) -> Result<(), ErrCode> {
+        if !Index::<N, I>::is_valid_pair(cp.wr_idx, cp.rd_idx) {
+            return Err(ErrCode::InvalidState);
+        }
+        self.rd_idx.set(cp.rd_idx);
+        self.wr_idx.set(cp.wr_idx);
+        Ok(())
+    }
+
+    // CRC-32 over the indices and the ring layout, so that a state saved
+    // by a firmware with another N or T is rejected
+    #[cfg(not(loom))]
+    fn state_crc(cp: &RingCheckpoint) -> u32 {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&cp.rd_idx.to_le_bytes());
+        bytes[4..8].copy_from_slice(&cp.wr_idx.to_le_bytes());
+        bytes[8..12].copy_from_slice(&(N as u32).to_le_bytes());
+        bytes[12..16].copy_from_slice(&(core::mem::size_of::<T>() as u32).to_le_bytes());
+        crc32(&bytes)
+    }
+
+    /// Checkpoint with a CRC, to keep next to a ring in no-init or
+    /// battery-backed RAM and resume it with `restore_state` after a reset
+    #[cfg(not(loom))]
+    pub fn save_state(&self) -> SavedState {
+        let ring = self.checkpoint();
+        SavedState { ring, crc: Self::state_crc(&ring) }
+    }
+
+    /// Re-import a state exported by `save_state`. InvalidState if the CRC
+    /// or the indices do not match, e.g. RAM lost on power up, in which case
+    /// the ring should be cleared instead.
+    ///
+    /// # Safety
+    /// Same as `restore`.
+    #[cfg(not(loom))]
+    pub unsafe fn restore_state(&self, state: &SavedState) -> Result<(), ErrCode> {
+        if state.crc != Self::state_crc(&state.ring) {
+            return Err(ErrCode::InvalidState);
+        }
+        self.restore(&state.ring)
+    }
+
+    /// Create an empty ring in the len bytes at ptr, e.g. memory mapped
+    /// with mmap or at a fixed address shared with another core. The
+    /// storage is left uninitialized, only the indices are written. Both
+    /// sides must be built with the same layout of Self, see `shmem` for a
+    /// ring shared with C.
+    ///
+    /// # Safety
+    /// ptr must be valid for reads and writes of len bytes for 'a and not
+    /// accessed by the other side before this returns.
+    #[cfg(not(loom))]
+    pub unsafe fn from_raw_parts<'a>(ptr: *mut u8, len: usize) -> Result<&'a Self, ShmError> {
+        check_region::<Self>(ptr, len)?;
+        let ring = ptr as *mut Self;
+        core::ptr::addr_of_mut!((*ring).rd_idx).write(CachePadded(Index::new()));
+        core::ptr::addr_of_mut!((*ring).wr_idx).write(CachePadded(Index::new()));
+        Ok(&*ring)
+    }
+
+    /// Attach to a ring created with `from_raw_parts` by the other side,
+    /// InvalidState if its indices are inconsistent
+    ///
+    /// # Safety
+    /// ptr must be valid for reads and writes of len bytes for 'a.
+    #[cfg(not(loom))]
+    pub unsafe fn attach<'a>(ptr: *mut u8, len: usize) -> Result<&'a Self, ShmError> {
+        check_region::<Self>(ptr, len)?;
+        let ring = &*(ptr as *const Self);
+        let (rd_idx, wr_idx) = ring.indices();
+        if !Index::<N, I>::is_valid_pair(wr_idx, rd_idx) {
+            return Err(ShmError::InvalidState);
+        }
+        Ok(ring)
+    }
+
+    /// Reference to the nth queued item relative to the read index, for
+    /// looking ahead before popping
+    #[inline(always)]
+    pub fn peek_at(&self, n: u32) -> Option<&T> {
+        if n < self.len() {
+            let slot = (self.rd_idx.mask() as usize + n as usize) % N;
+            Some(unsafe { self.slot_ref(slot) })
+        } else {
+            None
+        }
+    }
+
+    // Buffer location of the write index
+    #[inline(always)]
+    pub(crate) fn wr_slot(&self) -> usize {
+        self.wr_idx.mask() as usize
+    }
+
+    // Reference to the buffer location, caller must make sure the
+    // location has been written
+    #[inline(always)]
+    pub(crate) unsafe fn slot_ref(&self, slot: usize) -> &T {
+        &*self.buf_ptr(slot)
+    }
+
+    // Pointer to the buffer location, derived from the whole array so that
+    // consecutive locations can be copied in one go
+    #[inline(always)]
+    #[cfg(not(loom))]
+    fn buf_ptr(&self, slot: usize) -> *mut T {
+        // Any aligned pointer is valid for a zero sized T, skip the offset
+        if Self::IS_ZST {
+            return core::ptr::NonNull::dangling().as_ptr();
+        }
+        unsafe { UnsafeCell::raw_get(self.buffer_ucell.as_ptr().add(slot)) as *mut T }
+    }
+    // Only the accessed slot is checked by loom
+    #[cfg(loom)]
+    fn buf_ptr(&self, slot: usize) -> *mut T {
+        self.buffer_ucell[slot].get() as *mut T
+    }
+
     /// Returns the write index location as mutable reference.
     /// The Result<> return enforces handling of return type
     /// I.e. if user does not check for push success, the compiler
@@ -154,18 +595,49 @@ impl<T, const N: usize> RingBufRef<T, N> {
     /// Calling stage twice without commit in between results in the same
     /// location written! We could add some protection by remembering this
     /// during alloc but this will incur runtime cost
+    /// Prefer `alloc_uninit` or `push_with`.
+    ///
+    /// # Safety
+    /// The slot may be uninitialized. It must be initialized through the
+    /// reference without reading or dropping the old content, e.g. with
+    /// `core::ptr::write`, before it is read or committed.
+    #[allow(clippy::mut_from_ref)]
     #[inline(always)]
-    pub fn writer_front(&self) -> Option<&mut T> {
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        self.alloc_uninit().map(|m| &mut *m.as_mut_ptr())
+    }
+
+    /// Returns the vacant slot at the write index, to be initialized before
+    /// `commit`. Same caveat as `writer_front` about calling it twice
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn alloc_uninit(&self) -> Option<&mut MaybeUninit<T>> {
         if !self.is_full() {
             // buffer_ucell contains UnsafeCell<MaybeUninit<T>>
             // UnsafeCell's get is defined as "fn get(&self) -> *mut T"
-            let m: *mut MaybeUninit<T> = self.buffer_ucell[self.wr_idx.mask() as usize].get();
-            let t: &mut T = unsafe { &mut *(m as *mut T) };
-            Some(t)
+            Some(unsafe { self.writer_slot_unchecked() })
         } else {
             None
         }
     }
+
+    /// Initialize the slot at the write index in place with f and commit it.
+    /// f proves the initialization by returning the reference obtained from
+    /// `MaybeUninit::write`; any other reference is rejected with
+    /// InvalidState and nothing is committed
+    #[inline(always)]
+    pub fn push_with<F>(&self, f: F) -> Result<(), ErrCode>
+    where
+        F: for<'s> FnOnce(&'s mut MaybeUninit<T>) -> &'s mut T,
+    {
+        let slot = self.alloc_uninit().ok_or(ErrCode::BufFull)?;
+        let ptr = slot.as_mut_ptr();
+        if !core::ptr::eq(f(slot), ptr) {
+            return Err(ErrCode::InvalidState);
+        }
+        self.wr_idx.wrap_inc();
+        Ok(())
+    }
     /// Commit whatever at the write index location by moving the write index
     #[inline(always)]
     pub fn commit(&self) -> Result<(), ErrCode> {
@@ -182,20 +654,123 @@ impl<T, const N: usize> RingBufRef<T, N> {
     /// compiler copies T)
     #[inline(always)]
     pub fn push(&self, val: T) -> Result<(), ErrCode> {
-        if !self.is_full() {
-            // buffer_ucell contains UnsafeCell<MaybeUninit<T>>
-            // UnsafeCell's get is defined as "fn get(&self) -> *mut T"
-            // * (* mut T) deference allows the MaybeUninit.write() to be called to
-            // Set the value
-            unsafe {
-                (*self.buffer_ucell[self.wr_idx.mask() as usize].get()).write(val);
+        self.try_push(val).map_err(|_| ErrCode::BufFull)
+    }
+
+    /// Same as `push`, handing val back when full so that the caller can
+    /// retry with it instead of losing it
+    #[inline(always)]
+    pub fn try_push(&self, val: T) -> Result<(), T> {
+        match self.alloc_uninit() {
+            Some(slot) => {
+                slot.write(val);
+                self.wr_idx.wrap_inc();
+                Ok(())
             }
-            self.wr_idx.wrap_inc();
+            None => Err(val),
+        }
+    }
+    /// Copy as many items of src as fit, in at most two copies, and commit
+    /// them with a single write index update. Returns the number pushed
+    #[inline]
+    pub fn push_slice(&self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let n = src.len().min(self.free_len() as usize);
+        let start = self.wr_idx.mask() as usize;
+        let first = n.min(N - start);
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), self.buf_ptr(start), first);
+            core::ptr::copy_nonoverlapping(src.as_ptr().add(first), self.buf_ptr(0), n - first);
+        }
+        self.wr_idx.wrap_add(n as u32);
+        n
+    }
+
+    // Copy src into the vacant locations starting offset items past the
+    // write index without publishing them, producer only. False if they do
+    // not fit
+    #[cfg(all(not(loom), feature = "log"))]
+    pub(crate) fn stage_slice(&self, offset: usize, src: &[T]) -> bool
+    where
+        T: Copy,
+    {
+        if offset + src.len() > self.free_len() as usize {
+            return false;
+        }
+        let start = (self.wr_idx.mask() as usize + offset) % N;
+        let first = src.len().min(N - start);
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), self.buf_ptr(start), first);
+            core::ptr::copy_nonoverlapping(src.as_ptr().add(first), self.buf_ptr(0), src.len() - first);
+        }
+        true
+    }
+
+    // Vacant locations from the write index up to the buffer end
+    #[inline(always)]
+    fn contiguous_free(&self) -> usize {
+        let start = self.wr_idx.mask() as usize;
+        (N - self.len() as usize).min(N - start)
+    }
+
+    /// Grant exactly n contiguous vacant locations starting at the write
+    /// index. Fails with BufFull if they are not available, also when the
+    /// vacant space wraps around the buffer end. Same caveat as
+    /// `writer_front`, only the producer may hold a grant
+    #[inline]
+    pub fn grant_exact(&self, n: usize) -> Result<WriteGrant<'_, T, N, I>, ErrCode>
+    where
+        T: Copy,
+    {
+        if n > self.contiguous_free() {
+            return Err(ErrCode::BufFull);
+        }
+        let ptr = self.buf_ptr(self.wr_idx.mask() as usize) as *mut MaybeUninit<T>;
+        let buf = unsafe { core::slice::from_raw_parts_mut(ptr, n) };
+        Ok(WriteGrant { wr_idx: &self.wr_idx, buf })
+    }
+
+    /// Grant up to n contiguous vacant locations starting at the write
+    /// index, stopping at the buffer end. Fails with BufFull if none
+    #[inline]
+    pub fn grant_max(&self, n: usize) -> Result<WriteGrant<'_, T, N, I>, ErrCode>
+    where
+        T: Copy,
+    {
+        match n.min(self.contiguous_free()) {
+            0 => Err(ErrCode::BufFull),
+            n => self.grant_exact(n),
+        }
+    }
+
+    /// Returns n contiguous vacant locations from the write index to stage
+    /// several items, to be initialized before they are published together
+    /// by `commit_n`. None if they are not available, also when the vacant
+    /// space wraps around the buffer end. Same caveat as `alloc_uninit`
+    /// about calling it twice
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    pub fn alloc_n(&self, n: usize) -> Option<&mut [MaybeUninit<T>]> {
+        if n > self.contiguous_free() {
+            None
+        } else {
+            let ptr = self.buf_ptr(self.wr_idx.mask() as usize) as *mut MaybeUninit<T>;
+            Some(unsafe { core::slice::from_raw_parts_mut(ptr, n) })
+        }
+    }
+    /// Commit n locations from the write index with a single index update
+    #[inline(always)]
+    pub fn commit_n(&self, n: usize) -> Result<(), ErrCode> {
+        if n <= N - self.len() as usize {
+            self.wr_idx.wrap_add(n as u32);
             Ok(())
         } else {
             Err(ErrCode::BufFull)
         }
     }
+
     /// Returns an Option of reference to location at read index
     #[inline(always)]
     pub fn reader_front(&self) -> Option<&T> {
@@ -208,6 +783,7 @@ impl<T, const N: usize> RingBufRef<T, N> {
         }
     }
     /// Returns an Option of mutable reference to location at read index
+    #[allow(clippy::mut_from_ref)]
     #[inline(always)]
     pub fn reader_front_mut(&self) -> Option<&mut T> {
         if self.is_empty() {
@@ -219,6 +795,40 @@ impl<T, const N: usize> RingBufRef<T, N> {
         }
     }
 
+    // Unchecked counterparts for handles tracking the writable/readable
+    // counts themselves. Callers must have seen a non-zero count
+    #[inline(always)]
+    pub(crate) fn writable(&self) -> u32 {
+        self.free_len()
+    }
+    #[inline(always)]
+    pub(crate) fn readable(&self) -> u32 {
+        self.len()
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn writer_front_unchecked(&self) -> &mut T {
+        &mut *self.writer_slot_unchecked().as_mut_ptr()
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn writer_slot_unchecked(&self) -> &mut MaybeUninit<T> {
+        &mut *self.buffer_ucell[self.wr_idx.mask() as usize].get()
+    }
+    #[inline(always)]
+    pub(crate) unsafe fn commit_unchecked(&self) {
+        self.wr_idx.wrap_inc();
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn reader_front_unchecked(&self) -> &mut T {
+        &mut *(self.buffer_ucell[self.rd_idx.mask() as usize].get() as *mut T)
+    }
+    #[inline(always)]
+    pub(crate) unsafe fn pop_unchecked(&self) {
+        self.rd_idx.wrap_inc();
+    }
+
     /// Consume the item at rd_idx
     #[inline(always)]
     pub fn pop(&self) -> Result<(), ErrCode> {
@@ -229,19 +839,129 @@ impl<T, const N: usize> RingBufRef<T, N> {
             Err(ErrCode::BufEmpty)
         }
     }
+
+    /// Queued items from the read index as up to two contiguous slices,
+    /// the second one non-empty when they wrap around the buffer end
+    #[inline]
+    pub fn read_slices(&self) -> (&[T], &[T]) {
+        let n = self.len() as usize;
+        let start = self.rd_idx.mask() as usize;
+        let first = n.min(N - start);
+        unsafe {
+            (
+                core::slice::from_raw_parts(self.buf_ptr(start), first),
+                core::slice::from_raw_parts(self.buf_ptr(0), n - first),
+            )
+        }
+    }
+
+    /// Consume n items at once, at most the number queued. Returns the
+    /// number released
+    #[inline]
+    pub fn release(&self, n: usize) -> usize {
+        let n = n.min(self.len() as usize);
+        self.rd_idx.wrap_add(n as u32);
+        n
+    }
+
+    /// Move the item at rd_idx out and consume it
+    #[inline(always)]
+    pub fn pop_value(&self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let x: *mut MaybeUninit<T> = self.buffer_ucell[self.rd_idx.mask() as usize].get();
+            let val = unsafe { (*x).assume_init_read() };
+            self.rd_idx.wrap_inc();
+            Some(val)
+        }
+    }
+
+    /// Copy up to dst.len() items out, in at most two copies, and consume
+    /// them with a single read index update. Returns the number popped
+    #[inline]
+    pub fn pop_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let n = self.copy_to_slice(dst);
+        self.rd_idx.wrap_add(n as u32);
+        n
+    }
+
+    /// Copy up to dst.len() queued items out, oldest first, without
+    /// consuming them, e.g. to persist the contents on a fault. Returns
+    /// the number copied
+    #[inline]
+    pub fn copy_to_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let n = dst.len().min(self.len() as usize);
+        let start = self.rd_idx.mask() as usize;
+        let first = n.min(N - start);
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.buf_ptr(start), dst.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(self.buf_ptr(0), dst.as_mut_ptr().add(first), n - first);
+        }
+        n
+    }
+
+    /// Drop all queued items in place and consume them with a single read
+    /// index update. Returns the number cleared
+    #[inline]
+    pub fn clear(&self) -> u32 {
+        self.skip(self.len())
+    }
+
+    /// Drop up to n items in place and consume them with a single read
+    /// index update, e.g. to resynchronize a stream. Returns the number
+    /// skipped
+    #[inline]
+    pub fn skip(&self, n: u32) -> u32 {
+        let n = n.min(self.len());
+        let mut slot = self.rd_idx.mask() as usize;
+        for _ in 0..n {
+            unsafe { core::ptr::drop_in_place(self.buf_ptr(slot)) };
+            slot += 1;
+            if slot == N {
+                slot = 0;
+            }
+        }
+        self.rd_idx.wrap_add(n);
+        n
+    }
+
+    /// Process up to max items from the read index with f, then consume
+    /// them with a single read index update. Returns the number processed
+    #[inline]
+    pub fn process_n<F: FnMut(&mut T)>(&self, max: u32, mut f: F) -> u32 {
+        let n = max.min(self.len());
+        let mut slot = self.rd_idx.mask() as usize;
+        for _ in 0..n {
+            let x: *mut MaybeUninit<T> = self.buffer_ucell[slot].get();
+            f(unsafe { &mut *(x as *mut T) });
+            slot += 1;
+            if slot == N {
+                slot = 0;
+            }
+        }
+        self.rd_idx.wrap_add(n);
+        n
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    impl<T, const N: usize> RingBufRef<T, N> {
+    impl<T, const N: usize, I: IndexWidth> RingBufRef<T, N, I> {
         
         // Test only method for testing wraparound
         // at extremes
         pub fn test_init_wr_rd(&self, val: u32) {
-            self.wr_idx.cell.set(val);
-            self.rd_idx.cell.set(val);
+            self.wr_idx.set(val);
+            self.rd_idx.set(val);
         }
     }
  
@@ -270,10 +990,10 @@ mod tests {
     fn test_operations<const N: usize>(rbufr1: RingBufRef<u32, N>, iter: usize) {
 
         for i in 0..iter {
-            let loc = rbufr1.writer_front();
+            let loc = rbufr1.alloc_uninit();
 
             if let Some(v) = loc {
-                *v = i as u32;
+                v.write(i as u32);
             }
             assert!(rbufr1.commit().is_ok());
             if let Some(v) = rbufr1.reader_front() {
@@ -285,11 +1005,11 @@ mod tests {
         assert!(rbufr1.reader_front().is_none());
 
         for _ in 0..N {
-            assert!(rbufr1.writer_front().is_some());
+            assert!(rbufr1.alloc_uninit().is_some());
             assert!(rbufr1.commit().is_ok());
         }
         // should fail
-        assert!(rbufr1.writer_front().is_none());
+        assert!(rbufr1.alloc_uninit().is_none());
         assert!(rbufr1.commit().is_err());
 
         //println!("wr {} rd {}, len {}",
@@ -308,11 +1028,12 @@ mod tests {
 
         // alloc half
         for _ in 0..N / 2 {
-            assert!(rbufr1.writer_front().is_some());
+            assert!(rbufr1.alloc_uninit().is_some());
             assert!(rbufr1.commit().is_ok());
         }
     }
 
+    #[cfg(not(feature = "cache-padded"))]
     #[test]
     fn validate_size() {
         // 4 bytes of wr_idx, 4 bytes of rd_idx, 16*4 for buffer
@@ -325,6 +1046,14 @@ mod tests {
         assert!(core::mem::size_of::<RingBufRef<u8, 32>>() == (4 + 4 + 32));
     }
 
+    #[cfg(feature = "cache-padded")]
+    #[test]
+    fn validate_padded_size() {
+        // One line each for rd_idx and wr_idx, buffer padded to the alignment
+        assert!(core::mem::size_of::<RingBufRef<u32, 16>>() == (64 + 64 + 64));
+        assert!(core::mem::size_of::<RingBufRef<u8, 65>>() == (64 + 64 + 128));
+    }
+
     #[test]
     fn power_of_two_len() {
         let rbufr1: RingBufRef<u32, 16> = RingBufRef::new();
@@ -359,7 +1088,8 @@ mod tests {
     fn static_instance_example() {
         let intf: &'static Interface = &SHARED_INTF[0];
 
-        let alloc_res = intf.cmd_q.writer_front();
+        // Plain data command filled in place
+        let alloc_res = unsafe { intf.cmd_q.writer_front() };
 
         if let Some(cmd) = alloc_res {
             cmd.id = 42;
@@ -376,4 +1106,189 @@ mod tests {
     //fn zero_len() {
     //    test_operations::<0>();
     //}
+
+    #[test]
+    fn try_push_returns_value() {
+        let rbuf = RingBufRef::<std::string::String, 1>::new();
+        assert!(rbuf.try_push("a".into()).is_ok());
+        assert!(rbuf.try_push("b".into()) == Err("b".into()));
+        assert!(rbuf.pop_value().unwrap() == "a");
+    }
+
+    #[test]
+    fn error_display() {
+        fn check() -> Result<(), Box<dyn std::error::Error>> {
+            RingBufRef::<u32, 1>::new().pop()?;
+            Ok(())
+        }
+        assert!(check().unwrap_err().to_string() == "ring buffer empty");
+        assert!(RingBufRef::<u32, 1>::new().pop() == Err(ErrCode::BufEmpty));
+    }
+
+    #[test]
+    fn debug_format() {
+        let rbuf = RingBufRef::<u32, 3>::new();
+        rbuf.test_init_wr_rd(4);
+        assert!(rbuf.push(1).is_ok());
+        assert!(format!("{:?}", rbuf)
+            == "RingBufRef { capacity: 3, rd_idx: 4, wr_idx: 5, len: 1, is_full: false, is_empty: false }");
+    }
+
+    #[test]
+    fn alloc_commit_n() {
+        let rbuf = RingBufRef::<u32, 6>::new();
+        let write = |slots: &mut [MaybeUninit<u32>], vals: &[u32]| {
+            for (slot, val) in slots.iter_mut().zip(vals) {
+                slot.write(*val);
+            }
+        };
+        write(rbuf.alloc_n(4).unwrap(), &[1, 2, 3, 4]);
+        assert!(rbuf.is_empty());
+        assert!(rbuf.commit_n(4).is_ok());
+        assert!(rbuf.len() == 4 && rbuf.free_len() == 2);
+
+        // Vacant space wraps around
+        assert!(rbuf.pop().is_ok() && rbuf.pop().is_ok());
+        assert!(rbuf.alloc_n(3).is_none());
+        write(rbuf.alloc_n(2).unwrap(), &[5, 6]);
+        assert!(rbuf.commit_n(2).is_ok());
+        write(rbuf.alloc_n(2).unwrap(), &[7, 8]);
+        assert!(rbuf.commit_n(3).is_err());
+        assert!(rbuf.commit_n(2).is_ok());
+        assert!(rbuf.alloc_n(1).is_none());
+
+        let mut out = [0; 6];
+        assert!(rbuf.pop_slice(&mut out) == 6);
+        assert!(out == [3, 4, 5, 6, 7, 8]);
+    }
+
+    #[cfg(not(feature = "cache-padded"))]
+    #[test]
+    fn narrow_index() {
+        // 2 bytes of wr_idx, 2 bytes of rd_idx, 8*2 for buffer
+        assert!(core::mem::size_of::<RingBufRef<u16, 8, u16>>() == (2 + 2 + 8*2));
+        assert!(core::mem::size_of::<RingBufRef<u8, 4, u8>>() == (1 + 1 + 4));
+    }
+
+    #[test]
+    fn raw_parts() {
+        type Ring = RingBufRef<u32, 5>;
+        #[repr(C, align(64))]
+        struct Region([u8; 256]);
+        let mut region = Region([0xff; 256]);
+        let ptr = region.0.as_mut_ptr();
+        let len = core::mem::size_of::<Ring>();
+
+        // Uninitialized indices are rejected
+        assert!(unsafe { Ring::attach(ptr, len) }.err() == Some(ShmError::InvalidState));
+        assert!(unsafe { Ring::from_raw_parts(ptr.add(1), len) }.err() == Some(ShmError::Misaligned));
+        assert!(unsafe { Ring::from_raw_parts(ptr, len - 1) }.err() == Some(ShmError::RegionTooSmall));
+
+        let producer = unsafe { Ring::from_raw_parts(ptr, len) }.unwrap();
+        assert!(producer.push(7).is_ok());
+        let consumer = unsafe { Ring::attach(ptr, len) }.unwrap();
+        assert!(consumer.pop_value() == Some(7));
+    }
+
+    #[test]
+    fn saved_state() {
+        let rbufr: RingBufRef<u32, 5> = RingBufRef::new();
+        assert!(rbufr.push_slice(&[1, 2, 3]) == 3);
+        assert!(rbufr.pop().is_ok());
+        let state = rbufr.save_state();
+
+        // Garbage indices after a reset, resumed from the saved state
+        rbufr.test_init_wr_rd(7);
+        assert!(unsafe { rbufr.restore_state(&state) }.is_ok());
+        assert!(rbufr.pop_value() == Some(2) && rbufr.len() == 1);
+
+        let mut corrupted = state;
+        corrupted.ring.wr_idx ^= 1;
+        assert!(unsafe { rbufr.restore_state(&corrupted) } == Err(ErrCode::InvalidState));
+        let other: RingBufRef<u32, 6> = RingBufRef::new();
+        assert!(unsafe { other.restore_state(&state) } == Err(ErrCode::InvalidState));
+    }
+
+    #[test]
+    fn zero_sized_items() {
+        let signals: RingBufRef<(), 3> = RingBufRef::new();
+        #[cfg(not(feature = "cache-padded"))]
+        assert!(core::mem::size_of::<RingBufRef<(), 3>>() == 4 + 4);
+
+        for _ in 0..10 {
+            assert!(signals.push(()).is_ok());
+            assert!(signals.push_slice(&[(); 4]) == 2);
+            assert!(signals.push(()) == Err(ErrCode::BufFull));
+            assert!(signals.read_slices().0.len() + signals.read_slices().1.len() == 3);
+            assert!(signals.peek_at(2) == Some(&()));
+            assert!(signals.pop_value() == Some(()));
+            assert!(signals.release(5) == 2);
+            assert!(signals.pop_value().is_none());
+        }
+    }
+
+    #[test]
+    fn narrow_index_wrap() {
+        // Power of two wraps at the index width
+        let rbufr1: RingBufRef<u32, 16, u8> = RingBufRef::new();
+        rbufr1.test_init_wr_rd(u8::MAX as u32 - 2);
+        test_narrow_operations(&rbufr1, 600);
+
+        let rbufr2: RingBufRef<u32, 100, u8> = RingBufRef::new();
+        test_narrow_operations(&rbufr2, 600);
+
+        let rbufr3: RingBufRef<u32, 16, u16> = RingBufRef::new();
+        rbufr3.test_init_wr_rd(u16::MAX as u32 - 2);
+        test_narrow_operations(&rbufr3, 100);
+    }
+
+    #[test]
+    fn pop_slice_wrap() {
+        let rbufr1: RingBufRef<u32, 4> = RingBufRef::new();
+        assert!(rbufr1.push_slice(&[0, 1, 2]) == 3);
+        assert!(rbufr1.pop_value() == Some(0) && rbufr1.pop_value() == Some(1));
+        // Queued items 2..5 wrap around the buffer end
+        assert!(rbufr1.push_slice(&[3, 4, 5]) == 3);
+
+        // Destination shorter than the queue, the rest stays queued
+        let mut dst = [0; 3];
+        assert!(rbufr1.pop_slice(&mut dst) == 3);
+        assert!(dst == [2, 3, 4] && rbufr1.len() == 1);
+
+        let mut dst = [0; 4];
+        assert!(rbufr1.pop_slice(&mut dst) == 1);
+        assert!(dst[0] == 5 && rbufr1.is_empty());
+        assert!(rbufr1.pop_slice(&mut dst) == 0);
+    }
+
+    #[test]
+    fn clear_drops_each() {
+        use std::rc::Rc;
+
+        let trackers: [Rc<()>; 5] = core::array::from_fn(|_| Rc::new(()));
+        let rbufr1: RingBufRef<Rc<()>, 4> = RingBufRef::new();
+        assert!(rbufr1.clear() == 0);
+        assert!(rbufr1.push(trackers[0].clone()).is_ok());
+        assert!(rbufr1.pop_value().is_some());
+        // Queued across the buffer end
+        for tracker in &trackers[1..] {
+            assert!(rbufr1.push(tracker.clone()).is_ok());
+        }
+        assert!(rbufr1.clear() == 4);
+        assert!(rbufr1.is_empty());
+        assert!(trackers.iter().all(|tracker| Rc::strong_count(tracker) == 1));
+    }
+
+    fn test_narrow_operations<const N: usize, I: IndexWidth>(rbufr1: &RingBufRef<u32, N, I>, iter: u32) {
+        for i in 0..iter {
+            assert!(rbufr1.push(i).is_ok());
+            if i % 3 != 0 {
+                let len = rbufr1.len();
+                assert!(rbufr1.pop_value() == Some(i + 1 - len));
+            }
+            if rbufr1.is_full() {
+                rbufr1.clear();
+            }
+        }
+    }
 }
@@ -0,0 +1,185 @@
+//! `log::Log` backend formatting records into a static byte ring.
+//!
+//! Logging only formats into RAM, the bytes are drained to a UART or RTT
+//! from the idle loop through `read` or `drain`. A record is queued as a
+//! whole line `LEVEL target: message\n` or dropped and counted when the
+//! ring has no room for it, lines are never cut.
+//!
+//! The ring has a single producer. With the `critical-section` feature each
+//! record is written inside `critical_section::with`. Without it, a record
+//! logged while another context is writing one is dropped and counted.
+
+use core::fmt::{self, Write};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::ringbuf_ref::RingBufRef;
+#[cfg(not(feature = "critical-section"))]
+use crate::sync::TryLock;
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+pub struct RingLogger<const N: usize> {
+    ring: RingBufRef<u8, N>,
+    level: LevelFilter,
+    // Records that did not fit or were contended
+    dropped: AtomicU32,
+    #[cfg(not(feature = "critical-section"))]
+    producer: TryLock,
+}
+
+// Stages the line in the vacant space after the write index, published as
+// a whole once formatted
+struct RingWriter<'a, const N: usize> {
+    ring: &'a RingBufRef<u8, N>,
+    len: usize,
+}
+
+impl<const N: usize> Write for RingWriter<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.ring.stage_slice(self.len, s.as_bytes()) {
+            self.len += s.len();
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+fn write_record<W: Write>(w: &mut W, record: &Record) -> fmt::Result {
+    writeln!(w, "{} {}: {}", record.level(), record.target(), record.args())
+}
+
+impl<const N: usize> RingLogger<N> {
+
+    /// Logger passing records up to level
+    pub const fn new(level: LevelFilter) -> Self {
+        RingLogger {
+            ring: RingBufRef::new(),
+            level,
+            dropped: AtomicU32::new(0),
+            #[cfg(not(feature = "critical-section"))]
+            producer: TryLock::new(),
+        }
+    }
+
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    /// Records dropped for lack of room so far
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Bytes waiting to be drained
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    fn count_drop(&self) {
+        self.dropped.rmw_fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Single producer, see Log::log
+    fn push_record(&self, record: &Record) {
+        let mut w = RingWriter { ring: &self.ring, len: 0 };
+        if write_record(&mut w, record).is_ok() {
+            // Staged within the free space
+            let _ = self.ring.commit_n(w.len);
+        } else {
+            self.count_drop();
+        }
+    }
+
+    /// Copy queued bytes into dst and consume them, consumer side. Returns
+    /// the number copied
+    pub fn read(&self, dst: &mut [u8]) -> usize {
+        self.ring.pop_slice(dst)
+    }
+
+    /// Hand the queued bytes to f in up to two slices and consume them,
+    /// consumer side, e.g. to write them out over a blocking UART
+    pub fn drain<F: FnMut(&[u8])>(&self, mut f: F) -> usize {
+        let (first, second) = self.ring.read_slices();
+        let n = first.len() + second.len();
+        f(first);
+        if !second.is_empty() {
+            f(second);
+        }
+        self.ring.release(n)
+    }
+}
+
+impl<const N: usize> Log for RingLogger<N> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        #[cfg(feature = "critical-section")]
+        critical_section::with(|_| self.push_record(record));
+        #[cfg(not(feature = "critical-section"))]
+        if self.producer.try_with(|| self.push_record(record)).is_none() {
+            self.count_drop();
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    fn log_to<const N: usize>(logger: &RingLogger<N>, level: Level, msg: fmt::Arguments) {
+        logger.log(&Record::builder().level(level).target("app").args(msg).build());
+    }
+
+    #[test]
+    fn lines() {
+        let logger: RingLogger<32> = RingLogger::new(LevelFilter::Info);
+        let mut buf = [0; 32];
+
+        log_to(&logger, Level::Info, format_args!("boot {}", 1));
+        log_to(&logger, Level::Debug, format_args!("filtered"));
+        assert!(logger.read(&mut buf) == 17);
+        assert!(buf[..17] == *b"INFO app: boot 1\n");
+
+        // Whole lines only
+        log_to(&logger, Level::Warn, format_args!("low battery"));
+        log_to(&logger, Level::Error, format_args!("no room"));
+        assert!(logger.dropped() == 1);
+
+        let mut out = Vec::new();
+        assert!(logger.drain(|bytes| out.extend_from_slice(bytes)) == 22);
+        assert!(out == b"WARN app: low battery\n");
+        assert!(logger.is_empty());
+
+        // Formatted once, straight into the ring
+        struct Once<'a>(&'a core::cell::Cell<u32>);
+        impl fmt::Display for Once<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.set(self.0.get() + 1);
+                f.write_str("x")
+            }
+        }
+        let calls = core::cell::Cell::new(0);
+        log_to(&logger, Level::Info, format_args!("{}", Once(&calls)));
+        assert!(calls.get() == 1 && logger.len() == 12);
+
+        // Logged while another context holds the producer side
+        #[cfg(not(feature = "critical-section"))]
+        {
+            logger.producer.try_with(|| log_to(&logger, Level::Info, format_args!("nested")));
+            assert!(logger.dropped() == 2 && logger.len() == 12);
+        }
+    }
+}
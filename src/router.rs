@@ -0,0 +1,282 @@
+//! Router dispatching the messages of one SharedPool to several workers.
+//!
+//! Builds a star topology between a single producer (e.g. an ISR) and W
+//! worker tasks: the router drains the inbound pool and moves each message
+//! into the `Lane` chosen by a user classification function. Messages are
+//! moved bit-wise, no Clone is required. Payloads are not copied, the
+//! message keeps its inbound pool index and the worker reads the payload
+//! from the inbound pool through `Payloads`. Once released, the worker
+//! hands the location back over its lane and the router returns it to the
+//! inbound producer.
+
+use crate::pool_alloc::PoolAlloc;
+use crate::ringbuf::{Consumer as RingBufConsumer, Producer as RingBufProducer, RingBuf, SplitError};
+use crate::shared_pool::{Consumer, HasPoolIdx, PoolIndex, SharedPoolError};
+use crate::shared_singleton::SharedSingleton;
+
+/// Message ring from the router to one worker, and the ring handing the
+/// payload locations back. The return ring holds all N locations, hence
+/// never fills up
+pub struct Lane<Q, const N: usize, const M: usize> {
+    messages: RingBuf<Q, M>,
+    returns: RingBuf<PoolIndex<N>, N>,
+}
+
+impl<Q, const N: usize, const M: usize> Default for Lane<Q, N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Q, const N: usize, const M: usize> Lane<Q, N, M> {
+    pub const fn new() -> Self {
+        Lane {
+            messages: RingBuf::new(),
+            returns: RingBuf::new(),
+        }
+    }
+
+    // Split the router and worker ends, once in life time
+    pub fn split(&self) -> Result<(LaneProducer<'_, Q, N, M>, LaneConsumer<'_, Q, N, M>), SplitError> {
+        let (messages_prod, messages_cons) = self.messages.split()?;
+        let (returns_prod, returns_cons) = self.returns.split()?;
+        Ok((
+            LaneProducer {
+                messages: messages_prod,
+                returns: returns_cons,
+            },
+            LaneConsumer {
+                messages: messages_cons,
+                returns: returns_prod,
+            },
+        ))
+    }
+}
+
+/// Router end of a Lane
+pub struct LaneProducer<'a, Q: 'a, const N: usize, const M: usize> {
+    messages: RingBufProducer<'a, Q, M>,
+    returns: RingBufConsumer<'a, PoolIndex<N>, N>,
+}
+
+/// Worker end of a Lane
+pub struct LaneConsumer<'a, Q: 'a, const N: usize, const M: usize> {
+    messages: RingBufConsumer<'a, Q, M>,
+    returns: RingBufProducer<'a, PoolIndex<N>, N>,
+}
+
+impl<'a, Q: HasPoolIdx<N>, const N: usize, const M: usize> LaneConsumer<'a, Q, N, M> {
+    pub fn peek(&self) -> Option<&Q> {
+        self.messages.reader_front()
+    }
+
+    /// Message at the head and its payload in the inbound pool, if any
+    pub fn peek_with_payload<T>(&self, payloads: &Payloads<'a, T, N>) -> (Option<&Q>, Option<&'a SharedSingleton<T>>) {
+        match self.messages.reader_front() {
+            Some(message) => (Some(message), payloads.get(message.get_pool_idx())),
+            None => (None, None),
+        }
+    }
+
+    pub fn pop(&mut self) -> Result<(), SharedPoolError> {
+        self.messages
+            .pop()
+            .map_err(|_| SharedPoolError::AllocBufEmpty)
+    }
+
+    // Hand a payload location released with read_done back to the router
+    pub fn return_payload(&mut self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError> {
+        if !pidx.is_valid() {
+            return Err(SharedPoolError::InvalidPoolIndex);
+        }
+        self.returns
+            .try_push(pidx)
+            .map_err(|_| SharedPoolError::ReturnBufFull)
+    }
+}
+
+/// Payload storage of the inbound pool, shared by the workers
+pub struct Payloads<'a, T, const N: usize> {
+    pool_ref: &'a [SharedSingleton<T>; N],
+}
+
+impl<T, const N: usize> Clone for Payloads<'_, T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const N: usize> Copy for Payloads<'_, T, N> {}
+
+impl<'a, T, const N: usize> Payloads<'a, T, N> {
+    /// Payload at pidx, None if the index is not valid
+    pub fn get(&self, pidx: PoolIndex<N>) -> Option<&'a SharedSingleton<T>> {
+        usize::try_from(pidx).ok().map(|idx| &self.pool_ref[idx])
+    }
+}
+
+pub struct Router<'a, T, Q: HasPoolIdx<N> + 'a, const N: usize, const M: usize, A: PoolAlloc<N>, const W: usize> {
+    inbound: Consumer<'a, T, Q, N, M, A>,
+    outbound: [LaneProducer<'a, Q, N, M>; W],
+}
+
+impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, const W: usize> Router<'a, T, Q, N, M, A, W> {
+
+    pub fn new(inbound: Consumer<'a, T, Q, N, M, A>, outbound: [LaneProducer<'a, Q, N, M>; W]) -> Self {
+        Router { inbound, outbound }
+    }
+
+    /// Payloads of the inbound pool, for the workers to read
+    pub fn payloads(&self) -> Payloads<'a, T, N> {
+        Payloads { pool_ref: self.inbound.pool_ref() }
+    }
+
+    /// Return the payload locations handed back by the workers to the
+    /// inbound producer. Also done by `route`
+    pub fn collect_returns(&mut self) -> Result<(), SharedPoolError> {
+        for lane in self.outbound.iter_mut() {
+            while let Some(pidx) = lane.returns.pop_value() {
+                self.inbound.return_payload(pidx)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatch the message at the head of the inbound pool. `classify`
+    /// returns the outbound lane index, or None to discard the message (its
+    /// payload is returned to the inbound pool).
+    ///
+    /// Returns Ok(None) if there was nothing to route and Ok(Some(route))
+    /// once moved. If the chosen lane is full, or the route is out of range
+    /// (`InvalidPoolIndex`), the error is returned and the message stays in
+    /// the inbound pool to be retried.
+    pub fn route<F: FnOnce(&Q) -> Option<usize>>(&mut self, classify: F) -> Result<Option<usize>, SharedPoolError> {
+        self.collect_returns()?;
+
+        let (message, payload) = match self.inbound.peek_with_payload() {
+            (Some(message), payload) => (message, payload),
+            _ => return Ok(None),
+        };
+        let src_idx = message.get_pool_idx();
+
+        let route = match classify(message) {
+            Some(route) => route,
+            None => {
+                if let Some(payload) = payload {
+                    payload.read_done().map_err(|_| SharedPoolError::PayloadNotConsumerOwned)?;
+                }
+                self.inbound.pop()?;
+                if src_idx.is_valid() {
+                    self.inbound.return_payload(src_idx)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        let target = self.outbound.get_mut(route).ok_or(SharedPoolError::InvalidPoolIndex)?;
+        // Payload must have been passed to the consumer, the worker takes
+        // over the read side
+        if payload.is_some_and(|payload| payload.try_read().is_none()) {
+            return Err(SharedPoolError::PayloadNotConsumerOwned);
+        }
+        let slot = target.messages.alloc_uninit().ok_or(SharedPoolError::AllocBufFull)?;
+        // Move the message along with its pool index. The inbound slot is
+        // popped below and never read again
+        slot.write(unsafe { core::ptr::read(message) });
+        target.messages.commit().map_err(|_| SharedPoolError::AllocBufFull)?;
+
+        self.inbound.pop()?;
+        Ok(Some(route))
+    }
+
+    /// Route until the inbound pool is empty or routing fails. Returns the
+    /// number of messages handled and the error that stopped it, if any
+    pub fn route_all<F: FnMut(&Q) -> Option<usize>>(&mut self, mut classify: F) -> (usize, Option<SharedPoolError>) {
+        let mut count = 0;
+        while self.inbound.peek().is_some() {
+            if let Err(err) = self.route(&mut classify) {
+                return (count, Some(err));
+            }
+            count += 1;
+        }
+        (count, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_pool::SharedPool;
+
+    const POOL_DEPTH: usize = 4;
+
+    #[derive(Default)]
+    pub struct Message {
+        kind: u32,
+        payload: PoolIndex<POOL_DEPTH>,
+    }
+
+    impl HasPoolIdx<POOL_DEPTH> for Message {
+        fn get_pool_idx(&self) -> PoolIndex<POOL_DEPTH> {
+            self.payload
+        }
+        fn set_pool_idx(&mut self, pindex: PoolIndex<POOL_DEPTH>) {
+            self.payload = pindex
+        }
+    }
+
+    #[test]
+    fn route_by_kind() {
+        let inbound: SharedPool<u32, Message, POOL_DEPTH, 8> = SharedPool::new();
+        let lane0: Lane<Message, POOL_DEPTH, 8> = Lane::new();
+        let lane1: Lane<Message, POOL_DEPTH, 8> = Lane::new();
+
+        let (mut isr, router_in) = inbound.split().unwrap();
+        let (l0_prod, mut w0) = lane0.split().unwrap();
+        let (l1_prod, mut w1) = lane1.split().unwrap();
+        assert!(lane0.split().is_err());
+
+        let mut router = Router::new(router_in, [l0_prod, l1_prod]);
+        let payloads = router.payloads();
+
+        for i in 0..6 {
+            if i % 2 == 0 {
+                let (message, payload) = isr.stage_with_payload().unwrap();
+                message.kind = i;
+                *payload.try_write().unwrap() = 100 + i;
+                payload.write_done().unwrap();
+            } else {
+                isr.stage().unwrap().kind = i;
+            }
+            isr.commit().unwrap();
+        }
+        // No such lane, left queued
+        assert!(router.route(|_| Some(2)) == Err(SharedPoolError::InvalidPoolIndex));
+        // kind 5 is dropped
+        let routed = router.route_all(|m| if m.kind == 5 { None } else { Some((m.kind % 2) as usize) });
+        assert!(routed == (6, None));
+
+        // Payloads stay in the inbound pool until the worker is done
+        assert!(inbound.num_free() == POOL_DEPTH as u32 - 3);
+
+        for i in [0, 2, 4] {
+            let (message, payload) = w0.peek_with_payload(&payloads);
+            assert!(message.unwrap().kind == i);
+            assert!(*payload.unwrap().try_read().unwrap() == 100 + i);
+            payload.unwrap().read_done().unwrap();
+            let pidx = message.unwrap().get_pool_idx();
+            w0.pop().unwrap();
+            w0.return_payload(pidx).unwrap();
+        }
+        for i in [1, 3] {
+            let (message, payload) = w1.peek_with_payload(&payloads);
+            assert!(message.unwrap().kind == i && payload.is_none());
+            w1.pop().unwrap();
+        }
+        assert!(w1.peek().is_none());
+
+        // Handed back to the inbound producer
+        assert!(router.collect_returns().is_ok());
+        assert!(inbound.num_free() == POOL_DEPTH as u32);
+    }
+}
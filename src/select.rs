@@ -0,0 +1,77 @@
+//! Polling several consumers at once.
+//!
+//! An event loop servicing several rings, possibly of different item
+//! types, passes their consumers as `&dyn Pollable` to `poll_any` to learn
+//! which ones have items, or to `next_ready` to pick one round-robin so that
+//! no ring is starved by the ones polled before it.
+
+use crate::backend::Backend;
+use crate::duplex::Endpoint;
+use crate::notify::Notifier;
+use crate::ringbuf::Consumer;
+
+/// Receiving side of a queue whose readiness can be polled
+pub trait Pollable {
+    /// At least one item can be received
+    fn is_ready(&self) -> bool;
+}
+
+impl<T, const N: usize, B: Backend, Nf: Notifier> Pollable for Consumer<'_, T, N, B, Nf> {
+    fn is_ready(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+impl<Tx, Rx, const NT: usize, const NR: usize> Pollable for Endpoint<'_, Tx, Rx, NT, NR> {
+    fn is_ready(&self) -> bool {
+        self.peek().is_some()
+    }
+}
+
+/// Bit i set if sources[i] is ready, for up to 32 sources
+pub fn poll_any(sources: &[&dyn Pollable]) -> u32 {
+    sources
+        .iter()
+        .take(32)
+        .enumerate()
+        .filter(|(_, source)| source.is_ready())
+        .fold(0, |mask, (i, _)| mask | (1 << i))
+}
+
+/// Index of the first ready source at or after start, wrapping around
+pub fn next_ready(sources: &[&dyn Pollable], start: usize) -> Option<usize> {
+    let len = sources.len();
+    (0..len)
+        .map(|i| (start + i) % len)
+        .find(|&i| sources[i].is_ready())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duplex::Duplex;
+    use crate::ringbuf::RingBuf;
+
+    #[test]
+    fn mixed_sources() {
+        let bytes: RingBuf<u8, 4> = RingBuf::new();
+        let words: RingBuf<u32, 2> = RingBuf::new();
+        let duplex: Duplex<u16, u16, 2, 2> = Duplex::new();
+        let (mut byte_prod, byte_cons) = bytes.split().unwrap();
+        let (mut word_prod, mut word_cons) = words.split().unwrap();
+        let (mut a, b) = duplex.split().unwrap();
+
+        assert!(poll_any(&[&byte_cons, &word_cons, &b]) == 0);
+        assert!(next_ready(&[&byte_cons, &word_cons, &b], 0).is_none());
+
+        assert!(byte_prod.try_push(1).is_ok());
+        assert!(word_prod.try_push(2).is_ok());
+        assert!(a.send(3).is_ok());
+        assert!(poll_any(&[&byte_cons, &word_cons, &b]) == 0b111);
+
+        // Round-robin from the source after the last one serviced
+        assert!(word_cons.pop().is_ok());
+        assert!(next_ready(&[&byte_cons, &word_cons, &b], 1) == Some(2));
+        assert!(next_ready(&[&byte_cons, &word_cons], 1) == Some(0));
+    }
+}
@@ -0,0 +1,522 @@
+//! Fixed capacity Single Producer Single Consumer Ringbuffer using per-slot
+//! sequence stamps (Vyukov style) instead of a shared read/write index pair.
+//!
+//! Each side keeps its own position. Fullness and emptiness are decided by
+//! the stamp of the slot at that position only, so the producer never loads
+//! the read position and the consumer never loads the write position on the
+//! hot path. The cost is one stamp (4 bytes) per slot.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::ringbuf_ref::{CachePadded, ErrCode};
+
+struct Slot<T> {
+    // 2 * position when vacant for the producer at that position,
+    // 2 * position + 1 once committed for the consumer. The doubling keeps
+    // the committed stamp distinct from the next lap's vacant stamp at N = 1
+    seq: AtomicU32,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A slot sequence ring buffer of capacity N holding items of type T.
+/// Offers the same API as `RingBufRef`.
+pub struct SeqRingBufRef<T, const N: usize> {
+    // Producer position, only written by the producer
+    wr_pos: CachePadded<AtomicU32>,
+    // Consumer position, only written by the consumer
+    rd_pos: CachePadded<AtomicU32>,
+    slots: [Slot<T>; N],
+}
+
+// Delcare this is thread safe due to the SPSC premise. Each slot is handed
+// over by a release store of its stamp.
+unsafe impl<T: Send, const N: usize> Sync for SeqRingBufRef<T, N> {}
+
+impl<T, const N: usize> Default for SeqRingBufRef<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for SeqRingBufRef<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (rd_pos, wr_pos) = self.indices();
+        f.debug_struct("SeqRingBufRef")
+            .field("capacity", &N)
+            .field("rd_pos", &rd_pos)
+            .field("wr_pos", &wr_pos)
+            .field("len", &self.len())
+            .field("is_full", &self.is_full())
+            .field("is_empty", &self.is_empty())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T, const N: usize> defmt::Format for SeqRingBufRef<T, N> {
+    fn format(&self, f: defmt::Formatter) {
+        let (rd_pos, wr_pos) = self.indices();
+        defmt::write!(f, "SeqRingBufRef {{ capacity: {}, rd_pos: {}, wr_pos: {}, len: {}, is_full: {}, is_empty: {} }}",
+            N, rd_pos, wr_pos, self.len(), self.is_full(), self.is_empty())
+    }
+}
+
+impl<T, const N: usize> SeqRingBufRef<T, N> {
+
+    const OK: () = assert!(N > 0 && N <= (1 << 30), "Ringbuf capacity must be > 0 and <= 2^30");
+
+    // Positions wrap at the largest multiple of N not above 2^31 so that
+    // position % N stays continuous across the wrap and the doubled stamps
+    // fit in u32.
+    const RANGE: u32 = (1 << 31) / N as u32 * N as u32;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT_SLOT: Slot<T> = Slot {
+        seq: AtomicU32::new(0),
+        val: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: SeqRingBufRef<T, N> = Self::new();
+
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let _: () = SeqRingBufRef::<T, N>::OK;
+
+        // Slot i is vacant for position i
+        let mut slots = [Self::INIT_SLOT; N];
+        let mut i = 0;
+        while i < N {
+            slots[i].seq = AtomicU32::new(2 * i as u32);
+            i += 1;
+        }
+        SeqRingBufRef {
+            wr_pos: CachePadded(AtomicU32::new(0)),
+            rd_pos: CachePadded(AtomicU32::new(0)),
+            slots,
+        }
+    }
+
+    // pos + val in the position range, val must be <= N
+    #[inline(always)]
+    fn wrap_add(pos: u32, val: u32) -> u32 {
+        // Cannot overflow as both are below 2^31
+        let sum = pos + val;
+        if N.is_power_of_two() {
+            sum & (Self::RANGE - 1)
+        } else if sum >= Self::RANGE {
+            sum - Self::RANGE
+        } else {
+            sum
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, pos: u32) -> &Slot<T> {
+        &self.slots[pos as usize % N]
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        let pos = self.rd_pos.load(Ordering::Relaxed);
+        self.slot(pos).seq.load(Ordering::Acquire) != 2 * pos + 1
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        let pos = self.wr_pos.load(Ordering::Relaxed);
+        self.slot(pos).seq.load(Ordering::Acquire) != 2 * pos
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        // Snapshot only, reads the positions of both sides
+        let wr = self.wr_pos.load(Ordering::Relaxed);
+        let rd = self.rd_pos.load(Ordering::Relaxed);
+        if wr >= rd {
+            wr - rd
+        } else {
+            wr + (Self::RANGE - rd)
+        }
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Vacant slots, capacity minus len. Snapshot only, as len
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        (N as u32).saturating_sub(self.len())
+    }
+
+    /// Raw (consumer, producer) positions
+    #[inline(always)]
+    pub fn indices(&self) -> (u32, u32) {
+        (self.rd_pos.load(Ordering::Relaxed), self.wr_pos.load(Ordering::Relaxed))
+    }
+
+    /// Returns the slot at the producer position as mutable reference if vacant.
+    /// Calling it twice without commit returns the same location.
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        self.alloc_uninit().map(|m| &mut *m.as_mut_ptr())
+    }
+
+    /// Vacant slot at the producer position, see `RingBufRef::alloc_uninit`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn alloc_uninit(&self) -> Option<&mut MaybeUninit<T>> {
+        if !self.is_full() {
+            Some(unsafe { self.writer_slot_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Initialize the slot in place and publish it, see
+    /// `RingBufRef::push_with`
+    #[inline(always)]
+    pub fn push_with<F>(&self, f: F) -> Result<(), ErrCode>
+    where
+        F: for<'s> FnOnce(&'s mut MaybeUninit<T>) -> &'s mut T,
+    {
+        let slot = self.alloc_uninit().ok_or(ErrCode::BufFull)?;
+        let ptr = slot.as_mut_ptr();
+        if !core::ptr::eq(f(slot), ptr) {
+            return Err(ErrCode::InvalidState);
+        }
+        unsafe { self.commit_unchecked() };
+        Ok(())
+    }
+
+    /// Publish the slot at the producer position to the consumer
+    #[inline(always)]
+    pub fn commit(&self) -> Result<(), ErrCode> {
+        if !self.is_full() {
+            unsafe { self.commit_unchecked() };
+            Ok(())
+        } else {
+            Err(ErrCode::BufFull)
+        }
+    }
+
+    /// Write and commit in one step
+    #[inline(always)]
+    pub fn push(&self, val: T) -> Result<(), ErrCode> {
+        self.try_push(val).map_err(|_| ErrCode::BufFull)
+    }
+
+    /// Same as `push`, handing val back when full so that the caller can
+    /// retry with it
+    #[inline(always)]
+    pub fn try_push(&self, val: T) -> Result<(), T> {
+        match self.alloc_uninit() {
+            Some(slot) => {
+                slot.write(val);
+                unsafe { self.commit_unchecked() };
+                Ok(())
+            }
+            None => Err(val),
+        }
+    }
+
+    /// Push as many items of src as fit. Each slot stamp is released
+    /// individually
+    #[inline]
+    pub fn push_slice(&self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let mut n = 0;
+        while n < src.len() && self.push(src[n]).is_ok() {
+            n += 1;
+        }
+        n
+    }
+
+    /// Returns an Option of reference to the slot at the consumer position
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            let x: *mut MaybeUninit<T> = self.slot(self.rd_pos.load(Ordering::Relaxed)).val.get();
+            let t: &T = unsafe { &*(x as *const T) };
+            Some(t)
+        }
+    }
+
+    /// Reference to the nth committed item after the consumer position
+    #[inline(always)]
+    pub fn peek_at(&self, n: u32) -> Option<&T> {
+        if n as usize >= N {
+            return None;
+        }
+        let pos = Self::wrap_add(self.rd_pos.load(Ordering::Relaxed), n);
+        let slot = self.slot(pos);
+        if slot.seq.load(Ordering::Acquire) == 2 * pos + 1 {
+            Some(unsafe { &*(slot.val.get() as *const T) })
+        } else {
+            None
+        }
+    }
+
+    /// Returns an Option of mutable reference to the slot at the consumer position
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn reader_front_mut(&self) -> Option<&mut T> {
+        if self.is_empty() {
+            None
+        } else {
+            let x: *mut MaybeUninit<T> = self.slot(self.rd_pos.load(Ordering::Relaxed)).val.get();
+            let t: &mut T = unsafe { &mut *(x as *mut T) };
+            Some(t)
+        }
+    }
+
+    /// Consume the item at the consumer position, the slot becomes vacant
+    /// for the producer one lap later
+    #[inline(always)]
+    pub fn pop(&self) -> Result<(), ErrCode> {
+        if !self.is_empty() {
+            unsafe { self.pop_unchecked() };
+            Ok(())
+        } else {
+            Err(ErrCode::BufEmpty)
+        }
+    }
+
+    // Unchecked counterparts, see RingBufRef. Only the slot at the own
+    // position is known vacant/committed, counts are at most 1
+    #[inline(always)]
+    pub(crate) fn writable(&self) -> u32 {
+        !self.is_full() as u32
+    }
+    #[inline(always)]
+    pub(crate) fn readable(&self) -> u32 {
+        !self.is_empty() as u32
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn writer_front_unchecked(&self) -> &mut T {
+        &mut *self.writer_slot_unchecked().as_mut_ptr()
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn writer_slot_unchecked(&self) -> &mut MaybeUninit<T> {
+        &mut *self.slot(self.wr_pos.load(Ordering::Relaxed)).val.get()
+    }
+    #[inline(always)]
+    pub(crate) unsafe fn commit_unchecked(&self) {
+        let pos = self.wr_pos.load(Ordering::Relaxed);
+        self.slot(pos).seq.store(2 * pos + 1, Ordering::Release);
+        self.wr_pos.store(Self::wrap_add(pos, 1), Ordering::Relaxed);
+    }
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub(crate) unsafe fn reader_front_unchecked(&self) -> &mut T {
+        &mut *(self.slot(self.rd_pos.load(Ordering::Relaxed)).val.get() as *mut T)
+    }
+    #[inline(always)]
+    pub(crate) unsafe fn pop_unchecked(&self) {
+        let pos = self.rd_pos.load(Ordering::Relaxed);
+        self.slot(pos).seq.store(2 * Self::wrap_add(pos, N as u32), Ordering::Release);
+        self.rd_pos.store(Self::wrap_add(pos, 1), Ordering::Relaxed);
+    }
+
+    /// Move the item at the consumer position out and consume it
+    #[inline(always)]
+    pub fn pop_value(&self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let val = unsafe { core::ptr::read(self.reader_front_unchecked()) };
+            unsafe { self.pop_unchecked() };
+            Some(val)
+        }
+    }
+
+    /// Pop up to dst.len() items into dst. Each slot stamp is released
+    /// individually
+    #[inline]
+    pub fn pop_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let mut n = 0;
+        while n < dst.len() {
+            match self.pop_value() {
+                Some(val) => dst[n] = val,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Copy up to dst.len() committed items out, oldest first, without
+    /// consuming them. Returns the number copied
+    #[inline]
+    pub fn copy_to_slice(&self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let mut n = 0;
+        while n < dst.len() {
+            match self.peek_at(n as u32) {
+                Some(val) => dst[n] = *val,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Drop all committed items. Each slot stamp is released individually
+    #[inline]
+    pub fn clear(&self) -> u32 {
+        self.skip(u32::MAX)
+    }
+
+    /// Drop up to n committed items. Returns the number skipped
+    #[inline]
+    pub fn skip(&self, n: u32) -> u32 {
+        let mut skipped = 0;
+        while skipped < n && self.pop_value().is_some() {
+            skipped += 1;
+        }
+        skipped
+    }
+
+    /// Process up to max items from the consumer position with f, then
+    /// consume them. Each slot stamp is released individually
+    #[inline]
+    pub fn process_n<F: FnMut(&mut T)>(&self, max: u32, mut f: F) -> u32 {
+        let mut n = 0;
+        while n < max {
+            match self.reader_front_mut() {
+                Some(item) => f(item),
+                None => break,
+            }
+            // Checked non-empty above
+            let _ = self.pop();
+            n += 1;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl<T, const N: usize> SeqRingBufRef<T, N> {
+
+        // Test only method for testing wraparound
+        // at extremes. Only valid on an empty ring
+        pub fn test_init_wr_rd(&self, val: u32) {
+            for i in 0..N as u32 {
+                let pos = Self::wrap_add(val, i);
+                self.slot(pos).seq.store(2 * pos, Ordering::Relaxed);
+            }
+            self.wr_pos.store(val, Ordering::Relaxed);
+            self.rd_pos.store(val, Ordering::Relaxed);
+        }
+    }
+
+    fn test_operations<const N: usize>(rbufr1: SeqRingBufRef<u32, N>, iter: usize) {
+
+        for i in 0..iter {
+            assert!(rbufr1.push(i as u32).is_ok());
+            assert!(*rbufr1.reader_front().unwrap() == i as u32);
+            assert!(rbufr1.pop().is_ok());
+        }
+
+        assert!(rbufr1.reader_front().is_none());
+        assert!(rbufr1.pop().is_err());
+
+        for i in 0..N {
+            rbufr1.alloc_uninit().unwrap().write(i as u32);
+            assert!(rbufr1.commit().is_ok());
+        }
+        // should fail
+        assert!(rbufr1.len() as usize == N);
+        assert!(rbufr1.alloc_uninit().is_none());
+        assert!(rbufr1.commit().is_err());
+
+        // pop half
+        for i in 0..N / 2 {
+            assert!(*rbufr1.reader_front().unwrap() == i as u32);
+            assert!(rbufr1.pop().is_ok());
+        }
+        // alloc half
+        for _ in 0..N / 2 {
+            assert!(rbufr1.alloc_uninit().is_some());
+            assert!(rbufr1.commit().is_ok());
+        }
+        assert!(rbufr1.is_full());
+    }
+
+    #[test]
+    fn clear_drops_each() {
+        use std::rc::Rc;
+
+        let trackers: [Rc<()>; 3] = core::array::from_fn(|_| Rc::new(()));
+        let rbufr1: SeqRingBufRef<Rc<()>, 4> = SeqRingBufRef::new();
+        for tracker in &trackers {
+            assert!(rbufr1.push(tracker.clone()).is_ok());
+        }
+        assert!(rbufr1.clear() == 3 && rbufr1.is_empty());
+        assert!(trackers.iter().all(|tracker| Rc::strong_count(tracker) == 1));
+        assert!(rbufr1.clear() == 0);
+    }
+
+    #[test]
+    fn free_len_tracks_len() {
+        let rbufr1: SeqRingBufRef<u32, 3> = SeqRingBufRef::new();
+        // Start close to the wrap of the positions
+        rbufr1.test_init_wr_rd(SeqRingBufRef::<u32, 3>::RANGE - 1);
+        assert!(rbufr1.free_len() == 3);
+        for i in 0..3 {
+            assert!(rbufr1.push(i).is_ok());
+            assert!(rbufr1.free_len() == 2 - i && rbufr1.len() == i + 1);
+        }
+        assert!(rbufr1.push(3).is_err());
+        assert!(rbufr1.pop().is_ok());
+        assert!(rbufr1.free_len() == 1);
+    }
+
+    #[test]
+    fn power_of_two_len() {
+        test_operations::<16>(SeqRingBufRef::new(), 2 * 16 - 1 + 16 / 2);
+    }
+    #[test]
+    fn single() {
+        test_operations::<1>(SeqRingBufRef::new(), 7);
+    }
+    #[test]
+    fn non_power_of_two_len() {
+        test_operations::<15>(SeqRingBufRef::new(), 2 * 15 - 1 + 15 / 2);
+    }
+    #[test]
+    fn non_power_of_two_len_wrap() {
+        // Positions near the wrap of the position range
+        let rbufr1: SeqRingBufRef<u32, 15> = SeqRingBufRef::new();
+        rbufr1.test_init_wr_rd(SeqRingBufRef::<u32, 15>::RANGE - 3);
+        test_operations::<15>(rbufr1, 64);
+    }
+    #[test]
+    fn power_of_two_len_wrap() {
+        let rbufr1: SeqRingBufRef<u32, 16> = SeqRingBufRef::new();
+        rbufr1.test_init_wr_rd(SeqRingBufRef::<u32, 16>::RANGE - 3);
+        test_operations::<16>(rbufr1, 64);
+    }
+}
@@ -0,0 +1,153 @@
+//! Sequence lock protected value for small Copy types.
+//!
+//! A single writer publishes status words, timestamps and the like, any
+//! number of readers copy the value out without locking. The sequence is
+//! odd while a write is in progress; a reader retries when it saw an odd
+//! sequence or the sequence changed while it was copying. Only loads and
+//! stores are used, hence it works on targets without CAS as well.
+//!
+//! Readers spin while the writer is writing. Do not read from a context
+//! that preempts the writer and keeps it from finishing, use `try_read`
+//! there instead.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+
+pub struct SeqLock<T: Copy> {
+    seq: AtomicU32,
+    val: UnsafeCell<T>,
+    has_split_writer: Cell<bool>,
+}
+
+// Values are copied in and out, a torn copy is discarded by the readers
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+/// Writer handle, once in life time
+pub struct SeqLockWriter<'a, T: Copy> {
+    inner: &'a SeqLock<T>,
+}
+
+impl<T: Copy> SeqLock<T> {
+
+    pub const fn new(init: T) -> Self {
+        SeqLock {
+            seq: AtomicU32::new(0),
+            val: UnsafeCell::new(init),
+            has_split_writer: Cell::new(false),
+        }
+    }
+
+    /// Return the writer, once in life time
+    pub fn split_writer(&self) -> Option<SeqLockWriter<'_, T>> {
+        if self.has_split_writer.get() {
+            None
+        } else {
+            self.has_split_writer.set(true);
+            Some(SeqLockWriter { inner: self })
+        }
+    }
+
+    /// Single read attempt, None if it overlapped with a write
+    #[inline]
+    pub fn try_read(&self) -> Option<T> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 != 0 {
+            return None;
+        }
+        // May be torn, kept uninit and only returned if the sequence is
+        // unchanged
+        let val = unsafe { (self.val.get() as *const MaybeUninit<T>).read_volatile() };
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) == seq {
+            // No write overlapped the copy
+            Some(unsafe { val.assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// Latest value, retrying until it was not written meanwhile
+    #[inline]
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(val) = self.try_read() {
+                return val;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Number of writes so far (wrapping)
+    #[inline]
+    pub fn version(&self) -> u32 {
+        self.seq.load(Ordering::Acquire) >> 1
+    }
+}
+
+impl<'a, T: Copy> SeqLockWriter<'a, T> {
+
+    /// Publish val
+    #[inline]
+    pub fn write(&mut self, val: T) {
+        let lock = self.inner;
+        let seq = lock.seq.load(Ordering::Relaxed);
+        lock.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        // Odd sequence visible before any of the value
+        fence(Ordering::Release);
+        unsafe { lock.val.get().write_volatile(val) };
+        lock.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Current value, no retry needed on the writer side
+    #[inline]
+    pub fn get(&self) -> T {
+        unsafe { *self.inner.val.get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Status {
+        timestamp: u64,
+        flags: u32,
+    }
+
+    static STATUS: SeqLock<Status> = SeqLock::new(Status { timestamp: 0, flags: 0 });
+
+    #[test]
+    fn publish() {
+        let mut writer = STATUS.split_writer().unwrap();
+        assert!(STATUS.split_writer().is_none());
+        assert!(STATUS.read() == Status { timestamp: 0, flags: 0 });
+
+        writer.write(Status { timestamp: 10, flags: 1 });
+        assert!(STATUS.version() == 1);
+        assert!(STATUS.try_read() == Some(Status { timestamp: 10, flags: 1 }));
+        assert!(writer.get().timestamp == 10);
+    }
+
+    #[test]
+    fn threads() {
+        static LOCK: SeqLock<[u32; 8]> = SeqLock::new([0; 8]);
+        let mut writer = LOCK.split_writer().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            for i in 1..=10000 {
+                writer.write([i; 8]);
+            }
+        });
+        let mut last = 0;
+        while last != 10000 {
+            let val = LOCK.read();
+            // Never torn, never older than the last one read
+            assert!(val.iter().all(|v| *v == val[0]));
+            assert!(val[0] >= last);
+            last = val[0];
+        }
+        handle.join().unwrap();
+    }
+}
@@ -1,7 +1,19 @@
-use crate::ringbuf::{Consumer as RingBufConsumer, Producer as RingBufProducer, RingBuf};
+use crate::pool_alloc::{PoolAlloc, RingAlloc};
+use crate::ringbuf::{Consumer as RingBufConsumer, Diagnosed, Producer as RingBufProducer, RingBuf, RingBufCheckpoint, RingState, SplitError};
+use crate::backend::IndexPair;
+use crate::notify::{NoNotify, Notifier};
+use crate::ringbuf_ref::Index;
 use crate::shared_singleton::SharedSingleton;
+use crate::shmem::{check_region, ShmError};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::pin::Pin;
 
-#[derive(Debug)]
+#[cfg(feature = "derive")]
+pub use spsc_ringbuf_core_macros::HasPoolIdx;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SharedPoolError {
     PoolFull,
     AllocBufFull,
@@ -9,10 +21,37 @@ pub enum SharedPoolError {
     AllocBufEmpty,
     PayloadNotConsumerOwned,
     AlreadySplit,
+    InvalidState,
+    /// Pool index out of range of the pool
+    InvalidPoolIndex,
+    /// Location handed out or returned while still owned by a side
+    PayloadNotVacant,
+    /// Commit without a staged message
+    NotStaged,
+}
+
+impl core::fmt::Display for SharedPoolError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            SharedPoolError::PoolFull => "payload pool full",
+            SharedPoolError::AllocBufFull => "message ring full",
+            SharedPoolError::ReturnBufFull => "return ring full",
+            SharedPoolError::AllocBufEmpty => "message ring empty",
+            SharedPoolError::PayloadNotConsumerOwned => "payload not owned by the consumer",
+            SharedPoolError::AlreadySplit => "pool already split",
+            SharedPoolError::InvalidState => "invalid pool state",
+            SharedPoolError::InvalidPoolIndex => "invalid pool index",
+            SharedPoolError::PayloadNotVacant => "payload location not vacant",
+            SharedPoolError::NotStaged => "no message staged",
+        })
+    }
 }
 
+impl core::error::Error for SharedPoolError {}
+
 #[derive(Clone, Copy)]
-pub struct PoolIndex<const N: usize>(u32);
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PoolIndex<const N: usize>(pub(crate) u32);
 
 // Get usize from PoolIndex<N>
 impl<const N: usize> TryFrom<PoolIndex<N>> for usize {
@@ -29,118 +68,349 @@ impl<const N: usize> TryFrom<PoolIndex<N>> for usize {
     }
 }
 
+// No payload
+impl<const N: usize> Default for PoolIndex<N> {
+    fn default() -> Self {
+        PoolIndex(N as u32)
+    }
+}
+
 impl<const N: usize>  PoolIndex<N> {
     pub fn is_valid(&self) -> bool {
         self.0 < N as u32
     }
 }
 
-pub trait HasPoolIdx<const N: usize> {
+/// Implemented by the messages carrying a pool index, see
+/// `#[derive(HasPoolIdx)]` with the `derive` feature. Messages are staged in
+/// place, initialized with `Default` before the pool index is set
+pub trait HasPoolIdx<const N: usize>: Default {
     fn get_pool_idx(&self) -> PoolIndex<N>;
     fn set_pool_idx(&mut self, pindex: PoolIndex<N>);
 }
 
-pub struct Producer<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> {
+// A bare index is a message of its own, e.g. for a RingAlloc with no command
+impl<const N: usize> HasPoolIdx<N> for PoolIndex<N> {
+    fn get_pool_idx(&self) -> PoolIndex<N> {
+        *self
+    }
+    fn set_pool_idx(&mut self, pindex: PoolIndex<N>) {
+        *self = pindex;
+    }
+}
+
+/// Up to K bytes of payload stored directly in the message slot
+pub struct InlinePayload<const K: usize> {
+    pub(crate) len: u32,
+    pub(crate) data: [u8; K],
+}
+
+impl<const K: usize> InlinePayload<K> {
+    pub const fn new() -> Self {
+        InlinePayload { len: 0, data: [0; K] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len as usize]
+    }
+}
+
+impl<const K: usize> Default for InlinePayload<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Messages that can carry a small payload inline, skipping the pool
+pub trait HasInlinePayload<const K: usize> {
+    fn inline_payload(&self) -> &InlinePayload<K>;
+    fn inline_payload_mut(&mut self) -> &mut InlinePayload<K>;
+}
+
+/// Write access to a staged payload, passed to the consumer with
+/// `write_done` when dropped
+pub struct PayloadWriteGuard<'a, T> {
+    payload: &'a SharedSingleton<T>,
+    val: &'a mut T,
+}
+
+impl<T> Deref for PayloadWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.val
+    }
+}
+
+impl<T> DerefMut for PayloadWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.val
+    }
+}
+
+impl<T> Drop for PayloadWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // Claimed for write when the guard was made, cannot fail. Not
+        // asserted in release, a panic in drop may abort
+        let res = self.payload.write_done();
+        debug_assert!(res.is_ok());
+    }
+}
+
+/// Read access to a received payload. Dropping it calls `read_done` and
+/// returns the location to the pool.
+pub struct PayloadReadGuard<'a, T, const N: usize, A: PoolAlloc<N>> {
+    payload: &'a SharedSingleton<T>,
+    val: &'a T,
+    pidx: PoolIndex<N>,
+    pool_alloc: &'a A,
+}
+
+impl<T, const N: usize, A: PoolAlloc<N>> PayloadReadGuard<'_, T, N, A> {
+    pub fn pool_idx(&self) -> PoolIndex<N> {
+        self.pidx
+    }
+}
+
+impl<T, const N: usize, A: PoolAlloc<N>> Deref for PayloadReadGuard<'_, T, N, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.val
+    }
+}
+
+impl<T, const N: usize, A: PoolAlloc<N>> Drop for PayloadReadGuard<'_, T, N, A> {
+    fn drop(&mut self) {
+        // Released by hand already, nothing to return
+        if self.payload.read_done().is_ok() {
+            // Given back once per take thanks to read_done above, and the
+            // allocator holds all N locations, cannot fail. Not asserted in
+            // release, a panic in drop may abort
+            let res = self.pool_alloc.give(self.pidx);
+            debug_assert!(res.is_ok());
+        }
+    }
+}
+
+pub struct Producer<'a, T, Q: HasPoolIdx<N> + 'a, const N: usize, const M: usize, A: PoolAlloc<N> = RingAlloc<Q, M>, Nf: Notifier = NoNotify> {
     // Producer handle for the command allocation
-    pub alloc_prod: RingBufProducer<'a, Q, M>,
-    // Consumer handle for the return ringbuf
-    pub return_cons: RingBufConsumer<'a, Q, M>,
+    pub alloc_prod: RingBufProducer<'a, Q, M, IndexPair, Nf>,
+    // Free location tracking of the pool, producer only takes
+    pool_alloc: &'a A,
     // Reference to the payload pool
     pool_ref: &'a [SharedSingleton<T>; N],
+    // Most payloads in flight at once, only the producer updates it
+    high_water: &'a AtomicU32,
+    // Location of an aborted message, reused before the allocator
+    aborted: Option<PoolIndex<N>>,
+    // Pool index of the staged message, not valid if it has no payload.
+    // None if nothing is staged
+    staged: Option<PoolIndex<N>>,
 }
 
-impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> Producer<'a, T, Q, N, M> {
+impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, Nf: Notifier> Producer<'a, T, Q, N, M, A, Nf> {
     pub const fn new(
-        alloc_prod: RingBufProducer<'a, Q, M>,
-        return_cons: RingBufConsumer<'a, Q, M>,
+        alloc_prod: RingBufProducer<'a, Q, M, IndexPair, Nf>,
+        pool_alloc: &'a A,
         pool_ref: &'a [SharedSingleton<T>; N],
+        high_water: &'a AtomicU32,
     ) -> Self {
         Producer {
             alloc_prod,
-            return_cons,
+            pool_alloc,
             pool_ref,
+            high_water,
+            aborted: None,
+            staged: None,
         }
     }
 
-    // Internal - get an item from the pool
-    fn take_pool_item(&mut self) -> PoolIndex<N> {
-        // Check the return queue
-        if let Some(item) = self.return_cons.reader_front() {
-            // If there's a return item it must be a valid
-            // pool index
-            let payload_idx = usize::try_from(item.get_pool_idx()).unwrap();
+    /// Pool locations free for staging
+    pub fn free_payloads(&self) -> u32 {
+        self.pool_alloc.num_free() + self.aborted.is_some() as u32
+    }
 
-            // Assert location indicated as free is actually vacant
-            assert!(self.pool_ref[payload_idx].is_vacant());
+    /// Payloads staged and not yet returned by the consumer
+    pub fn in_flight(&self) -> u32 {
+        N as u32 - self.free_payloads()
+    }
 
-            // Pop the return queue
-            assert!(self.return_cons.pop().is_ok());
+    /// Most payloads in flight at once since the split
+    pub fn high_water(&self) -> u32 {
+        self.high_water.load(Ordering::Relaxed)
+    }
 
-            return PoolIndex(payload_idx as u32);
+    // Internal - get an item from the pool. A location found invalid or
+    // still owned is dropped from the pool and reported
+    fn take_pool_item(&mut self) -> Result<usize, SharedPoolError> {
+        // Check the free locations
+        let pidx = self
+            .aborted
+            .take()
+            .or_else(|| self.pool_alloc.take())
+            .ok_or(SharedPoolError::PoolFull)?;
+        let payload_idx = usize::try_from(pidx).map_err(|_| SharedPoolError::InvalidPoolIndex)?;
+
+        // Location indicated as free must actually be vacant
+        if !self.pool_ref[payload_idx].is_vacant() {
+            return Err(SharedPoolError::PayloadNotVacant);
         }
-        // Otherwise nothing is valid
-        PoolIndex(N as u32)
+
+        // Single writer, load and store is enough
+        let in_flight = self.in_flight();
+        if in_flight > self.high_water.load(Ordering::Relaxed) {
+            self.high_water.store(in_flight, Ordering::Relaxed);
+        }
+
+        Ok(payload_idx)
     }
 
     // Stage item for write without payload
     pub fn stage(&mut self) -> Option<&mut Q> {
-        if let Some(item) = self.alloc_prod.writer_front() {
-            item.set_pool_idx(PoolIndex::<N>(N as u32));
+        // Messages are staged in place, see HasPoolIdx
+        let item = self.alloc_prod.alloc_uninit()?.write(Q::default());
+        item.set_pool_idx(PoolIndex::<N>(N as u32));
+        self.staged = Some(PoolIndex::<N>(N as u32));
 
-            Some(item)
-        } else {
-            None
-        }
+        Some(item)
     }
 
     // Stage a command buffer and an accompanying payload from the pool
     // Return a pair of mutable references if successful
     pub fn stage_with_payload(&mut self) -> Result<(&mut Q, &SharedSingleton<T>), SharedPoolError> {
-        if let Ok(idx) = usize::try_from(self.take_pool_item()) {
-            let payload = &self.pool_ref[idx];
+        // Check the command queue first so that a pool item is never
+        // taken without a message to carry it
+        if self.alloc_prod.alloc_uninit().is_none() {
+            return Err(SharedPoolError::AllocBufFull);
+        }
+        let idx = self.take_pool_item()?;
+        let payload = &self.pool_ref[idx];
+
+        // Checked above
+        let item = self
+            .alloc_prod
+            .alloc_uninit()
+            .ok_or(SharedPoolError::AllocBufFull)?
+            .write(Q::default());
+        item.set_pool_idx(PoolIndex::<N>(idx as u32));
+        self.staged = Some(PoolIndex::<N>(idx as u32));
+
+        Ok((item, payload))
+    }
 
-            if let Some(item) = self.alloc_prod.writer_front() {
-                item.set_pool_idx(PoolIndex::<N>(idx as u32));
+    // Same as stage_with_payload with the payload claimed for write. The
+    // guard passes it to the consumer on drop, which must happen before
+    // the commit.
+    pub fn stage_with_guard(&mut self) -> Result<(&mut Q, PayloadWriteGuard<'a, T>), SharedPoolError> {
+        let pool_ref: &'a [SharedSingleton<T>; N] = self.pool_ref;
+        let (item, _) = self.stage_with_payload()?;
+        let idx = usize::try_from(item.get_pool_idx()).map_err(|_| SharedPoolError::InvalidPoolIndex)?;
+        let payload = &pool_ref[idx];
+        // Vacant location just taken from the pool
+        let val = payload.try_write().ok_or(SharedPoolError::PayloadNotVacant)?;
+
+        Ok((item, PayloadWriteGuard { payload, val }))
+    }
 
-                Ok((item, payload))
-            } else {
-                Err(SharedPoolError::AllocBufFull)
-            }
+    // Stage a command buffer for a payload of len bytes. Payloads that fit
+    // in K bytes are stored inline in the message and None is returned in
+    // place of the pool item. Larger payloads are allocated from the pool.
+    pub fn stage_with_len<const K: usize>(&mut self, len: usize) -> Result<(&mut Q, Option<&SharedSingleton<T>>), SharedPoolError>
+    where
+        Q: HasInlinePayload<K>,
+    {
+        if len <= K {
+            let item = self.stage().ok_or(SharedPoolError::AllocBufFull)?;
+            item.inline_payload_mut().len = len as u32;
+
+            Ok((item, None))
         } else {
-            Err(SharedPoolError::PoolFull)
+            let (item, payload) = self.stage_with_payload()?;
+            // Inline part must not be mistaken as valid
+            item.inline_payload_mut().len = 0;
+
+            Ok((item, Some(payload)))
         }
     }
 
-    // Commit the command. If command can contain payload, check
+    // Un-stage the current message. Its payload, if any, is released
+    // (a pinned value is dropped) and kept for the next staging instead
+    // of going back through the consumer.
+    pub fn abort(&mut self) {
+        // Only the index recorded by the staging is released, the slot
+        // itself may hold a stale message still owning its payload
+        let Some(pidx) = self.staged.take() else { return };
+        if let Ok(idx) = usize::try_from(pidx) {
+            let payload = &self.pool_ref[idx];
+            // Walk any claimed state to vacant, both fail if still vacant
+            let _ = payload.write_done();
+            let _ = payload.read_done();
+            // Staging always reuses an aborted location first, so
+            // there is none held already
+            self.aborted = Some(pidx);
+        }
+    }
+
+    // Commit the staged command. If command can contain payload, check
     // if the payload has already been passed to the consumer.
     pub fn commit(&mut self) -> Result<(), SharedPoolError> {
+        let pidx = self.staged.ok_or(SharedPoolError::NotStaged)?;
         // In payload has been allocated, check if passed to consumer.
-        if let Some(item) = self.alloc_prod.writer_front() {
-            if let Ok(idx) = usize::try_from(item.get_pool_idx()) {
-                if self.pool_ref[idx].try_read().is_none() {
-                    // Payload index is set but not passed to consumer
-                    return Err(SharedPoolError::PayloadNotConsumerOwned);
-                }
+        if let Ok(idx) = usize::try_from(pidx) {
+            if self.pool_ref[idx].try_read().is_none() {
+                // Payload index is set but not passed to consumer
+                return Err(SharedPoolError::PayloadNotConsumerOwned);
             }
         }
         // commit the command queue. Map the only possible commit error (BufFull)
         // to SharedPoolError::AllocBufFull
         self.alloc_prod
             .commit()
-            .map_err(|_| SharedPoolError::AllocBufFull)
+            .map_err(|_| SharedPoolError::AllocBufFull)?;
+        self.staged = None;
+        Ok(())
     }
 }
 
-pub struct Consumer<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> {
+impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, Nf: Notifier> Producer<'static, T, Q, N, M, A, Nf> {
+    // Stage a command buffer and a payload of a static pool as pinned. The
+    // pool can never move, so a value written with `write_pinned` stays at
+    // the same address until the consumer releases it with `read_done`,
+    // which drops it in place.
+    pub fn stage_with_pinned(&mut self) -> Result<(&mut Q, Pin<&'static SharedSingleton<T>>), SharedPoolError> {
+        let pool_ref: &'static [SharedSingleton<T>; N] = self.pool_ref;
+        let (item, _) = self.stage_with_payload()?;
+        let idx = usize::try_from(item.get_pool_idx()).map_err(|_| SharedPoolError::InvalidPoolIndex)?;
+
+        Ok((item, Pin::static_ref(&pool_ref[idx])))
+    }
+}
+
+pub struct Consumer<'a, T, Q: HasPoolIdx<N> + 'a, const N: usize, const M: usize, A: PoolAlloc<N> = RingAlloc<Q, M>, Nf: Notifier = NoNotify> {
     // Consumer handle for the command allocation
-    pub alloc_cons: RingBufConsumer<'a, Q, M>,
-    // Producer handle for the return ringbuf
-    pub return_prod: RingBufProducer<'a, Q, M>,
+    pub alloc_cons: RingBufConsumer<'a, Q, M, IndexPair, Nf>,
+    // Free location tracking of the pool, consumer only gives back
+    pool_alloc: &'a A,
     // Reference to the payload pool
     pool_ref: &'a [SharedSingleton<T>; N],
+    // Most payloads in flight at once, see Producer
+    high_water: &'a AtomicU32,
 }
 
-impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> Consumer<'a, T, Q, N, M> {
+impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, Nf: Notifier> Consumer<'a, T, Q, N, M, A, Nf> {
     pub fn peek_with_payload(&self) -> (Option<&Q>, Option<&SharedSingleton<T>>) {
         let ret = self.alloc_cons.reader_front();
 
@@ -162,6 +432,49 @@ impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> Consumer<'a, T, Q,
         self.alloc_cons.reader_front()
     }
 
+    /// Pool locations free for staging
+    pub fn free_payloads(&self) -> u32 {
+        self.pool_alloc.num_free()
+    }
+
+    /// Payloads staged and not yet returned
+    pub fn in_flight(&self) -> u32 {
+        N as u32 - self.pool_alloc.num_free()
+    }
+
+    /// Most payloads in flight at once since the split
+    pub fn high_water(&self) -> u32 {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    // Message at the head and a guard of its payload, if any. Dropping the
+    // guard releases the payload and returns it to the pool. The consumer
+    // stays borrowed meanwhile so that only one guard can exist.
+    pub fn peek_with_guard(&mut self) -> (Option<&Q>, Option<PayloadReadGuard<'_, T, N, A>>) {
+        let pool_ref: &'a [SharedSingleton<T>; N] = self.pool_ref;
+        match self.alloc_cons.reader_front() {
+            Some(message) => {
+                let pidx = message.get_pool_idx();
+                let guard = usize::try_from(pidx).ok().and_then(|idx| {
+                    let payload = &pool_ref[idx];
+                    payload.try_read().map(|val| PayloadReadGuard {
+                        payload,
+                        val,
+                        pidx,
+                        pool_alloc: self.pool_alloc,
+                    })
+                });
+                (Some(message), guard)
+            }
+            None => (None, None),
+        }
+    }
+
+    // Payload storage, e.g. for handing payloads on by index
+    pub(crate) fn pool_ref(&self) -> &'a [SharedSingleton<T>; N] {
+        self.pool_ref
+    }
+
     pub fn read_pool_item(&self, pidx: PoolIndex<N>) -> Option<&SharedSingleton<T>> {
         if let Ok(idx) = usize::try_from(pidx) {
             Some(&self.pool_ref[idx])
@@ -177,125 +490,278 @@ impl<'a, T, Q: HasPoolIdx<N>, const N: usize, const M: usize> Consumer<'a, T, Q,
             .map_err(|_| SharedPoolError::AllocBufEmpty)
     }
 
+    // Receive the message at the head in one call: f gets the message and
+    // its payload, if any, then the payload is released, the message
+    // popped and the payload location returned to the pool
+    pub fn recv<R, F: FnOnce(&Q, Option<&T>) -> R>(&mut self, f: F) -> Result<R, SharedPoolError> {
+        let message = self.alloc_cons.reader_front().ok_or(SharedPoolError::AllocBufEmpty)?;
+        let pidx = message.get_pool_idx();
+        let payload = match usize::try_from(pidx) {
+            Ok(idx) => Some(&self.pool_ref[idx]),
+            Err(_) => None,
+        };
+        let ret = match payload {
+            Some(payload) => {
+                let val = payload.try_read().ok_or(SharedPoolError::PayloadNotConsumerOwned)?;
+                f(message, Some(val))
+            }
+            None => f(message, None),
+        };
+        if let Some(payload) = payload {
+            payload.read_done().map_err(|_| SharedPoolError::PayloadNotConsumerOwned)?;
+        }
+        self.pop()?;
+        if payload.is_some() {
+            self.pool_alloc.give(pidx)?;
+        }
+        Ok(ret)
+    }
+
     // Return a payload location in the pool back to the Producer
     pub fn return_payload(&mut self, pidx: PoolIndex<N>) -> Result<(), SharedPoolError> {
-        // Allocation a location in the return queue
-        if let Some(re) = self.return_prod.writer_front() {
-            // Assert returned payload idx is at least valid
-            // That's the best we can do from consumer side
-            assert!(pidx.is_valid());
-
-            // pidx is asserted above to be valid
-            // pidx.0 is private, hence user cannot access the value
-            // directly. Also pool_ref is private
-            assert!(self.pool_ref[pidx.0 as usize].is_vacant());
-
-            re.set_pool_idx(pidx);
-
-            self.return_prod
-                .commit()
-                .map_err(|_| SharedPoolError::ReturnBufFull)
-        } else {
-            Err(SharedPoolError::ReturnBufFull)
+        // Returned payload idx must at least be valid and released,
+        // that's the best we can do from consumer side
+        let idx = usize::try_from(pidx).map_err(|_| SharedPoolError::InvalidPoolIndex)?;
+        if !self.pool_ref[idx].is_vacant() {
+            return Err(SharedPoolError::PayloadNotVacant);
         }
+
+        self.pool_alloc.give(pidx)
     }
 }
 
-pub struct SharedPool<T, Q: HasPoolIdx<N>, const N: usize, const M: usize> {
-    alloc_rbuf: RingBuf<Q, M>,
-    return_rbuf: RingBuf<Q, M>,
+impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, Nf: Notifier> Consumer<'static, T, Q, N, M, A, Nf> {
+    // Pinned access to the payload of the message at the head of a static
+    // pool, see Producer::stage_with_pinned
+    pub fn peek_with_pinned(&self) -> (Option<&Q>, Option<Pin<&'static SharedSingleton<T>>>) {
+        let pool_ref: &'static [SharedSingleton<T>; N] = self.pool_ref;
+        match self.alloc_cons.reader_front() {
+            Some(message) => (
+                Some(message),
+                usize::try_from(message.get_pool_idx())
+                    .ok()
+                    .map(|idx| Pin::static_ref(&pool_ref[idx])),
+            ),
+            _ => (None, None),
+        }
+    }
+}
+
+/// Plain-old-data copy of the SharedPool control state, C being the
+/// allocator checkpoint type
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolCheckpoint<C, const N: usize> {
+    pub alloc_rbuf: RingBufCheckpoint,
+    pub pool_alloc: C,
+    pub owners: [u8; N],
+}
+
+/// Snapshot of the SharedPool state for failure reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PoolState {
+    /// Message ring, its split flags are the pool's
+    pub alloc_rbuf: RingState,
+    pub num_free: u32,
+    pub pool_capacity: u32,
+}
+
+/// Producer and Consumer pair returned by SharedPool::split
+pub type SharedPoolHandles<'a, T, Q, const N: usize, const M: usize, A, Nf = NoNotify> =
+    (Producer<'a, T, Q, N, M, A, Nf>, Consumer<'a, T, Q, N, M, A, Nf>);
+
+/// Command ring of M messages Q with a pool of N payloads T. The free pool
+/// locations are tracked by the allocator backend A, a return ring buffer by
+/// default. See `pool_alloc::BitmapAlloc` for the low RAM alternative.
+pub struct SharedPool<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N> = RingAlloc<Q, M>, Nf: Notifier = NoNotify> {
+    alloc_rbuf: RingBuf<Q, M, IndexPair, Nf>,
+    pool_alloc: A,
     pool: [SharedSingleton<T>; N],
+    high_water: AtomicU32,
 }
 
-unsafe impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize> Sync for SharedPool<T, Q, N, M> {}
+// Payloads and messages move between the sides, the allocator is shared
+// under the same protocol
+unsafe impl<T: Send, Q: HasPoolIdx<N> + Send, const N: usize, const M: usize, A: PoolAlloc<N> + Sync, Nf: Notifier + Sync> Sync for SharedPool<T, Q, N, M, A, Nf> {}
+
+impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>> Default for SharedPool<T, Q, N, M, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize> SharedPool<T, Q, N, M> {
+impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>> SharedPool<T, Q, N, M, A> {
     // new
-    // initialize return_rbuf to be full
-    // return to be empty
+    // allocator starts with no free location, filled
+    // when the consumer is split
 
-    const OK: () = assert!(M >= N, "Ringbuf capacity (M) must be >= Pool Capacity (N)");
-    
-    #[allow(clippy::let_unit_value)]
     pub const fn new() -> Self {
-        let _: () = SharedPool::<T, Q, N, M>::OK;
+        Self::with_notifiers(NoNotify, NoNotify)
+    }
+
+    /// Create a pool in the len bytes at ptr, e.g. memory mapped with mmap
+    /// or at a fixed address shared with another core. Each side splits its
+    /// own half afterwards. Both sides must be built with the same layout of
+    /// Self.
+    ///
+    /// # Safety
+    /// ptr must be valid for reads and writes of len bytes for 'a and not
+    /// accessed by the other side before this returns.
+    pub unsafe fn from_raw_parts<'a>(ptr: *mut u8, len: usize) -> Result<&'a Self, ShmError> {
+        check_region::<Self>(ptr, len)?;
+        let pool = ptr as *mut Self;
+        pool.write(Self::new());
+        Ok(&*pool)
+    }
+
+    /// Attach to a pool created with `from_raw_parts` by the other side.
+    /// The message ring indices and payload owner flags are validated, the
+    /// allocator state is not.
+    ///
+    /// # Safety
+    /// ptr must be valid for reads and writes of len bytes for 'a.
+    pub unsafe fn attach<'a>(ptr: *mut u8, len: usize) -> Result<&'a Self, ShmError> {
+        check_region::<Self>(ptr, len)?;
+        let pool = &*(ptr as *const Self);
+        let ring = pool.alloc_rbuf.checkpoint().ring;
+        if !Index::<M>::is_valid_pair(ring.wr_idx, ring.rd_idx)
+            || !pool.pool.iter().all(|p| SharedSingleton::<T>::is_valid_checkpoint(p.checkpoint()))
+        {
+            return Err(ShmError::InvalidState);
+        }
+        Ok(pool)
+    }
+}
+
+impl<T, Q: HasPoolIdx<N>, const N: usize, const M: usize, A: PoolAlloc<N>, Nf: Notifier> SharedPool<T, Q, N, M, A, Nf> {
+
+    /// Pool notifying the consumer after each message commit and the
+    /// producer after each message pop, see `RingBuf::with_notifiers`
+    pub const fn with_notifiers(on_commit: Nf, on_pop: Nf) -> Self {
         SharedPool {
-            alloc_rbuf: RingBuf::new(),
-            return_rbuf: RingBuf::new(),
+            alloc_rbuf: RingBuf::with_notifiers(on_commit, on_pop),
+            pool_alloc: A::INIT,
             pool: [SharedSingleton::INIT_0; N],
+            high_water: AtomicU32::new(0),
         }
     }
 
     // Return the producer, once in life time
-    pub fn split_prod(&self) -> Result<Producer<'_, T, Q, N, M>, SharedPoolError> {
-        if self.alloc_rbuf.has_split_prod() || self.return_rbuf.has_split_cons() {
+    pub fn split_prod(&self) -> Result<Producer<'_, T, Q, N, M, A, Nf>, SplitError> {
+        if self.alloc_rbuf.has_split_prod() {
             // Can only split once in life time
-            Err(SharedPoolError::AlreadySplit)
+            Err(SplitError::AlreadySplitProd)
         } else {
-            // Split the allocation and return ring buffers to their
-            // corresponding producers and consumers. Not expected to fail
-            // since this is already protected by our own has split flag
-            let alloc_p = self.alloc_rbuf.split_prod().unwrap();
-            let ret_c = self.return_rbuf.split_cons().unwrap();
-
-            // Distribute the producers and consumers to the final
-            // Producer and Consumer wrappers
-            let producer = Producer {
-                alloc_prod: alloc_p,
-                return_cons: ret_c,
-                pool_ref: &self.pool,
-            };
+            // Split the allocation ring buffer producer, protected by the
+            // has split flag checked above
+            let alloc_p = self.alloc_rbuf.split_prod()?;
+
+            // Distribute the producer and allocator to the final
+            // Producer wrapper
+            let producer = Producer::new(alloc_p, &self.pool_alloc, &self.pool, &self.high_water);
             Ok(producer)
         }
     }
 
     // Return the consumer, once in life time
-    pub fn split_cons(&self) -> Result<Consumer<'_, T, Q, N, M>, SharedPoolError> {
-        if self.alloc_rbuf.has_split_cons() || self.return_rbuf.has_split_prod() {
+    pub fn split_cons(&self) -> Result<Consumer<'_, T, Q, N, M, A, Nf>, SplitError> {
+        if self.alloc_rbuf.has_split_cons() {
             // Can only split once in life time
-            Err(SharedPoolError::AlreadySplit)
+            Err(SplitError::AlreadySplitCons)
         } else {
-            // Split the allocation and return ring buffers to their
-            // corresponding producers and consumers. Not expected to fail
-            // since this is already protected by our own has split flag
-            let alloc_c = self.alloc_rbuf.split_cons().unwrap();
-            let mut ret_p = self.return_rbuf.split_prod().unwrap();
-
-            // Pre-fill the return queue with all the pool indices
-            for i in 0..N {
-                // Can unwrap here as we don't expect this fail
-                let item = ret_p.writer_front().unwrap();
-                item.set_pool_idx(PoolIndex(i as u32));
-                ret_p.commit().unwrap();
-            }
+            // Split the allocation ring buffer consumer, protected by the
+            // has split flag checked above
+            let alloc_c = self.alloc_rbuf.split_cons()?;
+
+            // Hand all the pool locations to the producer
+            self.pool_alloc.fill();
 
             let consumer = Consumer {
                 alloc_cons: alloc_c,
-                return_prod: ret_p,
+                pool_alloc: &self.pool_alloc,
                 pool_ref: &self.pool,
+                high_water: &self.high_water,
             };
             Ok(consumer)
         }
     }
-    // Split both producer and consumer handle together
-    pub fn split(&self) -> Result<(Producer<'_, T, Q, N, M>, Consumer<'_, T, Q, N, M>), SharedPoolError> {
-
-        match (self.split_prod(), self.split_cons())  {
-            (Ok(prod), Ok(cons)) => Ok((prod, cons)),
-            _ => Err(SharedPoolError::AlreadySplit)
+    // Split both producer and consumer handle together. Both flags are
+    // checked first so that a failure leaves neither side split, the error
+    // tells which side was split before
+    pub fn split(&self) -> Result<SharedPoolHandles<'_, T, Q, N, M, A, Nf>, SplitError> {
+        if self.alloc_rbuf.has_split_prod() {
+            return Err(SplitError::AlreadySplitProd);
         }
+        if self.alloc_rbuf.has_split_cons() {
+            return Err(SplitError::AlreadySplitCons);
+        }
+        Ok((self.split_prod()?, self.split_cons()?))
     }
 
     pub fn num_free(&self) -> u32 {
-        self.return_rbuf.len()
+        self.pool_alloc.num_free()
+    }
+
+    /// Most payloads in flight at once, for sizing N
+    pub fn high_water(&self) -> u32 {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    /// Capture the message ring and allocator state
+    pub fn state(&self) -> PoolState {
+        PoolState {
+            alloc_rbuf: self.alloc_rbuf.state(),
+            num_free: self.pool_alloc.num_free(),
+            pool_capacity: N as u32,
+        }
+    }
+
+    /// Attach the current state to error
+    pub fn diagnose<E>(&self, error: E) -> Diagnosed<E, PoolState> {
+        Diagnosed { error, state: self.state() }
+    }
+
+    /// Export the message ring, allocator and payload owner state
+    pub fn checkpoint(&self) -> PoolCheckpoint<A::Checkpoint, N> {
+        PoolCheckpoint {
+            alloc_rbuf: self.alloc_rbuf.checkpoint(),
+            pool_alloc: self.pool_alloc.checkpoint(),
+            owners: core::array::from_fn(|i| self.pool[i].checkpoint()),
+        }
+    }
+
+    /// Re-import a checkpoint. The owner flags are validated before anything
+    /// is restored, the ring and allocator states are validated by their own
+    /// restore.
+    ///
+    /// # Safety
+    /// Messages and payloads must be retained since the checkpoint and no
+    /// handle may be in use during the restore. Handles split before must
+    /// `refresh` their message ring handles afterwards.
+    pub unsafe fn restore(&self, cp: &PoolCheckpoint<A::Checkpoint, N>) -> Result<(), SharedPoolError> {
+        if !cp.owners.iter().all(|o| SharedSingleton::<T>::is_valid_checkpoint(*o)) {
+            return Err(SharedPoolError::InvalidState);
+        }
+        self.alloc_rbuf
+            .restore(&cp.alloc_rbuf)
+            .map_err(|_| SharedPoolError::InvalidState)?;
+        self.pool_alloc.restore(&cp.pool_alloc)?;
+        for (payload, owner) in self.pool.iter().zip(cp.owners.iter()) {
+            // Validated above
+            let _ = payload.restore(*owner);
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::cell::Cell;
 
     const POOL_DEPTH: usize = 16;
+    #[derive(Default)]
     pub struct Message {
         id: u32,
         payload: PoolIndex<POOL_DEPTH>,
@@ -314,11 +780,31 @@ mod tests {
         value: u32,
     }
 
-    static SHARED_POOL: SharedPool<Payload, Message, 16, 32> = SharedPool {
-        alloc_rbuf: RingBuf::INIT_0,
-        return_rbuf: RingBuf::INIT_0,
-        pool: [SharedSingleton::<Payload>::INIT_0; 16],
-    };
+    #[derive(Default)]
+    pub struct SmallMessage {
+        payload: PoolIndex<POOL_DEPTH>,
+        inline: InlinePayload<8>,
+    }
+
+    impl HasPoolIdx<POOL_DEPTH> for SmallMessage {
+        fn get_pool_idx(&self) -> PoolIndex<POOL_DEPTH> {
+            self.payload
+        }
+        fn set_pool_idx(&mut self, pindex: PoolIndex<POOL_DEPTH>) {
+            self.payload = pindex
+        }
+    }
+
+    impl HasInlinePayload<8> for SmallMessage {
+        fn inline_payload(&self) -> &InlinePayload<8> {
+            &self.inline
+        }
+        fn inline_payload_mut(&mut self) -> &mut InlinePayload<8> {
+            &mut self.inline
+        }
+    }
+
+    static SHARED_POOL: SharedPool<Payload, Message, 16, 32> = SharedPool::new();
 
     #[test]
     fn test_basic() {
@@ -390,5 +876,306 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bitmap_alloc() {
+        use crate::pool_alloc::{bitmap_words, BitmapAlloc};
+
+        // Message ring shallower than the pool is fine without the return ring
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 4, BitmapAlloc<{ bitmap_words(POOL_DEPTH) }>> =
+            SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        message.id = 1;
+        payload.try_write().unwrap().value = 2;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+
+        assert!(pool.num_free() == POOL_DEPTH as u32 - 1);
+        let state = pool.state();
+        assert!(state.num_free == POOL_DEPTH as u32 - 1 && state.alloc_rbuf.len == 1);
+        assert!(state.alloc_rbuf.has_split_prod && state.alloc_rbuf.has_split_cons);
+
+        let (recvd, payload) = consumer.peek_with_payload();
+        let pidx = recvd.unwrap().get_pool_idx();
+        assert!(payload.unwrap().try_read().unwrap().value == 2);
+        assert!(payload.unwrap().read_done().is_ok());
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.return_payload(pidx).is_ok());
+
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+    }
+
+    #[test]
+    fn test_inline_payload() {
+        let pool: SharedPool<[u8; 64], SmallMessage, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        // Small payload goes inline, no pool item used
+        let (message, payload) = producer.stage_with_len(8).unwrap();
+        assert!(payload.is_none());
+        message.inline_payload_mut().as_mut_slice().copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(producer.commit().is_ok());
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+
+        // Larger payload is allocated from the pool
+        let (message, payload) = producer.stage_with_len(9).unwrap();
+        assert!(message.inline_payload().is_empty());
+        payload.unwrap().try_write().unwrap()[..9].copy_from_slice(&[9; 9]);
+        payload.unwrap().write_done().unwrap();
+        assert!(producer.commit().is_ok());
+        assert!(pool.num_free() == POOL_DEPTH as u32 - 1);
+
+        let (recvd, payload) = consumer.peek_with_payload();
+        assert!(payload.is_none());
+        assert!(recvd.unwrap().inline_payload().as_slice() == [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(consumer.pop().is_ok());
+
+        let (recvd, payload) = consumer.peek_with_payload();
+        let pidx = recvd.unwrap().get_pool_idx();
+        assert!(payload.unwrap().try_read().unwrap()[..9] == [9; 9]);
+        assert!(payload.unwrap().read_done().is_ok());
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.return_payload(pidx).is_ok());
+    }
+
+    #[test]
+    fn test_pinned_payload() {
+        use core::marker::PhantomPinned;
+
+        // Self referential style payload, cannot be moved once pinned
+        pub struct DmaDesc {
+            buf: [u8; 4],
+            _pin: PhantomPinned,
+        }
+
+        static PINNED_POOL: SharedPool<DmaDesc, Message, POOL_DEPTH, 32> = SharedPool::new();
+
+        let (mut producer, mut consumer) = PINNED_POOL.split().unwrap();
+
+        let (message, payload) = producer.stage_with_pinned().unwrap();
+        message.id = 7;
+        let desc = payload.write_pinned(DmaDesc { buf: [1, 2, 3, 4], _pin: PhantomPinned }).ok().unwrap();
+        let addr = &desc.buf as *const _;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+
+        let (recvd, payload) = consumer.peek_with_pinned();
+        let payload = payload.unwrap();
+        let desc = payload.try_read_pinned().unwrap();
+        assert!(recvd.unwrap().id == 7);
+        assert!(desc.buf == [1, 2, 3, 4] && core::ptr::eq(&desc.buf, addr));
+
+        let pidx = recvd.unwrap().get_pool_idx();
+        assert!(payload.read_done().is_ok());
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.return_payload(pidx).is_ok());
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        message.id = 5;
+        payload.try_write().unwrap().value = 6;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+
+        let cp = pool.checkpoint();
+
+        // Consume everything, then go back to the checkpoint
+        let (recvd, payload) = consumer.peek_with_payload();
+        let pidx = recvd.unwrap().get_pool_idx();
+        assert!(payload.unwrap().read_done().is_ok());
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.return_payload(pidx).is_ok());
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+
+        assert!(unsafe { pool.restore(&cp) }.is_ok());
+        producer.alloc_prod.refresh();
+        consumer.alloc_cons.refresh();
+        assert!(pool.num_free() == POOL_DEPTH as u32 - 1);
+        let (recvd, payload) = consumer.peek_with_payload();
+        assert!(recvd.unwrap().id == 5);
+        assert!(payload.unwrap().try_read().unwrap().value == 6);
+
+        let mut bad = cp;
+        bad.owners[3] = 42;
+        assert!(unsafe { pool.restore(&bad) }.is_err());
+        assert!(pool.checkpoint() == cp);
+    }
+
+    #[test]
+    fn test_payload_guards() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        let (message, mut payload) = producer.stage_with_guard().unwrap();
+        message.id = 1;
+        payload.value = 10;
+        // Not passed to the consumer while the guard is alive
+        assert!(producer.commit() == Err(SharedPoolError::PayloadNotConsumerOwned));
+        drop(payload);
+        assert!(producer.commit().is_ok());
+        assert!(pool.num_free() == POOL_DEPTH as u32 - 1);
+
+        let (recvd, payload) = consumer.peek_with_guard();
+        assert!(recvd.unwrap().id == 1);
+        assert!(payload.unwrap().value == 10);
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.peek_with_guard().0.is_none());
+    }
+
+    #[test]
+    fn test_recv() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+        assert!(consumer.recv(|_, _| ()) == Err(SharedPoolError::AllocBufEmpty));
+
+        producer.stage().unwrap().id = 1;
+        assert!(producer.commit().is_ok());
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        message.id = 2;
+        payload.try_write().unwrap().value = 20;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+
+        assert!(consumer.recv(|m, p| (m.id, p.is_none())) == Ok((1, true)));
+        assert!(consumer.recv(|m, p| (m.id, p.unwrap().value)) == Ok((2, 20)));
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+        assert!(consumer.peek().is_none());
+    }
+
+    #[test]
+    fn test_pool_metrics() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        for round in 0..2 {
+            for _ in 0..3 - round {
+                let (_, payload) = producer.stage_with_payload().unwrap();
+                payload.try_write().unwrap().value = 0;
+                payload.write_done().unwrap();
+                assert!(producer.commit().is_ok());
+            }
+            assert!(producer.in_flight() == 3 - round);
+            assert!(consumer.free_payloads() == POOL_DEPTH as u32 - 3 + round);
+            while consumer.recv(|_, _| ()).is_ok() {}
+            assert!(consumer.in_flight() == 0);
+        }
+        // Message only staging does not count
+        producer.stage().unwrap();
+        assert!(producer.commit().is_ok());
+        assert!(producer.high_water() == 3);
+        assert!(consumer.high_water() == 3);
+        assert!(pool.high_water() == 3);
+    }
+
+    #[test]
+    fn test_abort() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        let pidx = message.get_pool_idx();
+        payload.try_write().unwrap().value = 1;
+        producer.abort();
+        assert!(producer.free_payloads() == POOL_DEPTH as u32);
+        assert!(producer.in_flight() == 0);
+        // Nothing staged, nothing to commit
+        producer.abort();
+        assert!(producer.commit() == Err(SharedPoolError::NotStaged));
+        assert!(consumer.peek().is_none());
+
+        // The aborted location is staged again
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        assert!(usize::try_from(message.get_pool_idx()) == usize::try_from(pidx));
+        payload.try_write().unwrap().value = 2;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+        assert!(consumer.recv(|_, p| p.unwrap().value) == Ok(2));
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+
+        // Nothing staged, a message in flight keeps its payload
+        let (_, payload) = producer.stage_with_payload().unwrap();
+        payload.try_write().unwrap().value = 3;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+        producer.abort();
+        assert!(producer.in_flight() == 1);
+        assert!(consumer.recv(|m, p| (m.get_pool_idx().is_valid(), p.is_some())) == Ok((true, true)));
+        assert!(producer.free_payloads() == POOL_DEPTH as u32);
+    }
+
+    #[test]
+    fn test_invalid_return() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        let (message, payload) = producer.stage_with_payload().unwrap();
+        let pidx = message.get_pool_idx();
+        payload.try_write().unwrap().value = 1;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+
+        // Errors instead of panics
+        assert!(consumer.return_payload(PoolIndex(POOL_DEPTH as u32)) == Err(SharedPoolError::InvalidPoolIndex));
+        assert!(consumer.return_payload(pidx) == Err(SharedPoolError::PayloadNotVacant));
+        assert!(consumer.recv(|_, p| p.unwrap().value) == Ok(1));
+        assert!(pool.num_free() == POOL_DEPTH as u32);
+    }
+
+    #[test]
+    fn test_split_all_or_nothing() {
+        let pool: SharedPool<Payload, Message, POOL_DEPTH, 32> = SharedPool::new();
+        let _consumer = pool.split_cons().unwrap();
+        // The producer is still available after the failed split
+        assert!(pool.split().err() == Some(SplitError::AlreadySplitCons));
+        assert!(pool.split_cons().err() == Some(SplitError::AlreadySplitCons));
+        let _producer = pool.split_prod().unwrap();
+        assert!(pool.split().err() == Some(SplitError::AlreadySplitProd));
+    }
+
+    #[test]
+    fn test_raw_parts() {
+        type Pool = SharedPool<Payload, Message, POOL_DEPTH, 32>;
+        #[repr(C, align(64))]
+        struct Region([u8; 8192]);
+        let mut region = Box::new(Region([0xff; 8192]));
+        let ptr = region.0.as_mut_ptr();
+        assert!(core::mem::size_of::<Pool>() <= 8192);
+
+        assert!(unsafe { Pool::attach(ptr, 8192) }.err() == Some(ShmError::InvalidState));
+        assert!(unsafe { Pool::from_raw_parts(ptr, 16) }.err() == Some(ShmError::RegionTooSmall));
+        let creator = unsafe { Pool::from_raw_parts(ptr, 8192) }.unwrap();
+        let mut producer = creator.split_prod().unwrap();
+        let other = unsafe { Pool::attach(ptr, 8192) }.unwrap();
+        let mut consumer = other.split_cons().unwrap();
+
+        let (_, payload) = producer.stage_with_payload().unwrap();
+        payload.try_write().unwrap().value = 3;
+        payload.write_done().unwrap();
+        assert!(producer.commit().is_ok());
+        assert!(consumer.recv(|_, p| p.unwrap().value) == Ok(3));
+    }
 
+    #[test]
+    fn test_notifiers() {
+        let commits = Cell::new(0);
+        let on_commit = || commits.set(commits.get() + 1);
+        let on_pop = || {};
+        let pool = SharedPool::<Payload, Message, POOL_DEPTH, 32, RingAlloc<Message, 32>, &dyn Fn()>::with_notifiers(&on_commit, &on_pop);
+        let (mut producer, mut consumer) = pool.split().unwrap();
+
+        let _ = producer.stage().unwrap();
+        assert!(commits.get() == 0);
+        assert!(producer.commit().is_ok());
+        assert!(commits.get() == 1);
+        assert!(consumer.recv(|_, p| p.is_none()) == Ok(true));
+    }
 }
@@ -3,17 +3,47 @@
 use core::{cell::Cell, cell::UnsafeCell};
 use core::mem::MaybeUninit;
 use core::marker::Sync;
+use core::pin::Pin;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ErrCode {
-    NotOwned
+    NotOwned,
+    InvalidState,
 }
 
+impl core::fmt::Display for ErrCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ErrCode::NotOwned => "singleton not owned by the caller",
+            ErrCode::InvalidState => "invalid singleton state",
+        })
+    }
+}
+
+impl core::error::Error for ErrCode {}
+
+#[repr(u8)]
 #[derive(Copy, Clone, PartialEq)]
 enum Owner {
     Vacant, // can be claimed for write
     Producer,  // claimed state
     Consumer,  // write done, passed to consumer
+    ProducerPinned, // claimed with an initialized pinned value
+    ConsumerPinned, // pinned value passed to consumer, dropped on release
+}
+
+impl Owner {
+    fn from_u8(val: u8) -> Option<Owner> {
+        match val {
+            0 => Some(Owner::Vacant),
+            1 => Some(Owner::Producer),
+            2 => Some(Owner::Consumer),
+            3 => Some(Owner::ProducerPinned),
+            4 => Some(Owner::ConsumerPinned),
+            _ => None,
+        }
+    }
 }
 
 /// Single producer Single consumer Shared Singleton
@@ -33,11 +63,19 @@ pub struct SharedSingleton <T> {
 
 // Delcare this is thread safe due to the owner protection
 // sequence (Producer-> consumer , consumer -> owner)
-unsafe impl <T> Sync for SharedSingleton<T> {}
+unsafe impl <T: Send> Sync for SharedSingleton<T> {}
+
+impl <T> Default for SharedSingleton<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl <T> SharedSingleton<T> {
     
+    #[allow(clippy::declare_interior_mutable_const)]
     const INIT_U: UnsafeCell<MaybeUninit<T>> = UnsafeCell::new(MaybeUninit::uninit());
+    #[allow(clippy::declare_interior_mutable_const)]
     pub const INIT_0: SharedSingleton<T> = Self::new();
 
     #[inline]
@@ -50,7 +88,31 @@ impl <T> SharedSingleton<T> {
         self.owner.get() == Owner::Vacant
     }
 
+    /// Export the owner flag as plain data
+    #[inline]
+    pub fn checkpoint(&self) -> u8 {
+        self.owner.get() as u8
+    }
+
+    /// Whether owner is a value exported by `checkpoint`
+    #[inline]
+    pub fn is_valid_checkpoint(owner: u8) -> bool {
+        Owner::from_u8(owner).is_some()
+    }
+
+    /// Re-import the owner flag exported by `checkpoint`
+    ///
+    /// # Safety
+    /// The singleton must hold the value written before the checkpoint
+    /// if the flag indicates one
+    #[inline]
+    pub unsafe fn restore(&self, owner: u8) -> Result<(), ErrCode> {
+        self.owner.set(Owner::from_u8(owner).ok_or(ErrCode::InvalidState)?);
+        Ok(())
+    }
+
     /// Returns mutable reference of T if singleton is vacant
+    #[allow(clippy::mut_from_ref)]
     #[inline]
     pub fn try_write(&self) -> Option<&mut T> {
         if self.owner.get() == Owner::Vacant {
@@ -64,15 +126,47 @@ impl <T> SharedSingleton<T> {
         }
     }
 
+    /// Moves val into the singleton if vacant and returns it pinned.
+    /// The value is dropped in place by `read_done` before the location
+    /// can be reused, as required by the Pin contract. Otherwise val is
+    /// handed back.
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    pub fn write_pinned(self: Pin<&Self>, val: T) -> Result<Pin<&mut T>, T> {
+        if self.owner.get() == Owner::Vacant {
+            let x: *mut MaybeUninit<T> = self.ucell.get();
+            let t: &mut T = unsafe { (*x).write(val) };
+            self.owner.set(Owner::ProducerPinned);
+            // The singleton is pinned hence so is its content
+            Ok(unsafe { Pin::new_unchecked(t) })
+        }
+        else {
+            Err(val)
+        }
+    }
+
     /// Pass ownership to Consumer from Producer
     #[inline]
     pub fn write_done(&self) -> Result<(),ErrCode> {
-        if self.owner.get() == Owner::Producer {
-            self.owner.set(Owner::Consumer);
-            Ok(())
+        match self.owner.get() {
+            Owner::Producer => self.owner.set(Owner::Consumer),
+            Owner::ProducerPinned => self.owner.set(Owner::ConsumerPinned),
+            _ => return Err(ErrCode::NotOwned),
+        }
+        Ok(())
+    }
+
+    /// Pinned reference to the value written by `write_pinned`, if owned
+    /// by Consumer, otherwise None
+    #[inline]
+    pub fn try_read_pinned(self: Pin<&Self>) -> Option<Pin<&T>> {
+        if self.owner.get() == Owner::ConsumerPinned {
+            let x: *mut MaybeUninit<T> = self.ucell.get();
+            let t: & T = unsafe {  & *(x as * const T)};
+            Some(unsafe { Pin::new_unchecked(t) })
         }
         else {
-            Err(ErrCode::NotOwned)
+            None
         }
     }
 
@@ -81,7 +175,7 @@ impl <T> SharedSingleton<T> {
     /// NOTE: does not check for multiple calls
     #[inline]
     pub fn try_read(&self) -> Option<&T> {
-        if self.owner.get() == Owner::Consumer {
+        if matches!(self.owner.get(), Owner::Consumer | Owner::ConsumerPinned) {
             let x: *mut MaybeUninit<T> = self.ucell.get();
             let t: & T = unsafe {  & *(x as * const T)};
             Some(t)
@@ -91,16 +185,17 @@ impl <T> SharedSingleton<T> {
         }
     }
 
-    /// Release location back to Producer
+    /// Release location back to Producer. A pinned value is dropped
+    /// in place first
     #[inline]
     pub fn read_done(&self) -> Result<(),ErrCode> {
-        if self.owner.get() == Owner::Consumer {
-            self.owner.set(Owner::Vacant);
-            Ok(())
-        }
-        else {
-            Err(ErrCode::NotOwned)
+        match self.owner.get() {
+            Owner::Consumer => (),
+            Owner::ConsumerPinned => unsafe { (*self.ucell.get()).assume_init_drop() },
+            _ => return Err(ErrCode::NotOwned),
         }
+        self.owner.set(Owner::Vacant);
+        Ok(())
     }
 }
 
@@ -138,4 +233,23 @@ mod tests {
 
     }
 
+    #[test]
+    fn pinned_value_dropped_on_release() {
+        use std::rc::Rc;
+
+        let shared = Box::pin(SharedSingleton::<Rc<()>>::new());
+        let tracker = Rc::new(());
+
+        let val = shared.as_ref().write_pinned(tracker.clone()).unwrap();
+        assert!(Rc::strong_count(&val) == 2);
+
+        // Only one value at a time
+        assert!(shared.as_ref().write_pinned(tracker.clone()).is_err());
+        assert!(shared.write_done().is_ok());
+
+        assert!(shared.as_ref().try_read_pinned().is_some());
+        assert!(shared.read_done().is_ok());
+        assert!(Rc::strong_count(&tracker) == 1);
+        assert!(shared.is_vacant());
+    }
 }
@@ -0,0 +1,233 @@
+//! Ring buffer in a `#[repr(C)]` shared memory region, for exchanging items
+//! with another core programmed in C (see `ShmRingHeader` in ringbuf_ref.h).
+//!
+//! The region starts with a header followed by the storage:
+//!
+//! | offset | field                                             |
+//! |--------|---------------------------------------------------|
+//! | 0      | magic, `SHM_MAGIC`                                |
+//! | 4      | version, `SHM_VERSION`                            |
+//! | 8      | capacity N                                        |
+//! | 12     | element size, the stride of T                     |
+//! | 16     | read index                                        |
+//! | 20     | write index                                       |
+//! | 24     | N elements, rounded up to the alignment of T      |
+//!
+//! The indices follow the `RingBufRef` convention: free running for power
+//! of two N, wrapping at 2*N otherwise. One side creates the region with
+//! `from_raw`, the other validates it with `attach`.
+
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+
+use crate::ringbuf_ref::{ErrCode, Index};
+
+pub const SHM_MAGIC: u32 = 0x5350_5343;
+pub const SHM_VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShmError {
+    /// Region not aligned for the header or T
+    Misaligned,
+    /// Region shorter than the structure placed in it
+    RegionTooSmall,
+    BadMagic,
+    BadVersion,
+    CapacityMismatch,
+    ElementSizeMismatch,
+    /// Indices out of range or more than N apart
+    InvalidState,
+}
+
+// Check that len bytes at ptr can hold an S
+pub(crate) fn check_region<S>(ptr: *mut u8, len: usize) -> Result<(), ShmError> {
+    if !(ptr as usize).is_multiple_of(align_of::<S>()) {
+        Err(ShmError::Misaligned)
+    } else if len < size_of::<S>() {
+        Err(ShmError::RegionTooSmall)
+    } else {
+        Ok(())
+    }
+}
+
+/// Header at the start of the shared region
+#[repr(C)]
+pub struct ShmHeader<const N: usize> {
+    pub magic: u32,
+    pub version: u32,
+    pub capacity: u32,
+    pub elem_size: u32,
+    rd_idx: Index<N>,
+    wr_idx: Index<N>,
+}
+
+/// Handle to a ring in a shared region. Same SPSC premise as RingBufRef,
+/// each side only uses its half of the API
+pub struct SharedMemRing<'a, T: Copy, const N: usize> {
+    header: &'a ShmHeader<N>,
+    buffer: *mut T,
+    _region: PhantomData<&'a mut [T; N]>,
+}
+
+unsafe impl<'a, T: Copy + Send, const N: usize> Send for SharedMemRing<'a, T, N> {}
+unsafe impl<'a, T: Copy + Send, const N: usize> Sync for SharedMemRing<'a, T, N> {}
+
+impl<'a, T: Copy, const N: usize> SharedMemRing<'a, T, N> {
+
+    /// Offset of the storage in the region
+    pub const STORAGE_OFFSET: usize = size_of::<ShmHeader<N>>().div_ceil(align_of::<T>()) * align_of::<T>();
+    /// Size in bytes of the region
+    pub const SIZE: usize = Self::STORAGE_OFFSET + N * size_of::<T>();
+
+    fn check_align(ptr: *mut u8) -> Result<(), ShmError> {
+        if !(ptr as usize).is_multiple_of(align_of::<ShmHeader<N>>().max(align_of::<T>())) {
+            Err(ShmError::Misaligned)
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn from_region(ptr: *mut u8) -> Self {
+        SharedMemRing {
+            header: &*(ptr as *const ShmHeader<N>),
+            buffer: ptr.add(Self::STORAGE_OFFSET) as *mut T,
+            _region: PhantomData,
+        }
+    }
+
+    /// Create an empty ring in the region at ptr, writing the header
+    ///
+    /// # Safety
+    /// ptr must point to SIZE bytes valid for 'a and not accessed by
+    /// the other side before this returns.
+    pub unsafe fn from_raw(ptr: *mut u8) -> Result<Self, ShmError> {
+        Self::check_align(ptr)?;
+        core::ptr::write(ptr as *mut ShmHeader<N>, ShmHeader {
+            magic: SHM_MAGIC,
+            version: SHM_VERSION,
+            capacity: N as u32,
+            elem_size: size_of::<T>() as u32,
+            rd_idx: Index::new(),
+            wr_idx: Index::new(),
+        });
+        Ok(Self::from_region(ptr))
+    }
+
+    /// Attach to a ring created by the other side, validating the header
+    ///
+    /// # Safety
+    /// ptr must point to SIZE bytes valid for 'a.
+    pub unsafe fn attach(ptr: *mut u8) -> Result<Self, ShmError> {
+        Self::check_align(ptr)?;
+        let ring = Self::from_region(ptr);
+        let header = ring.header;
+        if header.magic != SHM_MAGIC {
+            return Err(ShmError::BadMagic);
+        }
+        if header.version != SHM_VERSION {
+            return Err(ShmError::BadVersion);
+        }
+        if header.capacity as usize != N {
+            return Err(ShmError::CapacityMismatch);
+        }
+        if header.elem_size as usize != size_of::<T>() {
+            return Err(ShmError::ElementSizeMismatch);
+        }
+        if !Index::<N>::is_valid_pair(header.wr_idx.get(), header.rd_idx.get()) {
+            return Err(ShmError::InvalidState);
+        }
+        Ok(ring)
+    }
+
+    #[inline(always)]
+    fn slot(&self, idx: &Index<N>) -> *mut T {
+        unsafe { self.buffer.add(idx.mask() as usize) }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.header.rd_idx == self.header.wr_idx
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.header.wr_idx.wrap_dist(&self.header.rd_idx)
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.len() as usize == N
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Copy val in and commit it
+    #[inline(always)]
+    pub fn push(&self, val: T) -> Result<(), ErrCode> {
+        if !self.is_full() {
+            unsafe { self.slot(&self.header.wr_idx).write_volatile(val) };
+            self.header.wr_idx.wrap_inc();
+            Ok(())
+        } else {
+            Err(ErrCode::BufFull)
+        }
+    }
+
+    /// Copy the item at the read index out and consume it
+    #[inline(always)]
+    pub fn pop_value(&self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let val = unsafe { self.slot(&self.header.rd_idx).read_volatile() };
+            self.header.rd_idx.wrap_inc();
+            Some(val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C, align(8))]
+    struct Region([u8; 64]);
+
+    #[test]
+    fn create_and_attach() {
+        type Ring<'a> = SharedMemRing<'a, u16, 5>;
+        const { assert!(Ring::STORAGE_OFFSET == 24 && Ring::SIZE == 34) };
+
+        let mut region = Region([0; 64]);
+        let ptr = region.0.as_mut_ptr();
+
+        // Nothing there yet
+        assert!(unsafe { Ring::attach(ptr) }.err() == Some(ShmError::BadMagic));
+        assert!(unsafe { Ring::from_raw(ptr.add(1)) }.err() == Some(ShmError::Misaligned));
+
+        let producer = unsafe { Ring::from_raw(ptr) }.unwrap();
+        let consumer = unsafe { Ring::attach(ptr) }.unwrap();
+        for i in 0..12 {
+            assert!(producer.push(i).is_ok());
+            assert!(consumer.pop_value() == Some(i));
+        }
+        assert!(producer.push(0x1234).is_ok());
+
+        // Layout as seen from C, indices wrap at 2*N
+        assert!(region.0[..4] == SHM_MAGIC.to_ne_bytes());
+        assert!(region.0[8..12] == 5u32.to_ne_bytes());
+        assert!(region.0[12..16] == 2u32.to_ne_bytes());
+        assert!(region.0[16..20] == 2u32.to_ne_bytes());
+        assert!(region.0[20..24] == 3u32.to_ne_bytes());
+        assert!(region.0[24 + 2 * 2..24 + 2 * 3] == 0x1234u16.to_ne_bytes());
+
+        assert!(unsafe { SharedMemRing::<u16, 4>::attach(ptr) }.err() == Some(ShmError::CapacityMismatch));
+        assert!(unsafe { SharedMemRing::<u32, 5>::attach(ptr) }.err() == Some(ShmError::ElementSizeMismatch));
+        // Write index beyond 2*N
+        unsafe { ptr.add(20).write(20) };
+        assert!(unsafe { Ring::attach(ptr) }.err() == Some(ShmError::InvalidState));
+    }
+}
@@ -0,0 +1,180 @@
+//! Statically declared ring with zero sized handles.
+//!
+//! `static_ringbuf!(NAME, T, N)` declares the marker type NAME owning a
+//! static `RingBufRef<T, N>`. Its handles are zero sized tokens reaching the
+//! ring through the marker type instead of a reference, with no split flags:
+//! each ring has its own handle types, so handles of different rings cannot
+//! be mixed up, and the handles are neither Clone nor Copy. Splitting is
+//! unsafe instead, the caller guarantees it happens once, e.g. in the init
+//! function before the handles are moved to their contexts.
+//!
+//! ```
+//! use spsc_ringbuf_core::static_ringbuf;
+//! use spsc_ringbuf_core::static_ring::StaticRing;
+//!
+//! static_ringbuf!(EVENTS, u32, 16);
+//!
+//! // Only split here
+//! let (mut producer, mut consumer) = unsafe { EVENTS::split() };
+//! assert!(producer.try_push(1).is_ok());
+//! assert!(consumer.pop_value() == Some(1));
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::ringbuf_ref::{ErrCode, RingBufRef};
+
+/// Ring owned by a marker type, implemented by `static_ringbuf!`
+///
+/// # Safety
+///
+/// `ring` must return the same ring on every call, and that ring must not
+/// be reachable otherwise, in particular through the `ring` of another
+/// type. The handles rely on it being theirs alone
+pub unsafe trait StaticRing<T: 'static, const N: usize>: Sized {
+    fn ring() -> &'static RingBufRef<T, N>;
+
+    /// Producer and consumer of the ring
+    ///
+    /// # Safety
+    ///
+    /// Must be called once only, further handles would alias the ones
+    /// handed out first
+    unsafe fn split() -> (TokenProducer<Self, T, N>, TokenConsumer<Self, T, N>) {
+        (
+            TokenProducer { _marker: PhantomData },
+            TokenConsumer { _marker: PhantomData },
+        )
+    }
+}
+
+/// Declare marker type NAME owning a static RingBufRef<T, N>, see
+/// `static_ring`
+#[macro_export]
+macro_rules! static_ringbuf {
+    ($vis:vis $name:ident, $t:ty, $n:expr) => {
+        #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+        $vis struct $name;
+
+        // Private static, only reachable through the marker type
+        unsafe impl $crate::static_ring::StaticRing<$t, { $n }> for $name {
+            #[inline(always)]
+            fn ring() -> &'static $crate::ringbuf_ref::RingBufRef<$t, { $n }> {
+                static RING: $crate::ringbuf_ref::RingBufRef<$t, { $n }> = $crate::ringbuf_ref::RingBufRef::new();
+                &RING
+            }
+        }
+    };
+}
+
+// Ties a handle to its ring, Send only through the impls below
+type Marker<R, T> = PhantomData<(fn() -> R, *const T)>;
+
+/// Zero sized producer handle of the ring owned by R
+pub struct TokenProducer<R: StaticRing<T, N>, T: 'static, const N: usize> {
+    _marker: Marker<R, T>,
+}
+
+/// Zero sized consumer handle of the ring owned by R
+pub struct TokenConsumer<R: StaticRing<T, N>, T: 'static, const N: usize> {
+    _marker: Marker<R, T>,
+}
+
+// Same as the handles of RingBuf, items are moved across through the ring
+unsafe impl<R: StaticRing<T, N>, T: Send, const N: usize> Send for TokenProducer<R, T, N> {}
+unsafe impl<R: StaticRing<T, N>, T: Send, const N: usize> Send for TokenConsumer<R, T, N> {}
+
+impl<R: StaticRing<T, N>, T, const N: usize> TokenProducer<R, T, N> {
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        R::ring().len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        R::ring().is_empty()
+    }
+
+    #[inline(always)]
+    pub fn free_len(&self) -> u32 {
+        R::ring().free_len()
+    }
+
+    /// Slot at the write index, published by `commit`
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[inline(always)]
+    pub unsafe fn writer_front(&mut self) -> Option<&mut T> {
+        R::ring().writer_front()
+    }
+
+    #[inline(always)]
+    pub fn commit(&mut self) -> Result<(), ErrCode> {
+        R::ring().commit()
+    }
+
+    #[inline(always)]
+    pub fn try_push(&mut self, val: T) -> Result<(), T> {
+        R::ring().try_push(val)
+    }
+}
+
+impl<R: StaticRing<T, N>, T, const N: usize> TokenConsumer<R, T, N> {
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        R::ring().len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        R::ring().is_empty()
+    }
+
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<&T> {
+        R::ring().reader_front()
+    }
+
+    #[inline(always)]
+    pub fn reader_front_mut(&mut self) -> Option<&mut T> {
+        R::ring().reader_front_mut()
+    }
+
+    #[inline(always)]
+    pub fn pop(&mut self) -> Result<(), ErrCode> {
+        R::ring().pop()
+    }
+
+    #[inline(always)]
+    pub fn pop_value(&mut self) -> Option<T> {
+        R::ring().pop_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::static_ringbuf!(QUEUE, u32, 4);
+
+    #[test]
+    fn zero_sized_handles() {
+        let (mut producer, mut consumer) = unsafe { QUEUE::split() };
+        assert!(core::mem::size_of_val(&producer) == 0);
+        assert!(core::mem::size_of_val(&consumer) == 0);
+
+        unsafe { core::ptr::write(producer.writer_front().unwrap(), 7) };
+        assert!(producer.commit().is_ok());
+        for i in 0..3 {
+            assert!(producer.try_push(i).is_ok());
+        }
+        assert!(producer.try_push(9) == Err(9));
+        assert!(consumer.reader_front() == Some(&7));
+        assert!(consumer.pop().is_ok());
+        assert!(consumer.pop_value() == Some(0));
+        assert!(consumer.len() == 2 && producer.free_len() == 2);
+    }
+}
@@ -0,0 +1,80 @@
+//! Ring statistics maintained by the `RingBuf` handles under the `stats`
+//! feature.
+//!
+//! Each counter has a single writer, the producer for the push counters and
+//! the high-water mark, the consumer for pops, so they are updated with a
+//! plain load and store which also works on cores without atomic
+//! read-modify-write. Counters wrap around. Items committed through a
+//! `RingBufRef` `WriteGrant` bypass the Producer and are not counted.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Copy of the counters, see `RingBuf::stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StatsSnapshot {
+    pub pushes: u32,
+    pub pops: u32,
+    /// Commits or pushes that found the ring full
+    pub failed_pushes: u32,
+    /// Highest fill level seen after a push
+    pub high_water: u32,
+}
+
+pub struct RingStats {
+    pushes: AtomicU32,
+    pops: AtomicU32,
+    failed_pushes: AtomicU32,
+    high_water: AtomicU32,
+}
+
+impl Default for RingStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RingStats {
+    pub const fn new() -> Self {
+        RingStats {
+            pushes: AtomicU32::new(0),
+            pops: AtomicU32::new(0),
+            failed_pushes: AtomicU32::new(0),
+            high_water: AtomicU32::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn add(counter: &AtomicU32, n: u32) {
+        counter.store(counter.load(Ordering::Relaxed).wrapping_add(n), Ordering::Relaxed);
+    }
+
+    // Producer side, len being the fill level after the push
+    #[inline(always)]
+    pub(crate) fn record_push(&self, n: u32, len: u32) {
+        Self::add(&self.pushes, n);
+        if len > self.high_water.load(Ordering::Relaxed) {
+            self.high_water.store(len, Ordering::Relaxed);
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn record_failed_push(&self) {
+        Self::add(&self.failed_pushes, 1);
+    }
+
+    // Consumer side
+    #[inline(always)]
+    pub(crate) fn record_pop(&self, n: u32) {
+        Self::add(&self.pops, n);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            pushes: self.pushes.load(Ordering::Relaxed),
+            pops: self.pops.load(Ordering::Relaxed),
+            failed_pushes: self.failed_pushes.load(Ordering::Relaxed),
+            high_water: self.high_water.load(Ordering::Relaxed),
+        }
+    }
+}
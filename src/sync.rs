@@ -0,0 +1,194 @@
+//! Atomics and cells used by `RingBufRef`, swapped for loom's under
+//! `--cfg loom` so the model tests in tests/loom.rs explore the index
+//! orderings and check the slot accesses for races. With the
+//! `portable-atomic` feature the atomics come from portable-atomic, which
+//! provides read-modify-write on targets without CAS such as thumbv6m.
+
+#[cfg(not(loom))]
+pub(crate) use core::cell::UnsafeCell;
+#[cfg(all(not(loom), not(feature = "portable-atomic")))]
+pub(crate) use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+#[cfg(all(not(loom), feature = "portable-atomic"))]
+pub(crate) use portable_atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+/// loom cell with the `get` of core's UnsafeCell. The access is checked
+/// when the pointer is taken, which the ring does right before using it
+#[cfg(loom)]
+pub(crate) struct UnsafeCell<T>(loom::cell::UnsafeCell<T>);
+
+#[cfg(loom)]
+impl<T> UnsafeCell<T> {
+    pub(crate) fn new(val: T) -> Self {
+        UnsafeCell(loom::cell::UnsafeCell::new(val))
+    }
+
+    pub(crate) fn get(&self) -> *mut T {
+        self.0.with_mut(|ptr| ptr)
+    }
+}
+
+/// Data memory barrier around index publication, on top of the release
+/// store and acquire load, with the `cortex-m` feature for multi-core parts
+/// such as the RP2040 or dual-core STM32. No-op otherwise
+#[inline(always)]
+pub(crate) fn index_barrier() {
+    #[cfg(all(feature = "cortex-m", target_arch = "arm"))]
+    cortex_m::asm::dmb();
+}
+
+/// Read-modify-write used by the queues beyond the SPSC rings. With the
+/// `critical-section` feature each is a load and a store inside
+/// `critical_section::with`, for targets without native atomic RMW
+/// (thumbv6m, MSP430, AVR, RISC-V without the A extension). The indices of
+/// `RingBufRef` only need load/store and are unaffected. portable-atomic
+/// takes precedence when both features are on
+#[cfg(not(loom))]
+pub(crate) trait AtomicRmw {
+    type Value: Copy;
+
+    fn rmw_swap(&self, val: Self::Value, order: Ordering) -> Self::Value;
+    fn rmw_fetch_add(&self, val: Self::Value, order: Ordering) -> Self::Value;
+    fn rmw_fetch_sub(&self, val: Self::Value, order: Ordering) -> Self::Value;
+    fn rmw_fetch_or(&self, val: Self::Value, order: Ordering) -> Self::Value;
+    fn rmw_compare_exchange(
+        &self,
+        current: Self::Value,
+        new: Self::Value,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Self::Value, Self::Value>;
+}
+
+// Load and store halves of an RMW ordering
+#[cfg(all(feature = "critical-section", not(feature = "portable-atomic"), not(loom)))]
+fn load_order(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Acquire | Ordering::AcqRel => Ordering::Acquire,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Relaxed,
+    }
+}
+
+#[cfg(all(feature = "critical-section", not(feature = "portable-atomic"), not(loom)))]
+fn store_order(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release | Ordering::AcqRel => Ordering::Release,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Relaxed,
+    }
+}
+
+// Load then store of f(old) inside a critical section, returning old
+#[cfg(all(feature = "critical-section", not(feature = "portable-atomic"), not(loom)))]
+macro_rules! cs_update {
+    ($cell:expr, $order:expr, $f:expr) => {
+        critical_section::with(|_| {
+            let old = $cell.load(load_order($order));
+            $cell.store($f(old), store_order($order));
+            old
+        })
+    };
+}
+
+#[cfg(all(feature = "critical-section", not(feature = "portable-atomic"), not(loom)))]
+macro_rules! impl_atomic_rmw {
+    ($atomic:ty, $int:ty) => {
+        impl AtomicRmw for $atomic {
+            type Value = $int;
+
+            #[inline]
+            fn rmw_swap(&self, val: $int, order: Ordering) -> $int {
+                cs_update!(self, order, |_| val)
+            }
+            #[inline]
+            fn rmw_fetch_add(&self, val: $int, order: Ordering) -> $int {
+                cs_update!(self, order, |old: $int| old.wrapping_add(val))
+            }
+            #[inline]
+            fn rmw_fetch_sub(&self, val: $int, order: Ordering) -> $int {
+                cs_update!(self, order, |old: $int| old.wrapping_sub(val))
+            }
+            #[inline]
+            fn rmw_fetch_or(&self, val: $int, order: Ordering) -> $int {
+                cs_update!(self, order, |old| old | val)
+            }
+            #[inline]
+            fn rmw_compare_exchange(&self, current: $int, new: $int, success: Ordering, failure: Ordering) -> Result<$int, $int> {
+                critical_section::with(|_| {
+                    let old = self.load(load_order(success));
+                    if old == current {
+                        self.store(new, store_order(success));
+                        Ok(old)
+                    } else {
+                        Err(self.load(failure))
+                    }
+                })
+            }
+        }
+    };
+}
+
+#[cfg(all(any(not(feature = "critical-section"), feature = "portable-atomic"), not(loom)))]
+macro_rules! impl_atomic_rmw {
+    ($atomic:ty, $int:ty) => {
+        impl AtomicRmw for $atomic {
+            type Value = $int;
+
+            #[inline(always)]
+            fn rmw_swap(&self, val: $int, order: Ordering) -> $int {
+                self.swap(val, order)
+            }
+            #[inline(always)]
+            fn rmw_fetch_add(&self, val: $int, order: Ordering) -> $int {
+                self.fetch_add(val, order)
+            }
+            #[inline(always)]
+            fn rmw_fetch_sub(&self, val: $int, order: Ordering) -> $int {
+                self.fetch_sub(val, order)
+            }
+            #[inline(always)]
+            fn rmw_fetch_or(&self, val: $int, order: Ordering) -> $int {
+                self.fetch_or(val, order)
+            }
+            #[inline(always)]
+            fn rmw_compare_exchange(&self, current: $int, new: $int, success: Ordering, failure: Ordering) -> Result<$int, $int> {
+                self.compare_exchange(current, new, success, failure)
+            }
+        }
+    };
+}
+
+#[cfg(not(loom))]
+impl_atomic_rmw!(AtomicU8, u8);
+#[cfg(not(loom))]
+impl_atomic_rmw!(AtomicU16, u16);
+#[cfg(not(loom))]
+impl_atomic_rmw!(AtomicU32, u32);
+
+/// Serializes the producers of a ring logged or traced into from several
+/// contexts without the `critical-section` feature. A context finding it
+/// taken gives up instead of waiting, which could deadlock an ISR
+/// preempting the holder
+#[cfg(all(not(loom), not(feature = "critical-section")))]
+pub(crate) struct TryLock(AtomicU8);
+
+#[cfg(all(not(loom), not(feature = "critical-section")))]
+impl TryLock {
+    pub(crate) const fn new() -> Self {
+        TryLock(AtomicU8::new(0))
+    }
+
+    /// Run f holding the lock, None if it is taken
+    #[inline]
+    pub(crate) fn try_with<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        if self.0.rmw_swap(1, Ordering::Acquire) != 0 {
+            return None;
+        }
+        let res = f();
+        self.0.store(0, Ordering::Release);
+        Some(res)
+    }
+}
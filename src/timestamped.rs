@@ -0,0 +1,142 @@
+//! Ring buffer stamping each item with the commit time.
+//!
+//! The producer side reads the clock when an item is committed and stores
+//! the tick next to it. The consumer sees the stamp on peek, so the latency
+//! of an item or the age of the oldest queued one can be measured without
+//! carrying a time field in T. The clock is read from both sides and must be
+//! usable from both contexts, e.g. a free running cycle counter.
+
+use crate::clock::Clock;
+use crate::ringbuf_ref::{ErrCode, RingBufRef};
+
+/// RingBufRef of (commit tick, item)
+pub struct TimestampedRingBufRef<T, const N: usize, C: Clock> {
+    ring: RingBufRef<(u32, T), N>,
+    clock: C,
+}
+
+impl<T, const N: usize, C: Clock> TimestampedRingBufRef<T, N, C> {
+
+    pub const fn new(clock: C) -> Self {
+        TimestampedRingBufRef {
+            ring: RingBufRef::new(),
+            clock,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Slot at the write index, published by `commit`
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        self.ring.alloc_uninit().map(|slot| &mut *core::ptr::addr_of_mut!((*slot.as_mut_ptr()).1))
+    }
+
+    /// Stamp the item at the write index with the current tick and commit it
+    #[inline(always)]
+    pub fn commit(&self) -> Result<(), ErrCode> {
+        let slot = self.ring.alloc_uninit().ok_or(ErrCode::BufFull)?;
+        // Only the stamp is written, the item is left as is
+        unsafe { core::ptr::addr_of_mut!((*slot.as_mut_ptr()).0).write(self.clock.now()) };
+        self.ring.commit()
+    }
+
+    /// Write and commit in one step
+    #[inline(always)]
+    pub fn push(&self, val: T) -> Result<(), ErrCode> {
+        self.ring.push((self.clock.now(), val))
+    }
+
+    /// Oldest item with its commit tick
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<(u32, &T)> {
+        self.ring.reader_front().map(|(t, val)| (*t, val))
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn reader_front_mut(&self) -> Option<&mut T> {
+        self.ring.reader_front_mut().map(|(_, val)| val)
+    }
+
+    /// Ticks elapsed since the oldest item was committed
+    #[inline(always)]
+    pub fn age(&self) -> Option<u32> {
+        let (t, _) = self.reader_front()?;
+        Some(self.clock.now().wrapping_sub(t))
+    }
+
+    #[inline(always)]
+    pub fn pop(&self) -> Result<(), ErrCode> {
+        self.ring.pop()
+    }
+
+    /// Move the oldest item out with its commit tick
+    #[inline(always)]
+    pub fn pop_value(&self) -> Option<(u32, T)> {
+        self.ring.pop_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct TestClock(Cell<u32>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn stamps_at_commit() {
+        let ring: TimestampedRingBufRef<u32, 2, TestClock> = TimestampedRingBufRef::new(TestClock(Cell::new(u32::MAX - 1)));
+        let clock = ring.clock();
+
+        unsafe { core::ptr::write(ring.writer_front().unwrap(), 10) };
+        clock.0.set(u32::MAX);
+        assert!(ring.commit().is_ok());
+        clock.0.set(3);
+        assert!(ring.push(11).is_ok());
+        assert!(ring.push(12) == Err(ErrCode::BufFull));
+        assert!(ring.commit() == Err(ErrCode::BufFull));
+
+        // Age across the tick wrap
+        clock.0.set(5);
+        assert!(ring.age() == Some(6));
+        assert!(ring.reader_front() == Some((u32::MAX, &10)));
+        assert!(ring.pop().is_ok());
+        assert!(ring.age() == Some(2));
+        assert!(ring.pop_value() == Some((3, 11)));
+        assert!(ring.age().is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Trace event ring with deferred formatting.
+//!
+//! An event is an id, up to 4 argument words and the tick of a
+//! `clock::Clock`, pushed into a ring as a fixed size record without any
+//! formatting on the target. The records are encoded to bytes for the
+//! transport, and decoded and rendered on the host with a table mapping the
+//! ids to format strings.
+//!
+//! The ring has a single producer. With the `critical-section` feature each
+//! record is pushed inside `critical_section::with`. Without it, an event
+//! traced while another context is pushing one is dropped and counted.
+
+use core::fmt;
+
+use crate::clock::Clock;
+use crate::ringbuf_ref::RingBufRef;
+#[cfg(not(feature = "critical-section"))]
+use crate::sync::TryLock;
+use crate::sync::{AtomicRmw, AtomicU32, Ordering};
+
+pub const MAX_ARGS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TraceEvent {
+    pub id: u16,
+    pub timestamp: u32,
+    nargs: u8,
+    args: [u32; MAX_ARGS],
+}
+
+impl TraceEvent {
+
+    /// Encoded size: id, arg count, timestamp and the argument words, little
+    /// endian
+    pub const SIZE: usize = 2 + 1 + 4 + 4 * MAX_ARGS;
+
+    /// Event with the first MAX_ARGS words of args
+    pub fn new(id: u16, timestamp: u32, args: &[u32]) -> Self {
+        let nargs = args.len().min(MAX_ARGS);
+        let mut words = [0; MAX_ARGS];
+        words[..nargs].copy_from_slice(&args[..nargs]);
+        TraceEvent { id, timestamp, nargs: nargs as u8, args: words }
+    }
+
+    pub fn args(&self) -> &[u32] {
+        &self.args[..self.nargs as usize]
+    }
+
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        out[0..2].copy_from_slice(&self.id.to_le_bytes());
+        out[2] = self.nargs;
+        out[3..7].copy_from_slice(&self.timestamp.to_le_bytes());
+        for (i, word) in self.args.iter().enumerate() {
+            out[7 + 4 * i..11 + 4 * i].copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// None if bytes is shorter than SIZE or the arg count is invalid
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        let nargs = bytes[2];
+        if nargs as usize > MAX_ARGS {
+            return None;
+        }
+        let word = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        Some(TraceEvent {
+            id: u16::from_le_bytes([bytes[0], bytes[1]]),
+            timestamp: word(3),
+            nargs,
+            args: core::array::from_fn(|i| word(7 + 4 * i)),
+        })
+    }
+
+    /// Decode a stream of encoded events, stopping at the first invalid one
+    pub fn decode_all(bytes: &[u8]) -> impl Iterator<Item = TraceEvent> + '_ {
+        bytes.chunks_exact(Self::SIZE).map_while(Self::decode)
+    }
+
+    /// Render with the format string of the id, replacing each `{}` with the
+    /// next argument in decimal and each `{:x}` in hex. Missing arguments
+    /// render as `?`
+    pub fn render<W: fmt::Write>(&self, format: &str, w: &mut W) -> fmt::Result {
+        let mut args = self.args().iter();
+        let mut rest = format;
+        while let Some(start) = rest.find('{') {
+            w.write_str(&rest[..start])?;
+            let (hex, len) = if rest[start..].starts_with("{}") {
+                (false, 2)
+            } else if rest[start..].starts_with("{:x}") {
+                (true, 4)
+            } else {
+                w.write_str("{")?;
+                rest = &rest[start + 1..];
+                continue;
+            };
+            match args.next() {
+                Some(arg) if hex => write!(w, "{:x}", arg)?,
+                Some(arg) => write!(w, "{}", arg)?,
+                None => w.write_str("?")?,
+            }
+            rest = &rest[start + len..];
+        }
+        w.write_str(rest)
+    }
+}
+
+pub struct TraceRing<const N: usize, C: Clock> {
+    ring: RingBufRef<TraceEvent, N>,
+    clock: C,
+    // Events that did not fit or were contended
+    dropped: AtomicU32,
+    #[cfg(not(feature = "critical-section"))]
+    producer: TryLock,
+}
+
+impl<const N: usize, C: Clock> TraceRing<N, C> {
+
+    pub const fn new(clock: C) -> Self {
+        TraceRing {
+            ring: RingBufRef::new(),
+            clock,
+            dropped: AtomicU32::new(0),
+            #[cfg(not(feature = "critical-section"))]
+            producer: TryLock::new(),
+        }
+    }
+
+    /// Record event id with up to MAX_ARGS words, further words are ignored.
+    /// The event is dropped and counted when the ring is full, or without
+    /// the `critical-section` feature when another context is tracing
+    #[inline]
+    pub fn trace(&self, id: u16, args: &[u32]) {
+        #[cfg(feature = "critical-section")]
+        critical_section::with(|_| self.push(id, args));
+        #[cfg(not(feature = "critical-section"))]
+        if self.producer.try_with(|| self.push(id, args)).is_none() {
+            self.count_drop();
+        }
+    }
+
+    fn count_drop(&self) {
+        self.dropped.rmw_fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Single producer, see trace
+    #[inline(always)]
+    fn push(&self, id: u16, args: &[u32]) {
+        if self.ring.push(TraceEvent::new(id, self.clock.now(), args)).is_err() {
+            self.count_drop();
+        }
+    }
+
+    /// Events dropped for lack of room so far
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Oldest event, consumer side
+    pub fn pop(&self) -> Option<TraceEvent> {
+        self.ring.pop_value()
+    }
+
+    /// Encode as many whole events as fit into dst and consume them,
+    /// consumer side. Returns the number of bytes written
+    pub fn read_encoded(&self, dst: &mut [u8]) -> usize {
+        let mut n = 0;
+        for chunk in dst.chunks_exact_mut(TraceEvent::SIZE) {
+            match self.pop() {
+                Some(event) => chunk.copy_from_slice(&event.encode()),
+                None => break,
+            }
+            n += TraceEvent::SIZE;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct TestClock(Cell<u32>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> u32 {
+            self.0.set(self.0.get() + 10);
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn trace_decode_render() {
+        let trace: TraceRing<2, TestClock> = TraceRing::new(TestClock(Cell::new(0)));
+        trace.trace(1, &[42, 0xbeef]);
+        trace.trace(2, &[1, 2, 3, 4, 5]);
+        trace.trace(3, &[]);
+        assert!(trace.dropped() == 1);
+
+        let mut bytes = [0; 3 * TraceEvent::SIZE];
+        assert!(trace.read_encoded(&mut bytes) == 2 * TraceEvent::SIZE);
+
+        let events: Vec<_> = TraceEvent::decode_all(&bytes[..2 * TraceEvent::SIZE]).collect();
+        assert!(events.len() == 2);
+        assert!(events[0].id == 1 && events[0].timestamp == 10);
+        assert!(events[1].args() == [1, 2, 3, 4]);
+
+        let mut line = String::new();
+        assert!(events[0].render("rx {} bytes, crc {:x} {}", &mut line).is_ok());
+        assert!(line == "rx 42 bytes, crc beef ?");
+    }
+}
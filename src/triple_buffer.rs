@@ -0,0 +1,166 @@
+//! Triple buffer for tear-free sharing of the latest value in place.
+//!
+//! Unlike `Mailbox`, the three buffers are always initialized and accessed
+//! by reference. The writer fills its back buffer and publishes it by
+//! swapping it with the middle one, the reader swaps its front buffer with
+//! the middle one when a newer buffer was published. The reader keeps
+//! seeing the same front buffer until then, so control loops can read the
+//! newest value every cycle without copying it.
+//!
+//! The swaps use atomic read-modify-write, not available on targets without
+//! CAS such as thumbv6m unless the `critical-section` feature is on.
+
+use core::cell::{Cell, UnsafeCell};
+
+use crate::sync::{AtomicRmw, AtomicU8, Ordering};
+
+// Set in middle when its buffer was published after the reader's last swap
+const NEW: u8 = 0b100;
+const SLOT_MASK: u8 = 0b011;
+
+pub struct TripleBuffer<T> {
+    bufs: UnsafeCell<[T; 3]>,
+    // Buffer in the middle and the NEW flag
+    middle: AtomicU8,
+    has_split: Cell<bool>,
+}
+
+// Buffers are handed between the writer and the reader context
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+/// Writer handle, once in life time
+pub struct TripleWriter<'a, T> {
+    inner: &'a TripleBuffer<T>,
+    back: u8,
+}
+
+/// Reader handle, once in life time
+pub struct TripleReader<'a, T> {
+    inner: &'a TripleBuffer<T>,
+    front: u8,
+}
+
+impl<T> TripleBuffer<T> {
+
+    /// Reader starts at bufs[2], the back buffer is bufs[1]
+    pub const fn new(bufs: [T; 3]) -> Self {
+        TripleBuffer {
+            bufs: UnsafeCell::new(bufs),
+            middle: AtomicU8::new(0),
+            has_split: Cell::new(false),
+        }
+    }
+
+    /// Return the writer and reader, once in life time
+    pub fn split(&self) -> Option<(TripleWriter<'_, T>, TripleReader<'_, T>)> {
+        if self.has_split.get() {
+            None
+        } else {
+            self.has_split.set(true);
+            Some((TripleWriter { inner: self, back: 1 }, TripleReader { inner: self, front: 2 }))
+        }
+    }
+
+    /// A buffer was published and not picked up by the reader yet
+    #[inline]
+    pub fn has_new(&self) -> bool {
+        self.middle.load(Ordering::Acquire) & NEW != 0
+    }
+
+    #[inline(always)]
+    fn buf(&self, idx: u8) -> *mut T {
+        // Only ever a pointer to one element, the sides own distinct ones
+        unsafe { (self.bufs.get() as *mut T).add(idx as usize) }
+    }
+}
+
+impl<'a, T> TripleWriter<'a, T> {
+
+    /// Back buffer, holding whatever it was last swapped out with
+    #[inline]
+    pub fn back_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.inner.buf(self.back) }
+    }
+
+    /// Make the back buffer the latest one
+    #[inline]
+    pub fn publish(&mut self) {
+        let old = self.inner.middle.rmw_swap(self.back | NEW, Ordering::AcqRel);
+        self.back = old & SLOT_MASK;
+    }
+
+    /// Write val to the back buffer and publish it
+    #[inline]
+    pub fn write(&mut self, val: T) {
+        *self.back_mut() = val;
+        self.publish();
+    }
+}
+
+impl<'a, T> TripleReader<'a, T> {
+
+    /// Latest published buffer, the same one as before if nothing new
+    #[inline]
+    pub fn read(&mut self) -> &T {
+        let tbuf = self.inner;
+        if tbuf.has_new() {
+            // Only the writer sets NEW, still set at the swap
+            let old = tbuf.middle.rmw_swap(self.front, Ordering::AcqRel);
+            self.front = old & SLOT_MASK;
+        }
+        unsafe { &*tbuf.buf(self.front) }
+    }
+
+    #[inline]
+    pub fn has_new(&self) -> bool {
+        self.inner.has_new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_buffer() {
+        let tbuf = TripleBuffer::new([0u32; 3]);
+        let (mut writer, mut reader) = tbuf.split().unwrap();
+        assert!(tbuf.split().is_none());
+        assert!(*reader.read() == 0);
+
+        *writer.back_mut() = 1;
+        // Not visible before publish
+        assert!(*reader.read() == 0);
+        writer.publish();
+        assert!(*reader.read() == 1);
+        assert!(!reader.has_new());
+        assert!(*reader.read() == 1);
+
+        for i in 2..10 {
+            writer.write(i);
+        }
+        assert!(*reader.read() == 9);
+    }
+
+    #[test]
+    fn threads() {
+        static TBUF: TripleBuffer<[u32; 8]> = TripleBuffer::new([[0; 8]; 3]);
+        let (mut writer, mut reader) = TBUF.split().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            for i in 1..=10000 {
+                writer.back_mut().fill(i);
+                writer.publish();
+            }
+        });
+        let mut last = 0;
+        while last != 10000 {
+            let val = reader.read();
+            // Never torn, never older than the last one read
+            assert!(val.iter().all(|v| *v == val[0]));
+            assert!(val[0] >= last);
+            last = val[0];
+        }
+        handle.join().unwrap();
+    }
+}
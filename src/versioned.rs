@@ -0,0 +1,189 @@
+//! Ring buffer handing out versioned handles to committed items.
+//!
+//! A `Handle` records the buffer location and the commit sequence number of
+//! an item. `get` only resolves the handle while the item is still queued, so
+//! a stale handle is detected once the location has been popped and recycled
+//! instead of silently aliasing a newer item. Sequence numbers are u32, a
+//! handle can only be mistaken after 2^32 further commits.
+
+use crate::ringbuf_ref::{ErrCode, RingBufRef};
+use crate::sync::{AtomicU32, Ordering};
+
+/// Location + generation of a committed item
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Handle {
+    slot: u32,
+    seq: u32,
+}
+
+impl Handle {
+    /// Buffer location of the item
+    pub fn slot(&self) -> usize {
+        self.slot as usize
+    }
+
+    /// Commit sequence number of the item
+    pub fn generation(&self) -> u32 {
+        self.seq
+    }
+}
+
+/// RingBufRef with commit and pop counters for versioned handles
+pub struct VersionedRingBufRef<T, const N: usize> {
+    ring: RingBufRef<T, N>,
+    // Total number of commits, only written by the producer
+    committed: AtomicU32,
+    // Total number of pops, only written by the consumer
+    popped: AtomicU32,
+}
+
+// Sync through the inner ring and the atomic counters
+unsafe impl<T: Send, const N: usize> Sync for VersionedRingBufRef<T, N> {}
+
+impl<T, const N: usize> Default for VersionedRingBufRef<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> VersionedRingBufRef<T, N> {
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT_0: VersionedRingBufRef<T, N> = Self::new();
+
+    pub const fn new() -> Self {
+        VersionedRingBufRef {
+            ring: RingBufRef::new(),
+            committed: AtomicU32::new(0),
+            popped: AtomicU32::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.ring.len()
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Slot at the write index, published by `commit`
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        self.ring.writer_front()
+    }
+
+    /// Commit the write index location and return its handle
+    #[inline(always)]
+    pub fn commit(&self) -> Result<Handle, ErrCode> {
+        let handle = self.next_handle();
+        self.ring.commit()?;
+        self.committed.store(handle.seq.wrapping_add(1), Ordering::Release);
+        Ok(handle)
+    }
+
+    /// Write and commit in one step, returning the handle of the item
+    #[inline(always)]
+    pub fn push(&self, val: T) -> Result<Handle, ErrCode> {
+        let handle = self.next_handle();
+        self.ring.push(val)?;
+        self.committed.store(handle.seq.wrapping_add(1), Ordering::Release);
+        Ok(handle)
+    }
+
+    // Handle of the next item to be committed
+    #[inline(always)]
+    fn next_handle(&self) -> Handle {
+        Handle {
+            slot: self.ring.wr_slot() as u32,
+            seq: self.committed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the item of the handle if it has not been popped yet.
+    /// Consumer side, the item is looked up by its position relative to the
+    /// read index so that a foreign handle cannot reach outside the queue
+    #[inline(always)]
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        let popped = self.popped.load(Ordering::Acquire);
+        // Queued items are the sequence numbers in [popped, committed)
+        let pos = handle.seq.wrapping_sub(popped);
+        if pos < self.committed.load(Ordering::Acquire).wrapping_sub(popped) {
+            self.ring.peek_at(pos)
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<&T> {
+        self.ring.reader_front()
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn reader_front_mut(&self) -> Option<&mut T> {
+        self.ring.reader_front_mut()
+    }
+
+    /// Consume the item at the read index, invalidating its handle
+    #[inline(always)]
+    pub fn pop(&self) -> Result<(), ErrCode> {
+        self.ring.pop()?;
+        let popped = self.popped.load(Ordering::Relaxed);
+        self.popped.store(popped.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_handles() {
+        let ring: VersionedRingBufRef<u32, 2> = VersionedRingBufRef::new();
+
+        let h0 = ring.push(10).unwrap();
+        let h1 = ring.push(11).unwrap();
+        assert!(ring.push(12).is_err());
+
+        assert!(*ring.get(h0).unwrap() == 10);
+        assert!(*ring.get(h1).unwrap() == 11);
+
+        assert!(ring.pop().is_ok());
+        assert!(ring.get(h0).is_none());
+
+        // Same location recycled for a new item
+        let h2 = ring.push(12).unwrap();
+        assert!(h2.slot() == h0.slot());
+        assert!(h2.generation() != h0.generation());
+        assert!(ring.get(h0).is_none());
+        assert!(*ring.get(h2).unwrap() == 12);
+        assert!(*ring.get(h1).unwrap() == 11);
+
+        // Handle of a larger ring stays within this one
+        let big: VersionedRingBufRef<u32, 8> = VersionedRingBufRef::new();
+        for i in 0..6 {
+            assert!(big.push(i).is_ok());
+        }
+        assert!(ring.get(big.push(6).unwrap()).is_none());
+        assert!(ring.get(Handle { slot: 7, seq: h2.generation() }) == Some(&12));
+    }
+}
@@ -0,0 +1,185 @@
+//! Ring buffer over caller-provided element storage.
+//!
+//! `RingBufView` keeps the indices itself and borrows the element array,
+//! so the storage can be placed in a dedicated linker section, DMA capable
+//! RAM or battery-backed SRAM. Same API and index handling as `RingBufRef`.
+
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+use crate::ringbuf_ref::{ErrCode, Index, IndexWidth};
+
+pub struct RingBufView<'a, T, const N: usize, I: IndexWidth = u32> {
+    // this is from where we dequeue items
+    rd_idx: Index<N, I>,
+    //  where we enqueue new items
+    wr_idx: Index<N, I>,
+    // Start of the borrowed storage
+    buffer: *mut MaybeUninit<T>,
+    _storage: PhantomData<&'a mut [MaybeUninit<T>; N]>,
+}
+
+// Delcare this is thread safe due to the SPSC premise, same as RingBufRef.
+unsafe impl<'a, T: Send, const N: usize, I: IndexWidth> Sync for RingBufView<'a, T, N, I> {}
+unsafe impl<'a, T: Send, const N: usize, I: IndexWidth> Send for RingBufView<'a, T, N, I> {}
+
+impl<'a, T, const N: usize, I: IndexWidth> RingBufView<'a, T, N, I> {
+
+    const OK: () = assert!(N > 0, "Ringbuf capacity must be larger than 0!");
+
+    /// Empty ring using storage for the items. Whatever storage holds is
+    /// treated as uninitialized
+    #[allow(clippy::let_unit_value)]
+    pub fn new_in(storage: &'a mut [MaybeUninit<T>; N]) -> Self {
+        let _: () = RingBufView::<T, N, I>::OK;
+        RingBufView {
+            rd_idx: Index::new(),
+            wr_idx: Index::new(),
+            buffer: storage.as_mut_ptr(),
+            _storage: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, idx: &Index<N, I>) -> *mut T {
+        unsafe { self.buffer.add(idx.mask() as usize) as *mut T }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.rd_idx == self.wr_idx
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.wr_idx.wrap_dist(&self.rd_idx)
+    }
+
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.len() as usize == N
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the write index location as mutable reference, see
+    /// `RingBufRef::writer_front`
+    ///
+    /// # Safety
+    /// See `RingBufRef::writer_front`
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub unsafe fn writer_front(&self) -> Option<&mut T> {
+        if !self.is_full() {
+            Some(&mut *self.slot(&self.wr_idx))
+        } else {
+            None
+        }
+    }
+
+    /// Commit whatever at the write index location by moving the write index
+    #[inline(always)]
+    pub fn commit(&self) -> Result<(), ErrCode> {
+        if !self.is_full() {
+            self.wr_idx.wrap_inc();
+            Ok(())
+        } else {
+            Err(ErrCode::BufFull)
+        }
+    }
+
+    /// Alloc and commit in one step
+    #[inline(always)]
+    pub fn push(&self, val: T) -> Result<(), ErrCode> {
+        self.try_push(val).map_err(|_| ErrCode::BufFull)
+    }
+
+    /// Same as `push`, handing val back when full
+    #[inline(always)]
+    pub fn try_push(&self, val: T) -> Result<(), T> {
+        if !self.is_full() {
+            unsafe { self.slot(&self.wr_idx).write(val) };
+            self.wr_idx.wrap_inc();
+            Ok(())
+        } else {
+            Err(val)
+        }
+    }
+
+    /// Returns an Option of reference to location at read index
+    #[inline(always)]
+    pub fn reader_front(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(unsafe { &*self.slot(&self.rd_idx) })
+        }
+    }
+
+    /// Returns an Option of mutable reference to location at read index
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    pub fn reader_front_mut(&self) -> Option<&mut T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(unsafe { &mut *self.slot(&self.rd_idx) })
+        }
+    }
+
+    /// Consume the item at rd_idx
+    #[inline(always)]
+    pub fn pop(&self) -> Result<(), ErrCode> {
+        if !self.is_empty() {
+            self.rd_idx.wrap_inc();
+            Ok(())
+        } else {
+            Err(ErrCode::BufEmpty)
+        }
+    }
+
+    /// Move the item at rd_idx out and consume it
+    #[inline(always)]
+    pub fn pop_value(&self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let val = unsafe { self.slot(&self.rd_idx).read() };
+            self.rd_idx.wrap_inc();
+            Some(val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_storage() {
+        let mut storage = [MaybeUninit::<u32>::uninit(); 3];
+        {
+            let view: RingBufView<u32, 3, u8> = RingBufView::new_in(&mut storage);
+            for i in 0..10 {
+                assert!(view.push(i).is_ok());
+                unsafe { core::ptr::write(view.writer_front().unwrap(), i + 100) };
+                assert!(view.commit().is_ok());
+                assert!(view.pop_value() == Some(i));
+                assert!(*view.reader_front().unwrap() == i + 100);
+                assert!(view.pop().is_ok());
+            }
+            assert!(view.is_empty());
+            for i in 0..3 {
+                assert!(view.push(i).is_ok());
+            }
+            assert!(view.push(3).is_err());
+        }
+        // Items live in the provided storage
+        let mut items: Vec<u32> = storage.iter().map(|v| unsafe { v.assume_init() }).collect();
+        items.sort();
+        assert!(items == [0, 1, 2]);
+    }
+}
@@ -0,0 +1,78 @@
+//! Wait strategies for the blocking ring buffer operations.
+//!
+//! A strategy is called each time a blocking push finds the ring full or a
+//! blocking pop finds it empty, and reset once the operation went through.
+//! Any `FnMut()` is a strategy, e.g. `std::thread::yield_now` or
+//! `cortex_m::asm::wfe`. Parking with `std::thread::park_timeout` works as
+//! well, plain `park` would need the other side to unpark.
+
+/// Called while waiting for the other side
+pub trait WaitStrategy {
+    fn wait(&mut self);
+
+    /// Called after the operation completed
+    fn reset(&mut self) {}
+}
+
+impl<F: FnMut()> WaitStrategy for F {
+    fn wait(&mut self) {
+        self()
+    }
+}
+
+/// Busy wait with a spin loop hint
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    fn wait(&mut self) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Spin up to limit times per operation, then defer to inner, e.g. a yield
+/// or WFE
+pub struct SpinThen<W> {
+    spins: u32,
+    limit: u32,
+    inner: W,
+}
+
+impl<W: WaitStrategy> SpinThen<W> {
+    pub const fn new(limit: u32, inner: W) -> Self {
+        SpinThen { spins: 0, limit, inner }
+    }
+}
+
+impl<W: WaitStrategy> WaitStrategy for SpinThen<W> {
+    fn wait(&mut self) {
+        if self.spins < self.limit {
+            self.spins += 1;
+            core::hint::spin_loop();
+        } else {
+            self.inner.wait();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.spins = 0;
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spin_then_defer() {
+        let deferred = core::cell::Cell::new(0);
+        let mut wait = SpinThen::new(2, || deferred.set(deferred.get() + 1));
+        for _ in 0..5 {
+            wait.wait();
+        }
+        wait.reset();
+        wait.wait();
+        assert!(deferred.get() == 3);
+    }
+}
@@ -0,0 +1,39 @@
+// #[derive(HasPoolIdx)], run with `cargo test --features derive`
+#![cfg(all(not(loom), feature = "derive"))]
+use spsc_ringbuf_core::shared_pool::*;
+
+const POOL_DEPTH: usize = 4;
+
+#[derive(Default, HasPoolIdx)]
+pub struct Message {
+    id: u32,
+    payload: PoolIndex<POOL_DEPTH>,
+}
+
+// Second index of another pool, skipped by the derive
+#[derive(Default, HasPoolIdx)]
+pub struct Reply<T: Copy + Default>(T, #[pool_idx] PoolIndex<8>, #[allow(dead_code)] PoolIndex<8>);
+
+#[test]
+fn derived_pool_index() {
+    let pool: SharedPool<u32, Message, POOL_DEPTH, 4> = SharedPool::new();
+    let (mut producer, mut consumer) = pool.split().unwrap();
+
+    let (message, payload) = producer.stage_with_payload().unwrap();
+    message.id = 1;
+    *payload.try_write().unwrap() = 42;
+    payload.write_done().unwrap();
+    producer.commit().unwrap();
+    assert!(consumer.recv(|m, p| (m.id, p.copied())) == Ok((1, Some(42))));
+
+    // Marked field of a tuple struct
+    let pool: SharedPool<u32, Reply<u8>, 8, 8> = SharedPool::new();
+    let (mut producer, mut consumer) = pool.split().unwrap();
+    let (reply, payload) = producer.stage_with_payload().unwrap();
+    reply.0 = 2;
+    assert!(reply.1.is_valid());
+    *payload.try_write().unwrap() = 7;
+    payload.write_done().unwrap();
+    producer.commit().unwrap();
+    assert!(consumer.recv(|r, p| (r.0, p.copied())) == Ok((2, Some(7))));
+}
@@ -1,5 +1,8 @@
+// Needs the full crate, not built for the loom model tests
+#![cfg(not(loom))]
 use spsc_ringbuf_core::shared_pool::*;
 const POOL_DEPTH: usize = 16;
+#[derive(Default)]
 pub struct Message {
     id: u32,
     payload: PoolIndex<POOL_DEPTH>,
@@ -0,0 +1,85 @@
+//! loom model tests of the RingBufRef index protocol. Run with
+//! `RUSTFLAGS="--cfg loom" cargo test --release --test loom`
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use spsc_ringbuf_core::ringbuf_ref::RingBufRef;
+
+// Every interleaving of a producer pushing ITEMS through a ring of N
+// slots and a consumer popping them must deliver them in order, with
+// loom checking each slot access against the index updates
+fn push_pop<const N: usize>(items: u32) {
+    loom::model(move || {
+        let ring = Arc::new(RingBufRef::<u32, N>::new());
+
+        let producer = {
+            let ring = ring.clone();
+            thread::spawn(move || {
+                for i in 0..items {
+                    while ring.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        for i in 0..items {
+            loop {
+                if let Some(val) = ring.pop_value() {
+                    assert_eq!(val, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        producer.join().unwrap();
+        assert!(ring.is_empty());
+    });
+}
+
+#[test]
+fn push_pop_wrap() {
+    // Slot reuse after wrap around, power of two
+    push_pop::<2>(3);
+}
+
+#[test]
+fn push_pop_non_power_of_two() {
+    push_pop::<3>(4);
+}
+
+#[test]
+fn writer_front_commit() {
+    loom::model(|| {
+        let ring = Arc::new(RingBufRef::<u32, 1>::new());
+
+        let producer = {
+            let ring = ring.clone();
+            thread::spawn(move || {
+                for i in 0..2 {
+                    loop {
+                        if let Some(slot) = ring.alloc_uninit() {
+                            slot.write(i);
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                    ring.commit().unwrap();
+                }
+            })
+        };
+
+        for i in 0..2 {
+            loop {
+                if let Some(val) = ring.reader_front() {
+                    assert_eq!(*val, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+            ring.pop().unwrap();
+        }
+        producer.join().unwrap();
+    });
+}